@@ -0,0 +1,283 @@
+//! One-shot local HTTP listener for the OAuth callback.
+//!
+//! Instead of making the user paste the URL they were redirected to, we listen
+//! on the host and port of the redirect URI (by default
+//! `http://127.0.0.1:8888/callback`) and capture the authorization code from
+//! the first request that hits its path.
+
+use std::{
+  io,
+  net::{IpAddr, Ipv4Addr, SocketAddr},
+  time::Duration,
+};
+use thiserror::Error;
+use tokio::{
+  io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+  net::{TcpListener, TcpStream},
+};
+use url::{Host, Url};
+
+/// How long to wait for the user to authorize in the browser.
+pub const TIMEOUT: Duration = Duration::from_secs(180);
+
+/// How long a connection has to send its request line.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+const SUCCESS_PAGE: &str = "<!doctype html><html><head><title>orpheus</title></head>\
+  <body style=\"font-family: sans-serif; text-align: center; margin-top: 20vh\">\
+  <h1>Orpheus is authorized</h1><p>You can close this tab and go back to your terminal.</p>\
+  </body></html>";
+
+#[derive(Debug, Error)]
+pub enum CallbackError {
+  #[error("The redirect URI {0} is not a http loopback address with a port")]
+  UnsupportedRedirect(String),
+  #[error("Failed to listen on {0}: {1}")]
+  Bind(SocketAddr, io::Error),
+  #[error("Timed out waiting for the authorization")]
+  Timeout,
+  #[error("Spotify denied the authorization: {0}")]
+  Denied(String),
+  #[error("IO error: {0}")]
+  Io(#[from] io::Error),
+}
+
+/// Waits for Spotify to redirect the browser to `redirect_uri`, returning the
+/// authorization code once the `state` parameter is validated.
+/// Requests with another `state` are turned down, and the listener keeps waiting.
+///
+/// Gives up after [`TIMEOUT`] so the caller can fall back to a manual paste.
+pub async fn listen(redirect_uri: &str, state: &str) -> Result<String, CallbackError> {
+  let redirect = Url::parse(redirect_uri)
+    .map_err(|_| CallbackError::UnsupportedRedirect(redirect_uri.into()))?;
+  let address = socket_address(&redirect)
+    .ok_or_else(|| CallbackError::UnsupportedRedirect(redirect_uri.into()))?;
+
+  let listener = TcpListener::bind(address)
+    .await
+    .map_err(|err| CallbackError::Bind(address, err))?;
+  tracing::info!("listening for the OAuth callback on {address}");
+
+  tokio::time::timeout(TIMEOUT, accept(&listener, redirect.path(), state))
+    .await
+    .map_err(|_| CallbackError::Timeout)?
+}
+
+async fn accept(listener: &TcpListener, path: &str, state: &str) -> Result<String, CallbackError> {
+  loop {
+    let (mut stream, _) = listener.accept().await?;
+
+    // browsers may open connections ahead and never use them, which mustn't
+    // keep us from reading the actual redirect
+    let url = match tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await {
+      Ok(Ok(Some(url))) => url,
+      Ok(Ok(None)) | Err(_) => continue,
+      Ok(Err(err)) => {
+        tracing::warn!("failed to read a request to the OAuth callback: {err}");
+        continue;
+      }
+    };
+
+    // browsers also ask for things like `/favicon.ico`
+    if url.path() != path {
+      respond(&mut stream, "404 Not Found", "Not found").await;
+      continue;
+    }
+
+    let param = |name: &str| {
+      url
+        .query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+    };
+
+    // not the redirect we're waiting for, which may still come
+    if param("state").as_deref() != Some(state) {
+      respond(&mut stream, "400 Bad Request", "Invalid state parameter").await;
+      continue;
+    }
+
+    if let Some(error) = param("error") {
+      respond(
+        &mut stream,
+        "400 Bad Request",
+        &format!("Authorization failed: {error}"),
+      )
+      .await;
+      return Err(CallbackError::Denied(error));
+    }
+
+    match param("code") {
+      Some(code) => {
+        respond(&mut stream, "200 OK", SUCCESS_PAGE).await;
+        return Ok(code);
+      }
+      None => respond(&mut stream, "400 Bad Request", "Missing code parameter").await,
+    }
+  }
+}
+
+/// Reads the request line, returning the requested URL.
+async fn read_request(stream: &mut TcpStream) -> io::Result<Option<Url>> {
+  let mut request_line = String::new();
+  BufReader::new(stream).read_line(&mut request_line).await?;
+
+  let target = request_line.split_whitespace().nth(1);
+  Ok(target.and_then(|target| Url::parse(&format!("http://localhost{target}")).ok()))
+}
+
+/// Answers the request, the browser being gone doesn't change the outcome.
+async fn respond(stream: &mut TcpStream, status: &str, body: &str) {
+  let response = format!(
+    "HTTP/1.1 {status}\r\ncontent-type: text/html; charset=utf-8\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+    body.len()
+  );
+
+  let result = match stream.write_all(response.as_bytes()).await {
+    Ok(()) => stream.shutdown().await,
+    Err(err) => Err(err),
+  };
+  if let Err(err) = result {
+    tracing::warn!("failed to answer a request to the OAuth callback: {err}");
+  }
+}
+
+/// The address to listen on, only if the redirect URI is a loopback one.
+fn socket_address(redirect: &Url) -> Option<SocketAddr> {
+  if redirect.scheme() != "http" {
+    return None;
+  }
+
+  let ip = match redirect.host()? {
+    Host::Ipv4(ip) => IpAddr::V4(ip),
+    Host::Ipv6(ip) => IpAddr::V6(ip),
+    Host::Domain("localhost") => IpAddr::V4(Ipv4Addr::LOCALHOST),
+    Host::Domain(_) => return None,
+  };
+
+  match ip.is_loopback() {
+    true => Some(SocketAddr::new(ip, redirect.port()?)),
+    false => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tokio::{io::AsyncReadExt, task::JoinHandle};
+
+  const STATE: &str = "state";
+
+  /// Accepts the redirects to `/callback` on a port of its own.
+  async fn listener() -> (SocketAddr, JoinHandle<Result<String, CallbackError>>) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let accepted = tokio::spawn(async move { accept(&listener, "/callback", STATE).await });
+    (address, accepted)
+  }
+
+  /// Requests `target` like a browser, returning the status line of the response.
+  async fn get(address: SocketAddr, target: &str) -> String {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let request = format!("GET {target} HTTP/1.1\r\nhost: {address}\r\n\r\n");
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response.lines().next().unwrap_or_default().to_string()
+  }
+
+  #[tokio::test]
+  async fn the_code_of_the_redirect_is_captured() {
+    let (address, accepted) = listener().await;
+
+    let ignored = [
+      ("/favicon.ico", "HTTP/1.1 404 Not Found"),
+      (
+        "/callback?code=stolen&state=other",
+        "HTTP/1.1 400 Bad Request",
+      ),
+      ("/callback?code=stolen", "HTTP/1.1 400 Bad Request"),
+      ("/callback?state=state", "HTTP/1.1 400 Bad Request"),
+    ];
+    for (target, status) in ignored {
+      assert_eq!(get(address, target).await, status, "{target}");
+      assert!(!accepted.is_finished());
+    }
+
+    let status = get(address, "/callback?code=code&state=state").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(accepted.await.unwrap().unwrap(), "code");
+  }
+
+  #[tokio::test]
+  async fn denials_end_the_wait() {
+    let (address, accepted) = listener().await;
+
+    let status = get(address, "/callback?error=access_denied&state=state").await;
+
+    assert_eq!(status, "HTTP/1.1 400 Bad Request");
+    assert!(matches!(
+      accepted.await.unwrap(),
+      Err(CallbackError::Denied(error)) if error == "access_denied"
+    ));
+  }
+
+  #[tokio::test]
+  async fn idle_and_stray_connections_are_given_up() {
+    let (address, accepted) = listener().await;
+
+    // closed without a request, then opened ahead and never used
+    drop(TcpStream::connect(address).await.unwrap());
+    let _idle = TcpStream::connect(address).await.unwrap();
+    let mut stray = TcpStream::connect(address).await.unwrap();
+    stray.write_all(b"\r\n").await.unwrap();
+
+    // answered once the idle one times out
+    let status = get(address, "/callback?code=code&state=state").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(accepted.await.unwrap().unwrap(), "code");
+  }
+
+  #[tokio::test]
+  async fn only_loopback_redirects_are_listened_on() {
+    for redirect_uri in [
+      "https://127.0.0.1:8888/callback",
+      "http://example.com:8888/callback",
+      "http://192.168.1.2:8888/callback",
+      "http://127.0.0.1/callback",
+      "not a url",
+    ] {
+      assert!(
+        matches!(
+          listen(redirect_uri, STATE).await,
+          Err(CallbackError::UnsupportedRedirect(_))
+        ),
+        "{redirect_uri}"
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn the_port_of_the_redirect_uri_is_listened_on() {
+    // a port free to listen on
+    let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+      .await
+      .unwrap()
+      .local_addr()
+      .unwrap()
+      .port();
+    let redirect_uri = format!("http://127.0.0.1:{port}/callback");
+    let listened = tokio::spawn(async move { listen(&redirect_uri, STATE).await });
+
+    let address = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port);
+    // until it's bound, the probe being closed right away
+    while TcpStream::connect(address).await.is_err() {
+      tokio::task::yield_now().await;
+    }
+    let status = get(address, "/callback?code=code&state=state").await;
+
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(listened.await.unwrap().unwrap(), "code");
+  }
+}
@@ -0,0 +1,357 @@
+//! Storage of the Spotify app credentials.
+//!
+//! Credentials live in a versioned `credentials.toml` in the
+//! [profile directory](super::profile::Profile::dir). The PKCE flow only needs the client
+//! id and the redirect URI, so no client secret is stored. Files in the old
+//! line-based `credentials.txt` format are migrated on load.
+//!
+//! The `ORPHEUS_CLIENT_ID` and `ORPHEUS_REDIRECT_URI` environment variables
+//! take precedence over the stored values.
+
+use crate::auth::{self, AuthError, profile::Profile, vault::Vault};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use url::{Host, Url};
+
+/// Version of the credentials file written by this build.
+const VERSION: u32 = 1;
+
+pub const DEFAULT_REDIRECT_URI: &str = "http://127.0.0.1:8888/callback";
+
+const CLIENT_ID_VAR: &str = "ORPHEUS_CLIENT_ID";
+const REDIRECT_URI_VAR: &str = "ORPHEUS_REDIRECT_URI";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct StoredCredentials {
+  #[serde(default = "current_version")]
+  version: u32,
+  pub client_id: String,
+  #[serde(default = "default_redirect_uri")]
+  pub redirect_uri: String,
+}
+
+impl StoredCredentials {
+  pub fn new(client_id: String, redirect_uri: String) -> Self {
+    Self {
+      version: VERSION,
+      client_id,
+      redirect_uri,
+    }
+  }
+
+  pub fn validate(&self) -> Result<(), AuthError> {
+    validate_client_id(&self.client_id)?;
+    validate_redirect_uri(&self.redirect_uri)
+  }
+}
+
+/// Loads the stored credentials, with the environment overrides applied.
+///
+/// Returns `None` if there's neither a credentials file nor a client id
+/// in the environment, meaning the user has to be prompted for them.
+pub fn load(
+  profile: &Profile,
+  vault: Option<&Vault>,
+) -> Result<Option<StoredCredentials>, AuthError> {
+  let stored = match read(&profile.credentials_path()?, vault)? {
+    Some(creds) => Some(creds),
+    None => migrate(profile, vault)?,
+  };
+
+  let client_id = std::env::var(CLIENT_ID_VAR).ok();
+  let redirect_uri = std::env::var(REDIRECT_URI_VAR).ok();
+
+  let mut creds = match (stored, client_id) {
+    (Some(creds), None) => creds,
+    (Some(creds), Some(client_id)) => StoredCredentials { client_id, ..creds },
+    (None, Some(client_id)) => StoredCredentials::new(client_id, default_redirect_uri()),
+    (None, None) => return Ok(None),
+  };
+
+  if let Some(redirect_uri) = redirect_uri {
+    creds.redirect_uri = redirect_uri;
+  }
+
+  creds.validate()?;
+  Ok(Some(creds))
+}
+
+pub fn save(
+  profile: &Profile,
+  creds: &StoredCredentials,
+  vault: Option<&Vault>,
+) -> Result<(), AuthError> {
+  let content = toml::to_string(creds).map_err(|e| AuthError::Credentials(e.to_string()))?;
+  auth::write_secret(&profile.credentials_path()?, &content, vault)
+}
+
+fn legacy_path(profile: &Profile) -> Result<PathBuf, AuthError> {
+  let mut path = profile.dir()?;
+  path.push("credentials.txt");
+  Ok(path)
+}
+
+/// Whether there are credentials to [load](self::load): stored, in the
+/// legacy format or in the environment.
+pub fn exist(profile: &Profile) -> Result<bool, AuthError> {
+  Ok(
+    profile.credentials_path()?.exists()
+      || legacy_path(profile)?.exists()
+      || std::env::var(CLIENT_ID_VAR).is_ok_and(|id| !id.is_empty()),
+  )
+}
+
+/// Removes the old `credentials.txt`, so it's not migrated over new credentials.
+pub fn remove_legacy(profile: &Profile) -> Result<(), AuthError> {
+  let legacy = legacy_path(profile)?;
+  if legacy.exists() {
+    std::fs::remove_file(legacy)?;
+  }
+  Ok(())
+}
+
+fn read(path: &Path, vault: Option<&Vault>) -> Result<Option<StoredCredentials>, AuthError> {
+  let Some(content) = auth::read_secret(path, vault)? else {
+    return Ok(None);
+  };
+
+  let creds: StoredCredentials =
+    toml::from_str(&content).map_err(|e| AuthError::Credentials(e.to_string()))?;
+
+  match creds.version > VERSION {
+    true => Err(AuthError::Credentials(format!(
+      "version {} was written by a newer orpheus",
+      creds.version
+    ))),
+    false => Ok(Some(creds)),
+  }
+}
+
+/// Converts the old `credentials.txt`, made of the client id, the client
+/// secret and the redirect URI on their own lines.
+fn migrate(
+  profile: &Profile,
+  vault: Option<&Vault>,
+) -> Result<Option<StoredCredentials>, AuthError> {
+  let legacy = legacy_path(profile)?;
+  if !legacy.exists() {
+    return Ok(None);
+  }
+
+  let content = std::fs::read_to_string(&legacy)?;
+  let lines: Vec<&str> = content.lines().map(str::trim).collect();
+
+  let Some(client_id) = lines.first().filter(|id| !id.is_empty()) else {
+    return Ok(None);
+  };
+  let redirect_uri = lines
+    .get(2)
+    .filter(|uri| !uri.is_empty())
+    .map_or_else(default_redirect_uri, |uri| uri.to_string());
+
+  // checked before anything is rewritten, so a bad file can be fixed in place
+  let creds = StoredCredentials::new(client_id.to_string(), redirect_uri);
+  creds.validate()?;
+  save(profile, &creds, vault)?;
+  std::fs::remove_file(&legacy)?;
+
+  let path = profile.credentials_path()?;
+  println!("✓ Migrated {} to {}", legacy.display(), path.display());
+  Ok(Some(creds))
+}
+
+/// Spotify client ids are 32 hexadecimal characters.
+pub fn validate_client_id(client_id: &str) -> Result<(), AuthError> {
+  match client_id.len() == 32 && client_id.chars().all(|c| c.is_ascii_hexdigit()) {
+    true => Ok(()),
+    false => Err(AuthError::InvalidCredentials(
+      "the Client ID must be 32 hexadecimal characters".to_string(),
+    )),
+  }
+}
+
+/// Spotify only accepts `https` redirect URIs, or `http` on a loopback address.
+pub fn validate_redirect_uri(redirect_uri: &str) -> Result<(), AuthError> {
+  let invalid = |reason: &str| AuthError::InvalidCredentials(format!("the Redirect URI {reason}"));
+  let url = Url::parse(redirect_uri).map_err(|_| invalid("is not a valid URL"))?;
+
+  match (url.scheme(), url.host()) {
+    ("https", Some(_)) => Ok(()),
+    ("http", Some(Host::Ipv4(ip))) if ip.is_loopback() => Ok(()),
+    ("http", Some(Host::Ipv6(ip))) if ip.is_loopback() => Ok(()),
+    ("http", _) => Err(invalid(
+      "must use a loopback address like 127.0.0.1 when using http",
+    )),
+    _ => Err(invalid("must use http or https")),
+  }
+}
+
+fn current_version() -> u32 {
+  VERSION
+}
+
+fn default_redirect_uri() -> String {
+  DEFAULT_REDIRECT_URI.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::Mutex;
+  use tempfile::TempDir;
+
+  const CLIENT_ID: &str = "0123456789abcdef0123456789abcdef";
+
+  /// Held by the tests reading the environment, which is shared by all of them.
+  static ENV: Mutex<()> = Mutex::new(());
+
+  fn profile() -> (TempDir, Profile) {
+    let dir = TempDir::new().unwrap();
+    let profile = Profile::rooted(dir.path());
+    std::fs::create_dir_all(profile.dir().unwrap()).unwrap();
+    (dir, profile)
+  }
+
+  fn write_legacy(profile: &Profile, content: &str) {
+    std::fs::write(legacy_path(profile).unwrap(), content).unwrap();
+  }
+
+  /// Sets the environment overrides, or removes them if `None`.
+  fn set_env(client_id: Option<&str>, redirect_uri: Option<&str>) {
+    for (var, value) in [(CLIENT_ID_VAR, client_id), (REDIRECT_URI_VAR, redirect_uri)] {
+      // SAFETY: the tests touching the environment hold `ENV`
+      unsafe {
+        match value {
+          Some(value) => std::env::set_var(var, value),
+          None => std::env::remove_var(var),
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn legacy_credentials_are_migrated() {
+    let _env = ENV.lock().unwrap_or_else(|err| err.into_inner());
+    set_env(None, None);
+    let (_dir, profile) = profile();
+    write_legacy(
+      &profile,
+      &format!("{CLIENT_ID}\nsecret\nhttps://example.com/callback\n"),
+    );
+
+    let creds = load(&profile, None).unwrap().unwrap();
+
+    assert_eq!(creds.client_id, CLIENT_ID);
+    assert_eq!(creds.redirect_uri, "https://example.com/callback");
+    assert!(!legacy_path(&profile).unwrap().exists());
+    let stored = read(&profile.credentials_path().unwrap(), None).unwrap();
+    assert_eq!(stored.unwrap().client_id, CLIENT_ID);
+  }
+
+  #[test]
+  fn legacy_credentials_without_redirect_uri_get_the_default_one() {
+    let _env = ENV.lock().unwrap_or_else(|err| err.into_inner());
+    set_env(None, None);
+    let (_dir, profile) = profile();
+    write_legacy(&profile, CLIENT_ID);
+
+    let creds = load(&profile, None).unwrap().unwrap();
+    assert_eq!(creds.redirect_uri, DEFAULT_REDIRECT_URI);
+  }
+
+  #[test]
+  fn invalid_legacy_credentials_are_left_untouched() {
+    let _env = ENV.lock().unwrap_or_else(|err| err.into_inner());
+    set_env(None, None);
+    let (_dir, profile) = profile();
+    write_legacy(&profile, "not-a-client-id\nsecret\n");
+
+    assert!(matches!(
+      load(&profile, None),
+      Err(AuthError::InvalidCredentials(_))
+    ));
+    assert!(legacy_path(&profile).unwrap().exists());
+    assert!(!profile.credentials_path().unwrap().exists());
+  }
+
+  #[test]
+  fn the_environment_overrides_the_stored_credentials() {
+    let _env = ENV.lock().unwrap_or_else(|err| err.into_inner());
+    let (_dir, profile) = profile();
+    let overridden = "fedcba9876543210fedcba9876543210";
+
+    set_env(Some(overridden), Some("http://[::1]:9999/callback"));
+    let creds = load(&profile, None).unwrap().unwrap();
+    assert_eq!(creds.client_id, overridden);
+    assert_eq!(creds.redirect_uri, "http://[::1]:9999/callback");
+
+    let stored = StoredCredentials::new(CLIENT_ID.to_string(), DEFAULT_REDIRECT_URI.to_string());
+    save(&profile, &stored, None).unwrap();
+    set_env(None, Some("https://example.com/callback"));
+    let creds = load(&profile, None).unwrap().unwrap();
+    assert_eq!(creds.client_id, CLIENT_ID);
+    assert_eq!(creds.redirect_uri, "https://example.com/callback");
+
+    // checked like the stored ones
+    set_env(None, Some("http://example.com/callback"));
+    assert!(load(&profile, None).is_err());
+
+    set_env(None, None);
+    let creds = load(&profile, None).unwrap().unwrap();
+    assert_eq!(creds.redirect_uri, DEFAULT_REDIRECT_URI);
+  }
+
+  #[test]
+  fn nothing_is_loaded_without_credentials() {
+    let _env = ENV.lock().unwrap_or_else(|err| err.into_inner());
+    set_env(None, None);
+    let (_dir, profile) = profile();
+
+    assert!(load(&profile, None).unwrap().is_none());
+    assert!(!exist(&profile).unwrap());
+  }
+
+  #[test]
+  fn credentials_of_newer_versions_are_refused() {
+    let (_dir, profile) = profile();
+    let path = profile.credentials_path().unwrap();
+
+    std::fs::write(&path, format!("client_id = \"{CLIENT_ID}\"")).unwrap();
+    let creds = read(&path, None).unwrap().unwrap();
+    assert_eq!(creds.version, VERSION);
+
+    let newer = format!("version = {}\nclient_id = \"{CLIENT_ID}\"", VERSION + 1);
+    std::fs::write(&path, newer).unwrap();
+    assert!(matches!(read(&path, None), Err(AuthError::Credentials(_))));
+  }
+
+  #[test]
+  fn client_ids_are_32_hexadecimal_characters() {
+    assert!(validate_client_id(CLIENT_ID).is_ok());
+    assert!(validate_client_id(&CLIENT_ID.to_uppercase()).is_ok());
+
+    for invalid in ["", &CLIENT_ID[1..], "0123456789abcdef0123456789abcdeg"] {
+      assert!(validate_client_id(invalid).is_err(), "{invalid}");
+    }
+  }
+
+  #[test]
+  fn redirect_uris_are_https_or_loopback_http() {
+    for valid in [
+      "https://example.com/callback",
+      "http://127.0.0.1:8888/callback",
+      "http://[::1]:8888/callback",
+    ] {
+      assert!(validate_redirect_uri(valid).is_ok(), "{valid}");
+    }
+
+    for invalid in [
+      "http://localhost:8888/callback",
+      "http://example.com/callback",
+      "ftp://127.0.0.1/callback",
+      "not a url",
+    ] {
+      assert!(validate_redirect_uri(invalid).is_err(), "{invalid}");
+    }
+  }
+}
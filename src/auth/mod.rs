@@ -0,0 +1,821 @@
+//! Spotify authentication module for Orpheus.
+//!
+//! This module handles one-time authentication with the Spotify Web API.
+//! On first run, it prompts the user for their Spotify app credentials,
+//! then stores them locally. Tokens are cached and automatically refreshed,
+//! so users don't need to re-authenticate.
+//!
+//! Both the credentials and the token cache can be encrypted at rest
+//! by the [vault](self::vault).
+
+#![allow(unused)]
+
+pub(crate) mod callback;
+pub(crate) mod credentials;
+pub(crate) mod profile;
+pub(crate) mod session;
+pub(crate) mod vault;
+
+use crate::{
+  auth::{
+    credentials::{DEFAULT_REDIRECT_URI, StoredCredentials},
+    profile::Profile,
+    vault::{Vault, VaultError},
+  },
+  config::AuthConfig,
+};
+use clap::ValueEnum;
+use directories::ProjectDirs;
+use rspotify::{
+  AuthCodePkceSpotify, CallbackError, ClientError, Config, Credentials, OAuth, Token,
+  TokenCallback,
+  http::HttpError,
+  prelude::{BaseClient, OAuthClient},
+  scopes,
+};
+use serde::Deserialize;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Environment variable with the new passphrase when [rotating](self::rotate_passphrase) it.
+const NEW_PASSPHRASE_VAR: &str = "ORPHEUS_NEW_PASSPHRASE";
+const MAX_PASSPHRASE_ATTEMPTS: usize = 3;
+
+const SCOPES: [&str; 14] = [
+  "playlist-read-collaborative",
+  "playlist-read-private",
+  "playlist-modify-private",
+  "playlist-modify-public",
+  "user-follow-read",
+  "user-follow-modify",
+  "user-library-modify",
+  "user-library-read",
+  "user-modify-playback-state",
+  "user-read-currently-playing",
+  "user-read-playback-state",
+  "user-read-playback-position",
+  "user-read-private",
+  "user-read-recently-played",
+];
+
+/// How the user authorizes orpheus with Spotify.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMode {
+  /// Open the browser and capture the redirect with a local listener.
+  #[default]
+  Browser,
+  /// Print the authorization URL and ask for the redirect URL or code,
+  /// for machines without a browser, e.g. over SSH.
+  Headless,
+}
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+  #[error("Failed to find or create config directory")]
+  ConfigDir,
+  #[error("Failed to read user input: {0}")]
+  InputError(String),
+  #[error("The OAuth flow was not properly configured: {0}")]
+  OAuthConfig(String),
+  #[error("Failed to authenticate with Spotify: {0}")]
+  Authentication(String),
+  #[error("Could not reach Spotify: {0}")]
+  Offline(String),
+  #[error("Invalid credentials file: {0}")]
+  Credentials(String),
+  #[error("Invalid credentials: {0}")]
+  InvalidCredentials(String),
+  #[error("Invalid profile: {0}")]
+  Profile(String),
+  #[error("Could not decrypt the stored files: {0}")]
+  Vault(#[from] VaultError),
+  #[error("IO error: {0}")]
+  Io(#[from] io::Error),
+}
+
+/// Creates and authenticates a Spotify client.
+///
+/// This function handles the complete authentication flow:
+///
+/// 1. **First run**: Prompts for Spotify app credentials, then opens a browser
+///    for OAuth authorization. Both credentials and tokens are cached.
+/// 2. **Subsequent runs**: Loads cached credentials and tokens. If the access
+///    token is expired, it's automatically refreshed using the refresh token.
+///
+/// The authorization itself depends on the [mode](self::AuthMode) in `options`.
+/// If the stored files are encrypted, or `options` asks for them to be, the
+/// passphrase is taken from the environment or prompted for.
+///
+/// # Example
+///
+/// ```no_run
+/// use orpheus::{auth::{authenticate, profile::Profile}, config::AuthConfig};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let spotify = authenticate(&Profile::default(), &AuthConfig::default())
+///         .await
+///         .expect("Failed to authenticate");
+///     // Now you can use `spotify` to make API calls
+/// }
+/// ```
+pub async fn authenticate(
+  profile: &Profile,
+  options: &AuthConfig,
+) -> Result<AuthCodePkceSpotify, AuthError> {
+  let vault = unlock(profile, options)?;
+  let stored = get_or_prompt_credentials(profile, vault.as_ref())?;
+  let cache_path = profile.token_cache_path()?;
+
+  if let Some(parent) = cache_path.parent() {
+    std::fs::create_dir_all(parent).map_err(|_| AuthError::ConfigDir)?;
+  }
+
+  if let Some(vault) = &vault {
+    seal_plaintext(profile, vault)?;
+  }
+
+  let mut spotify = client(profile, stored, vault.clone())?;
+  let cached_token = read_token(profile, vault.as_ref())?;
+
+  match cached_token {
+    Some(token) => {
+      *spotify.token.lock().await.unwrap() = Some(token);
+    }
+    _ => {
+      let auth_url = spotify
+        .get_authorize_url(None)
+        .map_err(|e| AuthError::Authentication(e.to_string()))?;
+
+      let code = match options.mode {
+        AuthMode::Browser => authorize(&spotify, &auth_url).await?,
+        AuthMode::Headless => authorize_headless(&spotify, &auth_url, options.qr)?,
+      };
+      spotify
+        .request_token(&code)
+        .await
+        .map_err(|e| match is_unreachable(&e) {
+          true => AuthError::Offline(e.to_string()),
+          false => AuthError::Authentication(e.to_string()),
+        })?;
+
+      println!("Authentication successful!");
+      println!("Token cached at: {}", cache_path.display());
+    }
+  }
+
+  // try to refresh the token to ensure it's valid
+  // this also saves the refreshed token to the cache
+  if let Err(e) = spotify.refresh_token().await {
+    let err_msg = e.to_string();
+    if is_unreachable(&e) {
+      eprintln!("Could not reach Spotify, starting offline");
+    } else if !err_msg.contains("refresh") {
+      eprintln!("Warning: Could not refresh token: {}", err_msg);
+    }
+  }
+
+  Ok(spotify)
+}
+
+/// Whether `err` is a failure to reach Spotify at all, rather than a response.
+fn is_unreachable(err: &ClientError) -> bool {
+  matches!(err, ClientError::Http(err) if matches!(**err, HttpError::Client(_)))
+}
+
+/// Creates a client for `profile` from what's stored, without any interaction,
+/// so it can be done while the TUI is running.
+///
+/// Encrypted profiles need the passphrase in the environment. If there's no
+/// cached token, the client has none and the user is asked to authorize again
+/// on its first request.
+pub async fn restore(profile: &Profile) -> Result<AuthCodePkceSpotify, AuthError> {
+  let vault = match sealed_files(profile)?.first() {
+    Some(sealed) => {
+      let vault = Vault::from_env().ok_or(AuthError::Profile(format!(
+        "profile '{}' is encrypted, set {} to switch to it",
+        profile.name(),
+        vault::PASSPHRASE_VAR
+      )))?;
+      vault.open(&std::fs::read_to_string(sealed)?)?;
+      Some(vault)
+    }
+    None => None,
+  };
+
+  restore_with(profile, vault).await
+}
+
+/// Like [restore](self::restore), but asks for the passphrase if it's needed.
+pub async fn open(profile: &Profile) -> Result<AuthCodePkceSpotify, AuthError> {
+  let vault = match sealed_files(profile)?.first() {
+    Some(sealed) => Some(unlock_file(sealed)?),
+    None => None,
+  };
+
+  restore_with(profile, vault).await
+}
+
+async fn restore_with(
+  profile: &Profile,
+  vault: Option<Vault>,
+) -> Result<AuthCodePkceSpotify, AuthError> {
+  let stored = credentials::load(profile, vault.as_ref())?.ok_or(AuthError::Profile(format!(
+    "profile '{}' has no credentials",
+    profile.name()
+  )))?;
+
+  let spotify = client(profile, stored, vault.clone())?;
+  *spotify.token.lock().await.unwrap() = read_token(profile, vault.as_ref())?;
+
+  Ok(spotify)
+}
+
+/// Creates a client for `profile` with the given credentials, with no token yet.
+pub(crate) fn client(
+  profile: &Profile,
+  stored: StoredCredentials,
+  vault: Option<Vault>,
+) -> Result<AuthCodePkceSpotify, AuthError> {
+  let creds = Credentials::new_pkce(&stored.client_id);
+
+  let oauth = OAuth {
+    redirect_uri: stored.redirect_uri,
+    scopes: scopes!(
+      "playlist-read-collaborative",
+      "playlist-read-private",
+      "playlist-modify-private",
+      "playlist-modify-public",
+      "user-follow-read",
+      "user-follow-modify",
+      "user-library-modify",
+      "user-library-read",
+      "user-modify-playback-state",
+      "user-read-currently-playing",
+      "user-read-playback-state",
+      "user-read-playback-position",
+      "user-read-private",
+      "user-read-recently-played"
+    ),
+    ..Default::default()
+  };
+
+  // the token is cached by the callback instead of rspotify,
+  // so that it can go through the vault
+  let cache_path = profile.token_cache_path()?;
+  let token_path = cache_path.clone();
+  let callback = TokenCallback(Box::new(move |token| {
+    write_token(&token_path, &token, vault.as_ref())
+      .map_err(|e| CallbackError::CustomizedError(e.to_string()))
+  }));
+
+  let config = Config {
+    cache_path,
+    token_cached: false,
+    token_refreshing: true, // auto-refresh expired tokens
+    token_callback_fn: Arc::new(Some(callback)),
+    ..Default::default()
+  };
+
+  Ok(AuthCodePkceSpotify::with_config(creds, oauth, config))
+}
+
+/// Opens the authorization URL in the browser and returns the authorization code.
+///
+/// The code is captured by the [callback listener](self::callback), falling back
+/// to asking for the redirected URL if it can't bind or times out.
+async fn authorize(spotify: &AuthCodePkceSpotify, url: &str) -> Result<String, AuthError> {
+  println!("Opening browser for Spotify authorization...");
+  if webbrowser::open(url).is_err() {
+    println!("Could not open the browser.");
+  }
+  println!("If the browser didn't open, visit this URL:");
+  println!();
+  println!("  {url}");
+  println!();
+
+  let oauth = &spotify.oauth;
+  match callback::listen(&oauth.redirect_uri, &oauth.state).await {
+    Ok(code) => return Ok(code),
+    Err(e) => eprintln!("Could not capture the authorization automatically: {e}"),
+  }
+
+  let redirected = prompt("Enter the URL you were redirected to: ")?;
+  parse_code(spotify, &redirected)
+    .ok_or_else(|| AuthError::Authentication("Invalid redirect URL".to_string()))
+}
+
+/// Asks for the authorization without a browser or a listener on this machine,
+/// as when orpheus runs on a remote host over SSH.
+fn authorize_headless(
+  spotify: &AuthCodePkceSpotify,
+  url: &str,
+  qr: bool,
+) -> Result<String, AuthError> {
+  println!("Headless authorization");
+  println!();
+  println!("  1. Open this URL in a browser on any device:");
+  println!();
+  println!("  {url}");
+  println!();
+
+  if qr {
+    match qr_code(url) {
+      Some(code) => println!("{code}"),
+      None => println!("  (the URL is too long to fit in a QR code)"),
+    }
+  }
+
+  println!("  2. Log in and accept. Spotify then redirects to");
+  println!("     {}", spotify.oauth.redirect_uri);
+  println!("     which will fail to load on that device, and that's fine.");
+  println!("  3. Copy the full URL from the address bar, or just the value");
+  println!("     of its `code` parameter, and paste it below.");
+  println!();
+
+  let input = prompt("Redirect URL or code: ")?;
+  parse_code(spotify, &input).ok_or_else(|| {
+    AuthError::InputError("Could not find an authorization code in the input".to_string())
+  })
+}
+
+/// Extracts the authorization code from either the redirect URL, validating
+/// its state, or the bare code itself.
+pub fn parse_code(spotify: &AuthCodePkceSpotify, input: &str) -> Option<String> {
+  let input = input.trim();
+
+  if input.contains('?') {
+    return spotify.parse_response_code(input);
+  }
+
+  let is_code = !input.is_empty()
+    && input
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+  is_code.then(|| input.to_string())
+}
+
+pub(crate) fn qr_code(data: &str) -> Option<String> {
+  use qrcode::{QrCode, render::unicode::Dense1x2};
+
+  let code = QrCode::new(data).ok()?;
+  Some(
+    code
+      .render::<Dense1x2>()
+      .dark_color(Dense1x2::Light)
+      .light_color(Dense1x2::Dark)
+      .quiet_zone(true)
+      .build(),
+  )
+}
+
+/// Gets the vault when the stored files are encrypted or `options` asks them to be.
+fn unlock(profile: &Profile, options: &AuthConfig) -> Result<Option<Vault>, AuthError> {
+  let sealed = sealed_files(profile)?;
+  if sealed.is_empty() && !options.encrypt {
+    return Ok(None);
+  }
+
+  let Some(sealed) = sealed.first() else {
+    return new_vault().map(Some);
+  };
+
+  unlock_file(sealed).map(Some)
+}
+
+/// Asks for the passphrase until it opens the file at `sealed`.
+fn unlock_file(sealed: &Path) -> Result<Vault, AuthError> {
+  let content = std::fs::read_to_string(sealed)?;
+
+  if let Some(vault) = Vault::from_env() {
+    vault.open(&content)?;
+    return Ok(vault);
+  }
+
+  for attempt in 1..=MAX_PASSPHRASE_ATTEMPTS {
+    let vault = Vault::new(prompt_hidden("Passphrase: ")?);
+    match vault.open(&content) {
+      Ok(_) => return Ok(vault),
+      Err(VaultError::Decrypt) if attempt < MAX_PASSPHRASE_ATTEMPTS => {
+        eprintln!("Wrong passphrase, try again.")
+      }
+      Err(e) => return Err(e.into()),
+    }
+  }
+
+  Err(VaultError::Decrypt.into())
+}
+
+fn new_vault() -> Result<Vault, AuthError> {
+  if let Some(vault) = Vault::from_env() {
+    return Ok(vault);
+  }
+
+  println!("Choose a passphrase to encrypt your credentials and token.");
+  new_passphrase().map(Vault::new)
+}
+
+fn new_passphrase() -> Result<String, AuthError> {
+  if let Ok(passphrase) = std::env::var(NEW_PASSPHRASE_VAR) {
+    return Ok(passphrase);
+  }
+
+  let passphrase = prompt_hidden("New passphrase: ")?;
+  if passphrase.is_empty() {
+    return Err(AuthError::InputError(
+      "Passphrase cannot be empty".to_string(),
+    ));
+  }
+
+  match prompt_hidden("Confirm passphrase: ")? == passphrase {
+    true => Ok(passphrase),
+    false => Err(AuthError::InputError(
+      "Passphrases do not match".to_string(),
+    )),
+  }
+}
+
+/// Re-encrypts the credentials and the token cache with a new passphrase,
+/// encrypting them for the first time if they're not yet.
+pub fn rotate_passphrase(profile: &Profile) -> Result<(), AuthError> {
+  let paths = [profile.credentials_path()?, profile.token_cache_path()?];
+
+  let current = match sealed_files(profile)?.first() {
+    Some(sealed) => Some(unlock_file(sealed)?),
+    None => None,
+  };
+
+  let contents = paths
+    .iter()
+    .map(|path| read_secret(path, current.as_ref()))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  let next = Vault::new(new_passphrase()?);
+  let files = paths
+    .into_iter()
+    .zip(contents)
+    .filter_map(|(path, content)| Some((path, content?)))
+    .collect::<Vec<_>>();
+  reseal(&files, &next)?;
+
+  println!("✓ Passphrase changed");
+  Ok(())
+}
+
+/// Writes the `files` sealed by the `vault`, all of them or none, so that they
+/// can't end up under different passphrases.
+fn reseal(files: &[(PathBuf, String)], vault: &Vault) -> Result<(), AuthError> {
+  let originals = files
+    .iter()
+    .map(|(path, _)| std::fs::read(path))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  let staged = files
+    .iter()
+    .map(|(path, _)| {
+      let mut temporary = path.as_os_str().to_owned();
+      temporary.push(".new");
+      PathBuf::from(temporary)
+    })
+    .collect::<Vec<_>>();
+  let remove_staged = || {
+    for temporary in &staged {
+      let _ = std::fs::remove_file(temporary);
+    }
+  };
+
+  for ((_, content), temporary) in files.iter().zip(&staged) {
+    if let Err(err) = write_secret(temporary, content, Some(vault)) {
+      remove_staged();
+      return Err(err);
+    }
+  }
+
+  for (index, ((path, _), temporary)) in files.iter().zip(&staged).enumerate() {
+    if let Err(err) = std::fs::rename(temporary, path) {
+      // puts back those already replaced
+      for ((path, _), original) in files.iter().zip(&originals).take(index) {
+        write_private(path, original)?;
+      }
+      remove_staged();
+      return Err(err.into());
+    }
+  }
+
+  Ok(())
+}
+
+/// Signs out of `profile` by removing its token cache, so that it has
+/// to be authorized again. Returns whether it was signed in.
+pub fn logout(profile: &Profile) -> Result<bool, AuthError> {
+  let path = profile.token_cache_path()?;
+  match path.exists() {
+    true => std::fs::remove_file(path).map(|_| true).map_err(Into::into),
+    false => Ok(false),
+  }
+}
+
+/// Asks for new credentials, replacing the stored ones. The token was issued
+/// to the previous app, so this also [logs out](self::logout).
+pub fn reset_credentials(profile: &Profile, options: &AuthConfig) -> Result<(), AuthError> {
+  let vault = unlock(profile, options)?;
+  let creds = prompt_for_credentials()?;
+
+  credentials::save(profile, &creds, vault.as_ref())?;
+  credentials::remove_legacy(profile)?;
+  logout(profile)?;
+
+  println!(
+    "✓ Credentials saved to: {}",
+    profile.credentials_path()?.display()
+  );
+  println!("  Run orpheus to authorize it with Spotify.");
+  Ok(())
+}
+
+/// Stored files that are encrypted.
+fn sealed_files(profile: &Profile) -> Result<Vec<PathBuf>, AuthError> {
+  Ok(
+    [profile.credentials_path()?, profile.token_cache_path()?]
+      .into_iter()
+      .filter(|path| vault::is_sealed_file(path))
+      .collect(),
+  )
+}
+
+/// Encrypts the stored files that are still in plain text.
+fn seal_plaintext(profile: &Profile, vault: &Vault) -> Result<(), AuthError> {
+  for path in [profile.credentials_path()?, profile.token_cache_path()?] {
+    if path.exists() && !vault::is_sealed_file(&path) {
+      let content = std::fs::read_to_string(&path)?;
+      write_secret(&path, &content, Some(vault))?;
+    }
+  }
+
+  Ok(())
+}
+
+/// Reads the file at `path`, decrypting it if it's sealed.
+pub(crate) fn read_secret(path: &Path, vault: Option<&Vault>) -> Result<Option<String>, AuthError> {
+  if !path.exists() {
+    return Ok(None);
+  }
+
+  let content = std::fs::read_to_string(path)?;
+  if !vault::is_sealed(&content) {
+    return Ok(Some(content));
+  }
+
+  let vault = vault.ok_or(VaultError::Decrypt)?;
+  let plaintext = vault.open(&content)?;
+  String::from_utf8(plaintext)
+    .map(Some)
+    .map_err(|_| VaultError::Malformed.into())
+}
+
+/// Writes `content` to `path`, sealed by the `vault` if there's one.
+pub(crate) fn write_secret(
+  path: &Path,
+  content: &str,
+  vault: Option<&Vault>,
+) -> Result<(), AuthError> {
+  match vault {
+    Some(vault) => write_private(path, vault.seal(content.as_bytes())?.as_bytes()),
+    None => write_private(path, content.as_bytes()),
+  }
+}
+
+/// Writes `content` to `path`, readable only by the current user.
+pub(crate) fn write_private(path: &Path, content: &[u8]) -> Result<(), AuthError> {
+  if let Some(parent) = path.parent() {
+    std::fs::create_dir_all(parent).map_err(|_| AuthError::ConfigDir)?;
+  }
+
+  std::fs::write(path, content)?;
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    let mut perms = std::fs::metadata(path)?.permissions();
+    perms.set_mode(0o600);
+    std::fs::set_permissions(path, perms)?;
+  }
+
+  Ok(())
+}
+
+/// Reads the cached token. A cache that can't be parsed is ignored,
+/// but one that can't be decrypted is an error so it's not overwritten.
+fn read_token(profile: &Profile, vault: Option<&Vault>) -> Result<Option<Token>, AuthError> {
+  let content = read_secret(&profile.token_cache_path()?, vault)?;
+  Ok(content.and_then(|content| serde_json::from_str(&content).ok()))
+}
+
+fn write_token(path: &Path, token: &Token, vault: Option<&Vault>) -> Result<(), AuthError> {
+  let content =
+    serde_json::to_string(token).map_err(|e| AuthError::Authentication(e.to_string()))?;
+  write_secret(path, &content, vault)
+}
+
+pub fn config_dir() -> Result<PathBuf, AuthError> {
+  ProjectDirs::from("", "", "orpheus")
+    .map(|dirs| dirs.config_dir().to_path_buf())
+    .ok_or(AuthError::ConfigDir)
+}
+
+fn prompt(message: &str) -> Result<String, AuthError> {
+  print!("{}", message);
+  io::stdout().flush()?;
+
+  let mut input = String::new();
+  io::stdin().read_line(&mut input)?;
+
+  Ok(input.trim().to_string())
+}
+
+/// Prompts without echoing the input, for passphrases.
+fn prompt_hidden(message: &str) -> Result<String, AuthError> {
+  use ratatui::crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    terminal,
+  };
+
+  print!("{}", message);
+  io::stdout().flush()?;
+
+  // not a terminal, so there's nothing to hide
+  if terminal::enable_raw_mode().is_err() {
+    return prompt("");
+  }
+
+  let mut input = String::new();
+  let result = loop {
+    match event::read() {
+      Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => match key.code {
+        KeyCode::Enter => break Ok(()),
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+          break Err(AuthError::InputError("Cancelled".to_string()));
+        }
+        KeyCode::Char(c) => input.push(c),
+        KeyCode::Backspace => {
+          input.pop();
+        }
+        _ => {}
+      },
+      Ok(_) => {}
+      Err(e) => break Err(e.into()),
+    }
+  };
+
+  terminal::disable_raw_mode()?;
+  println!();
+
+  result.map(|_| input)
+}
+
+fn prompt_for_credentials() -> Result<StoredCredentials, AuthError> {
+  println!("To use Orpheus, you need to create a Spotify Developer App:");
+  println!();
+  println!("  1. Go to: https://developer.spotify.com/dashboard");
+  println!("  2. Log in with your Spotify account");
+  println!("  3. Click 'Create App'");
+  println!("  4. Fill in a name and description (anything you like)");
+  println!("  5. Set the Redirect URI to: {DEFAULT_REDIRECT_URI}");
+  println!("  6. Check 'Web API' under 'Which API/SDKs are you planning to use?'");
+  println!("  7. Accept the terms and click 'Save'");
+  println!("  8. Click 'Settings' to find your Client ID");
+  println!();
+
+  let client_id = prompt("Enter your Client ID: ")?;
+  if client_id.is_empty() {
+    return Err(AuthError::InputError(
+      "Client ID cannot be empty".to_string(),
+    ));
+  }
+  credentials::validate_client_id(&client_id)?;
+
+  let redirect_uri = prompt(&format!(
+    "Enter your Redirect URI [{DEFAULT_REDIRECT_URI}]: "
+  ))?;
+  let redirect_uri = if redirect_uri.is_empty() {
+    DEFAULT_REDIRECT_URI.to_string()
+  } else {
+    redirect_uri
+  };
+  credentials::validate_redirect_uri(&redirect_uri)?;
+
+  Ok(StoredCredentials::new(client_id, redirect_uri))
+}
+
+fn get_or_prompt_credentials(
+  profile: &Profile,
+  vault: Option<&Vault>,
+) -> Result<StoredCredentials, AuthError> {
+  if let Some(creds) = credentials::load(profile, vault)? {
+    return Ok(creds);
+  }
+  let creds = prompt_for_credentials()?;
+  credentials::save(profile, &creds, vault)?;
+  println!(
+    "✓ Credentials saved to: {}",
+    profile.credentials_path()?.display()
+  );
+
+  Ok(creds)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::TempDir;
+
+  /// Secrets sealed by `old` in `dir`, with the contents to reseal them with.
+  fn secrets(dir: &TempDir, old: &Vault) -> Vec<(PathBuf, String)> {
+    ["credentials.toml", "token.json"]
+      .into_iter()
+      .map(|name| {
+        let path = dir.path().join(name);
+        write_secret(&path, &format!("old {name}"), Some(old)).unwrap();
+        (path, format!("new {name}"))
+      })
+      .collect()
+  }
+
+  #[test]
+  fn codes_are_parsed_from_redirects_or_on_their_own() {
+    let dir = TempDir::new().unwrap();
+    let stored = StoredCredentials::new(
+      "0123456789abcdef0123456789abcdef".to_string(),
+      credentials::DEFAULT_REDIRECT_URI.to_string(),
+    );
+    let spotify = client(&Profile::rooted(dir.path()), stored, None).unwrap();
+    let state = spotify.get_oauth().state.clone();
+    let redirect = |query: &str| format!("{}?{query}", credentials::DEFAULT_REDIRECT_URI);
+
+    let cases = [
+      (
+        redirect(&format!("code=AQB-x_1&state={state}")),
+        Some("AQB-x_1"),
+      ),
+      (
+        format!("  {}\n", redirect(&format!("state={state}&code=c"))),
+        Some("c"),
+      ),
+      ("AQB-x_1".to_string(), Some("AQB-x_1")),
+      (" AQB-x_1 \n".to_string(), Some("AQB-x_1")),
+      (redirect("code=AQB&state=other"), None),
+      (redirect("code=AQB"), None),
+      (
+        redirect(&format!("error=access_denied&state={state}")),
+        None,
+      ),
+      ("?code=AQB".to_string(), None),
+      ("".to_string(), None),
+      ("not a code".to_string(), None),
+      ("code=AQB".to_string(), None),
+      ("https://example.com/callback".to_string(), None),
+    ];
+
+    for (input, code) in cases {
+      assert_eq!(parse_code(&spotify, &input).as_deref(), code, "{input}");
+    }
+  }
+
+  #[test]
+  fn resealed_files_all_open_with_the_new_passphrase() {
+    let dir = TempDir::new().unwrap();
+    let (old, new) = (Vault::cheap("old"), Vault::cheap("new"));
+    let files = secrets(&dir, &old);
+
+    reseal(&files, &new).unwrap();
+
+    for (path, content) in &files {
+      assert_eq!(
+        read_secret(path, Some(&new)).unwrap().as_ref(),
+        Some(content)
+      );
+      assert!(read_secret(path, Some(&old)).is_err());
+    }
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), files.len());
+  }
+
+  #[test]
+  fn files_are_kept_as_they_were_if_one_cant_be_resealed() {
+    let dir = TempDir::new().unwrap();
+    let (old, new) = (Vault::cheap("old"), Vault::cheap("new"));
+    let files = secrets(&dir, &old);
+    // where the last one would be staged
+    std::fs::create_dir(dir.path().join("token.json.new")).unwrap();
+
+    assert!(reseal(&files, &new).is_err());
+
+    for (path, _) in &files {
+      let name = path.file_name().unwrap().to_string_lossy();
+      let content = read_secret(path, Some(&old)).unwrap();
+      assert_eq!(content, Some(format!("old {name}")));
+    }
+    assert!(!dir.path().join("credentials.toml.new").exists());
+  }
+}
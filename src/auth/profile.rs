@@ -0,0 +1,142 @@
+//! Named profiles, for machines shared between accounts.
+//!
+//! Every profile has its own credentials, token cache, config overrides and
+//! local caches. The default profile keeps its files at the root of the
+//! [config directory](super::config_dir), while named ones live under
+//! `profiles/<name>`.
+
+use crate::auth::{AuthError, config_dir};
+use directories::ProjectDirs;
+use std::path::PathBuf;
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Profile {
+  /// `None` for the default profile.
+  name: Option<String>,
+  /// Directory of the profile's files in place of the user's directories.
+  root: Option<PathBuf>,
+}
+
+impl Profile {
+  /// The profile called `name`, which can be made of letters, digits, `-` and `_`.
+  pub fn named(name: &str) -> Result<Self, AuthError> {
+    if name == DEFAULT_PROFILE {
+      return Ok(Self::default());
+    }
+
+    let is_valid = !name.is_empty()
+      && name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    match is_valid {
+      true => Ok(Self {
+        name: Some(name.to_string()),
+        root: None,
+      }),
+      false => Err(AuthError::Profile(format!(
+        "'{name}' is not a valid name, use letters, digits, '-' and '_'"
+      ))),
+    }
+  }
+
+  /// The default profile, keeping its files under `root` so that tests don't
+  /// touch the user's own.
+  #[cfg(test)]
+  pub fn rooted(root: impl Into<PathBuf>) -> Self {
+    Self {
+      name: None,
+      root: Some(root.into()),
+    }
+  }
+
+  pub fn name(&self) -> &str {
+    self.name.as_deref().unwrap_or(DEFAULT_PROFILE)
+  }
+
+  /// Directory of the profile's configuration and secrets.
+  pub fn dir(&self) -> Result<PathBuf, AuthError> {
+    let mut path = match &self.root {
+      Some(root) => root.join("config"),
+      None => config_dir()?,
+    };
+    if let Some(name) = &self.name {
+      path.push("profiles");
+      path.push(name);
+    }
+    Ok(path)
+  }
+
+  /// Directory of the profile's local caches.
+  pub fn cache_dir(&self) -> Result<PathBuf, AuthError> {
+    let mut path = match &self.root {
+      Some(root) => root.join("cache"),
+      None => ProjectDirs::from("", "", "orpheus")
+        .map(|dirs| dirs.cache_dir().to_path_buf())
+        .ok_or(AuthError::ConfigDir)?,
+    };
+    if let Some(name) = &self.name {
+      path.push("profiles");
+      path.push(name);
+    }
+    Ok(path)
+  }
+
+  /// Socket of the [IPC server](crate::ipc) of the instance running the profile,
+  /// in the runtime directory if there's one.
+  pub fn socket_path(&self) -> Result<PathBuf, AuthError> {
+    let runtime_dir = ProjectDirs::from("", "", "orpheus")
+      .and_then(|dirs| dirs.runtime_dir().map(|dir| dir.to_path_buf()));
+    let mut path = match (&self.root, runtime_dir) {
+      (Some(root), _) => root.join("run"),
+      (None, Some(dir)) => dir,
+      (None, None) => Self::default().cache_dir()?,
+    };
+    path.push(format!("{}.sock", self.name()));
+    Ok(path)
+  }
+
+  pub fn credentials_path(&self) -> Result<PathBuf, AuthError> {
+    self.file("credentials.toml")
+  }
+
+  pub fn token_cache_path(&self) -> Result<PathBuf, AuthError> {
+    self.file("token_cache.json")
+  }
+
+  /// Config overrides of the profile. For the default one that's the base config.
+  pub fn config_path(&self) -> Result<PathBuf, AuthError> {
+    self.file("config.toml")
+  }
+
+  /// Every profile with stored credentials, starting with the default one.
+  pub fn list() -> Result<Vec<Self>, AuthError> {
+    let mut profiles = vec![Self::default()];
+
+    let mut dir = config_dir()?;
+    dir.push("profiles");
+
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+      return Ok(profiles);
+    };
+
+    let mut named = entries
+      .flatten()
+      .filter(|entry| entry.path().join("credentials.toml").exists())
+      .filter_map(|entry| entry.file_name().into_string().ok())
+      .filter_map(|name| Self::named(&name).ok())
+      .collect::<Vec<_>>();
+    named.sort_by(|a, b| a.name().cmp(b.name()));
+
+    profiles.extend(named);
+    Ok(profiles)
+  }
+
+  fn file(&self, name: &str) -> Result<PathBuf, AuthError> {
+    let mut path = self.dir()?;
+    path.push(name);
+    Ok(path)
+  }
+}
@@ -0,0 +1,73 @@
+//! Details of the session a client is signed in with.
+
+use crate::auth::AuthError;
+use chrono::{DateTime, TimeDelta, Utc};
+use rspotify::{
+  AuthCodePkceSpotify,
+  model::PrivateUser,
+  prelude::{BaseClient, Id, OAuthClient},
+};
+
+/// The account, scopes and token expiry in use.
+#[derive(Debug, Clone)]
+pub struct Session {
+  pub user_id: String,
+  pub display_name: Option<String>,
+  pub client_id: String,
+  /// Scopes granted to the token, sorted.
+  pub scopes: Vec<String>,
+  pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Session {
+  /// Fetches the current user, or `None` if the client isn't signed in.
+  ///
+  /// That might refresh the token, so the expiry is read afterwards.
+  pub async fn current(spotify: &AuthCodePkceSpotify) -> Result<Option<Self>, AuthError> {
+    if spotify.token.lock().await.unwrap().is_none() {
+      return Ok(None);
+    }
+
+    let user = spotify
+      .current_user()
+      .await
+      .map_err(|e| AuthError::Authentication(e.to_string()))?;
+
+    Ok(Self::of(spotify, user).await)
+  }
+
+  /// The session of `user`, fetched with the client, or `None` if it has no token.
+  pub async fn of(spotify: &AuthCodePkceSpotify, user: PrivateUser) -> Option<Self> {
+    let token = spotify.token.lock().await.unwrap().clone()?;
+
+    let mut scopes = token.scopes.into_iter().collect::<Vec<_>>();
+    scopes.sort();
+
+    Some(Self {
+      user_id: user.id.id().to_string(),
+      display_name: user.display_name,
+      client_id: spotify.creds.id.clone(),
+      scopes,
+      expires_at: token.expires_at,
+    })
+  }
+
+  /// The display name, or the id for accounts without one.
+  pub fn account(&self) -> &str {
+    self.display_name.as_deref().unwrap_or(&self.user_id)
+  }
+
+  /// Time left before the token expires, negative once it has.
+  pub fn expires_in(&self) -> Option<TimeDelta> {
+    self.expires_at.map(|at| at - Utc::now())
+  }
+
+  /// Human readable [expiry](Self::expires_in), e.g. `in 42 minutes`.
+  pub fn expiry(&self) -> String {
+    match self.expires_in() {
+      Some(left) if left > TimeDelta::zero() => format!("in {} minutes", left.num_minutes()),
+      Some(_) => "expired".to_string(),
+      None => "unknown".to_string(),
+    }
+  }
+}
@@ -0,0 +1,235 @@
+//! At-rest encryption for the token cache and the credentials.
+//!
+//! Files are sealed with XChaCha20-Poly1305 under a key derived from the
+//! user's passphrase with Argon2id. Every sealed file carries its own salt and
+//! nonce after a header, so they can be recognised and opened transparently:
+//!
+//! ```text
+//! orpheus-vault:v1:<base64(salt | nonce | ciphertext)>
+//! ```
+//!
+//! The passphrase can be supplied with the `ORPHEUS_PASSPHRASE` environment
+//! variable for automation, otherwise it's prompted for.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chacha20poly1305::{
+  KeyInit, XChaCha20Poly1305, XNonce,
+  aead::{Aead, Payload},
+};
+use rand::RngCore;
+use std::path::Path;
+use thiserror::Error;
+
+pub const PASSPHRASE_VAR: &str = "ORPHEUS_PASSPHRASE";
+
+const HEADER: &str = "orpheus-vault:v1:";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum VaultError {
+  #[error("Wrong passphrase, or the file is corrupted")]
+  Decrypt,
+  #[error("Failed to encrypt the file")]
+  Encrypt,
+  #[error("The encrypted file is malformed")]
+  Malformed,
+  #[error("Failed to derive the key: {0}")]
+  KeyDerivation(String),
+}
+
+/// Seals and opens files with a key derived from the passphrase.
+#[derive(Clone)]
+pub struct Vault {
+  passphrase: String,
+  /// Cost of deriving the key, the same for every file.
+  params: Params,
+}
+
+impl std::fmt::Debug for Vault {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str("Vault")
+  }
+}
+
+impl Vault {
+  pub fn new(passphrase: String) -> Self {
+    Self {
+      passphrase,
+      params: Params::default(),
+    }
+  }
+
+  /// A vault deriving its keys at the lowest cost, so that tests don't wait on Argon2.
+  #[cfg(test)]
+  pub fn cheap(passphrase: &str) -> Self {
+    Self {
+      passphrase: passphrase.to_string(),
+      params: Params::new(Params::MIN_M_COST, Params::MIN_T_COST, 1, None).unwrap(),
+    }
+  }
+
+  /// The vault of the passphrase in the environment, if any.
+  pub fn from_env() -> Option<Self> {
+    std::env::var(PASSPHRASE_VAR)
+      .ok()
+      .filter(|passphrase| !passphrase.is_empty())
+      .map(Self::new)
+  }
+
+  pub fn seal(&self, plaintext: &[u8]) -> Result<String, VaultError> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let cipher = self.cipher(&salt)?;
+    let ciphertext = cipher
+      .encrypt(
+        XNonce::from_slice(&nonce),
+        Payload {
+          msg: plaintext,
+          aad: HEADER.as_bytes(),
+        },
+      )
+      .map_err(|_| VaultError::Encrypt)?;
+
+    let mut sealed = Vec::with_capacity(SALT_LEN + NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+
+    Ok(format!("{HEADER}{}\n", STANDARD.encode(sealed)))
+  }
+
+  pub fn open(&self, sealed: &str) -> Result<Vec<u8>, VaultError> {
+    let encoded = sealed
+      .trim()
+      .strip_prefix(HEADER)
+      .ok_or(VaultError::Malformed)?;
+    let bytes = STANDARD
+      .decode(encoded)
+      .map_err(|_| VaultError::Malformed)?;
+
+    if bytes.len() < SALT_LEN + NONCE_LEN {
+      return Err(VaultError::Malformed);
+    }
+
+    let (salt, rest) = bytes.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    self
+      .cipher(salt)?
+      .decrypt(
+        XNonce::from_slice(nonce),
+        Payload {
+          msg: ciphertext,
+          aad: HEADER.as_bytes(),
+        },
+      )
+      .map_err(|_| VaultError::Decrypt)
+  }
+
+  fn cipher(&self, salt: &[u8]) -> Result<XChaCha20Poly1305, VaultError> {
+    let mut key = [0u8; KEY_LEN];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+      .hash_password_into(self.passphrase.as_bytes(), salt, &mut key)
+      .map_err(|e| VaultError::KeyDerivation(e.to_string()))?;
+
+    Ok(XChaCha20Poly1305::new(&key.into()))
+  }
+}
+
+/// Whether the content was sealed by a [vault](self::Vault).
+pub fn is_sealed(content: &str) -> bool {
+  content.starts_with(HEADER)
+}
+
+/// Whether the file at `path` exists and is sealed.
+pub fn is_sealed_file(path: &Path) -> bool {
+  std::fs::read_to_string(path).is_ok_and(|content| is_sealed(&content))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::auth::{AuthError, read_secret, write_secret};
+  use tempfile::TempDir;
+
+  #[test]
+  fn sealed_files_open_with_the_same_passphrase() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("secret");
+    let vault = Vault::cheap("passphrase");
+
+    write_secret(&path, "token", Some(&vault)).unwrap();
+
+    assert!(is_sealed_file(&path));
+    assert!(!std::fs::read_to_string(&path).unwrap().contains("token"));
+    let content = read_secret(&path, Some(&vault)).unwrap();
+    assert_eq!(content.as_deref(), Some("token"));
+  }
+
+  #[test]
+  fn plain_files_are_read_as_they_are() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("secret");
+
+    write_secret(&path, "token", None).unwrap();
+
+    assert!(!is_sealed_file(&path));
+    let content = read_secret(&path, Some(&Vault::cheap("passphrase"))).unwrap();
+    assert_eq!(content.as_deref(), Some("token"));
+  }
+
+  #[test]
+  fn a_wrong_passphrase_opens_nothing() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("secret");
+    write_secret(&path, "token", Some(&Vault::cheap("passphrase"))).unwrap();
+
+    let wrong = Vault::cheap("wrong");
+    assert!(matches!(
+      read_secret(&path, Some(&wrong)),
+      Err(AuthError::Vault(VaultError::Decrypt))
+    ));
+    assert!(matches!(
+      read_secret(&path, None),
+      Err(AuthError::Vault(VaultError::Decrypt))
+    ));
+  }
+
+  #[test]
+  fn tampered_files_are_refused() {
+    let vault = Vault::cheap("passphrase");
+    let sealed = vault.seal(b"token").unwrap();
+    let encoded = sealed.trim().strip_prefix(HEADER).unwrap();
+
+    let mut bytes = STANDARD.decode(encoded).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    let tampered = format!("{HEADER}{}", STANDARD.encode(&bytes));
+    assert!(matches!(vault.open(&tampered), Err(VaultError::Decrypt)));
+
+    let truncated = format!("{HEADER}{}", STANDARD.encode(&bytes[..SALT_LEN]));
+    assert!(matches!(vault.open(&truncated), Err(VaultError::Malformed)));
+
+    let other_header = sealed.replace("v1", "v2");
+    assert!(matches!(
+      vault.open(&other_header),
+      Err(VaultError::Malformed)
+    ));
+    assert!(matches!(
+      vault.open(&format!("{HEADER}not base64!")),
+      Err(VaultError::Malformed)
+    ));
+  }
+
+  #[test]
+  fn every_seal_has_its_own_salt_and_nonce() {
+    let vault = Vault::cheap("passphrase");
+    assert_ne!(vault.seal(b"token").unwrap(), vault.seal(b"token").unwrap());
+  }
+}
@@ -0,0 +1,136 @@
+//! Command line arguments.
+//!
+//! Flags override the matching [configuration](crate::config::Config) fields.
+
+mod playback;
+
+use crate::{
+  auth::{self, AuthError, AuthMode, credentials, profile::Profile, session::Session},
+  cli::playback::Playback,
+  config::Config,
+  io::IoError,
+};
+use clap::{Parser, Subcommand};
+use thiserror::Error;
+
+#[derive(Debug, Parser)]
+#[command(
+  name = "orpheus",
+  version,
+  about = "Spotify TUI client built to be fast and intuitive",
+  after_help = "Commands exit with 1 on errors, 2 on invalid arguments, 3 when not signed in, \
+                4 when nothing is playing or there's no active device, 5 on network errors and 6 \
+                when rate limited."
+)]
+pub(crate) struct Cli {
+  /// Profile to use, each with its own account and configuration.
+  #[arg(long, short, default_value = auth::profile::DEFAULT_PROFILE)]
+  pub profile: String,
+  /// How to authorize orpheus with Spotify.
+  #[arg(long, value_enum)]
+  pub auth: Option<AuthMode>,
+  /// Print a QR code of the authorization URL when authorizing headlessly.
+  #[arg(long)]
+  pub qr: bool,
+
+  #[command(subcommand)]
+  pub command: Option<Command>,
+}
+
+/// Commands that run without starting the TUI.
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+  /// Change the passphrase of the encrypted credentials and token cache,
+  /// or encrypt them for the first time.
+  RotatePassphrase,
+  /// Sign out by removing the cached token.
+  Logout,
+  /// Enter the Spotify app credentials again, replacing the stored ones.
+  ResetCredentials,
+  /// Show the account, scopes and token expiry in use.
+  Whoami,
+
+  #[command(flatten)]
+  Playback(Playback),
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum CliError {
+  #[error(transparent)]
+  Auth(#[from] AuthError),
+  #[error(transparent)]
+  Io(#[from] IoError),
+  #[error("Profile '{0}' is not signed in, run orpheus to authorize it")]
+  NotSignedIn(String),
+  #[error("Nothing is playing")]
+  NothingPlaying,
+  #[error("Invalid argument: {0}")]
+  InvalidArgument(String),
+  #[error("Failed to write JSON: {0}")]
+  Json(#[from] serde_json::Error),
+}
+
+impl CliError {
+  /// Status code to exit with, so scripts can tell errors apart.
+  pub fn exit_code(&self) -> i32 {
+    match self {
+      Self::NotSignedIn(_) | Self::Io(IoError::Unauthorized) => 3,
+      Self::NothingPlaying | Self::Io(IoError::Status(404)) => 4,
+      Self::Io(IoError::Network(_)) => 5,
+      Self::Io(IoError::RateLimited(_)) => 6,
+      Self::InvalidArgument(_) => 2,
+      _ => 1,
+    }
+  }
+}
+
+impl Command {
+  pub async fn run(&self, profile: &Profile, config: &Config) -> Result<(), CliError> {
+    match self {
+      Self::RotatePassphrase => auth::rotate_passphrase(profile)?,
+      Self::Logout => match auth::logout(profile)? {
+        true => println!("✓ Logged out of profile '{}'", profile.name()),
+        false => println!("Profile '{}' is not signed in", profile.name()),
+      },
+      Self::ResetCredentials => auth::reset_credentials(profile, &config.auth)?,
+      Self::Whoami => whoami(profile).await?,
+      Self::Playback(playback) => {
+        if !credentials::exist(profile)? {
+          return Err(CliError::NotSignedIn(profile.name().to_string()));
+        }
+
+        let spotify = auth::open(profile).await?;
+        if spotify.token.lock().await.unwrap().is_none() {
+          return Err(CliError::NotSignedIn(profile.name().to_string()));
+        }
+        playback.run(&spotify).await?
+      }
+    }
+
+    Ok(())
+  }
+}
+
+async fn whoami(profile: &Profile) -> Result<(), CliError> {
+  let spotify = auth::open(profile).await?;
+
+  let Some(session) = Session::current(&spotify).await? else {
+    println!("Profile '{}' is not signed in", profile.name());
+    return Ok(());
+  };
+
+  println!("Profile:   {}", profile.name());
+  println!("Account:   {} ({})", session.account(), session.user_id);
+  println!("Client ID: {}", session.client_id);
+  println!("Scopes:    {}", session.scopes.join(", "));
+  match session.expires_at {
+    Some(at) => println!(
+      "Expires:   {} ({})",
+      at.format("%F %T UTC"),
+      session.expiry()
+    ),
+    None => println!("Expires:   {}", session.expiry()),
+  }
+
+  Ok(())
+}
@@ -0,0 +1,438 @@
+//! Playback commands, to script orpheus or bind media keys without the TUI.
+//!
+//! Requests go through the same [retry policy](crate::io::send) as the TUI.
+
+use crate::{
+  cli::CliError,
+  io::{self, IoError, target::PlayTarget},
+  now_playing::timestamp,
+};
+use clap::{Subcommand, ValueEnum};
+use rspotify::{
+  AuthCodePkceSpotify as Spotify,
+  model::{
+    AdditionalType, CurrentPlaybackContext, Device, PlayableItem, RepeatState, SearchResult,
+    SearchType,
+  },
+  prelude::{BaseClient, Id, OAuthClient},
+};
+use serde::Serialize;
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum Playback {
+  /// Resume playback, or play the track, album, playlist, artist or show of a Spotify URI.
+  Play { uri: Option<String> },
+  /// Pause playback.
+  Pause,
+  /// Skip to the next track.
+  Next,
+  /// Go back to the previous track.
+  #[command(alias = "previous")]
+  Prev,
+  /// Seek to a position like `90` or `1:30`, or by an offset like `+10` or `-0:30`.
+  Seek {
+    #[arg(allow_hyphen_values = true)]
+    position: String,
+  },
+  /// Set the volume from 0 to 100, or change it by an offset like `+5` or `-5`.
+  Volume {
+    #[arg(allow_hyphen_values = true)]
+    level: String,
+  },
+  /// Turn shuffle on or off, toggling it by default.
+  Shuffle {
+    #[arg(value_enum, default_value_t)]
+    state: Switch,
+  },
+  /// Set the repeat mode, cycling through them by default.
+  Repeat {
+    #[arg(value_enum)]
+    mode: Option<Repeat>,
+  },
+  /// Show what's playing. Exits with 4 if nothing is.
+  Status {
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+  },
+  /// List the devices available for playback.
+  Devices {
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+  },
+  /// Search Spotify.
+  Search {
+    query: String,
+    #[arg(long = "type", value_enum, default_value_t)]
+    kind: SearchKind,
+    #[arg(long, default_value_t = 10)]
+    limit: u32,
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+  },
+}
+
+/// Output format of the commands printing data.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Format {
+  #[default]
+  Text,
+  /// Spotify's objects as JSON, for scripts.
+  Json,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Switch {
+  On,
+  Off,
+  #[default]
+  Toggle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Repeat {
+  Off,
+  Track,
+  Context,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum SearchKind {
+  #[default]
+  Track,
+  Album,
+  Artist,
+  Playlist,
+  Show,
+  Episode,
+}
+
+impl Playback {
+  pub async fn run(&self, spotify: &Spotify) -> Result<(), CliError> {
+    match self {
+      Self::Play { uri: None } => {
+        io::send(spotify, None, true, |spotify| {
+          spotify.resume_playback(None, None)
+        })
+        .await?
+      }
+      Self::Play { uri: Some(uri) } => play(spotify, uri).await?,
+      Self::Pause => io::send(spotify, None, true, |spotify| spotify.pause_playback(None)).await?,
+      Self::Next => io::send(spotify, None, false, |spotify| spotify.next_track(None)).await?,
+      Self::Prev => io::send(spotify, None, false, |spotify| spotify.previous_track(None)).await?,
+
+      Self::Seek { position } => {
+        let (offset, ms) = parse_offset(position, parse_position)?;
+        let ms = match offset {
+          true => (progress_ms(&playing(spotify).await?) + ms).max(0),
+          false => ms,
+        };
+
+        let position = chrono::Duration::milliseconds(ms);
+        io::send(spotify, None, true, |spotify| {
+          spotify.seek_track(position, None)
+        })
+        .await?
+      }
+
+      Self::Volume { level } => {
+        let (offset, level) = parse_offset(level, |level| level.parse().ok())?;
+        let level = match offset {
+          true => {
+            let current = playing(spotify).await?.device.volume_percent.unwrap_or(0);
+            i64::from(current) + level
+          }
+          false => level,
+        };
+
+        let level = level.clamp(0, 100) as u8;
+        io::send(spotify, None, true, |spotify| spotify.volume(level, None)).await?
+      }
+
+      Self::Shuffle { state } => {
+        let state = match state {
+          Switch::On => true,
+          Switch::Off => false,
+          Switch::Toggle => !playing(spotify).await?.shuffle_state,
+        };
+        io::send(spotify, None, true, |spotify| spotify.shuffle(state, None)).await?
+      }
+
+      Self::Repeat { mode } => {
+        let mode = match mode {
+          Some(Repeat::Off) => RepeatState::Off,
+          Some(Repeat::Track) => RepeatState::Track,
+          Some(Repeat::Context) => RepeatState::Context,
+          None => match playing(spotify).await?.repeat_state {
+            RepeatState::Off => RepeatState::Context,
+            RepeatState::Context => RepeatState::Track,
+            RepeatState::Track => RepeatState::Off,
+          },
+        };
+        io::send(spotify, None, true, |spotify| spotify.repeat(mode, None)).await?
+      }
+
+      Self::Status { format } => {
+        let context = current_playback(spotify).await?;
+        match (format, &context) {
+          // `null`, so that scripts parsing the output don't fail
+          (Format::Json, _) => print_json(&context)?,
+          (Format::Text, Some(context)) => print_status(context),
+          // told once by the error
+          (Format::Text, None) => {}
+        }
+
+        if context.is_none() {
+          return Err(CliError::NothingPlaying);
+        }
+      }
+
+      Self::Devices { format } => {
+        let devices = io::send(spotify, None, true, |spotify| spotify.device()).await?;
+        match format {
+          Format::Json => print_json(&devices)?,
+          Format::Text => devices.iter().for_each(print_device),
+        }
+      }
+
+      Self::Search {
+        query,
+        kind,
+        limit,
+        format,
+      } => {
+        let kind = match kind {
+          SearchKind::Track => SearchType::Track,
+          SearchKind::Album => SearchType::Album,
+          SearchKind::Artist => SearchType::Artist,
+          SearchKind::Playlist => SearchType::Playlist,
+          SearchKind::Show => SearchType::Show,
+          SearchKind::Episode => SearchType::Episode,
+        };
+
+        let result = io::send(spotify, None, true, |spotify| {
+          spotify.search(query, kind, None, None, Some(*limit), None)
+        })
+        .await?;
+
+        match format {
+          Format::Json => print_json(&result)?,
+          Format::Text => print_search(&result),
+        }
+      }
+    }
+
+    Ok(())
+  }
+}
+
+async fn play(spotify: &Spotify, uri: &str) -> Result<(), CliError> {
+  let target = PlayTarget::parse(uri)
+    .ok_or_else(|| CliError::InvalidArgument(format!("'{uri}' is not a playable Spotify URI")))?;
+
+  Ok(io::send(spotify, None, true, |spotify| target.play(spotify)).await?)
+}
+
+async fn current_playback(spotify: &Spotify) -> Result<Option<CurrentPlaybackContext>, IoError> {
+  io::send(spotify, None, true, |spotify| {
+    spotify.current_playback(
+      None,
+      Some(vec![&AdditionalType::Episode, &AdditionalType::Track]),
+    )
+  })
+  .await
+}
+
+/// The current playback, needed to apply an offset or toggle.
+async fn playing(spotify: &Spotify) -> Result<CurrentPlaybackContext, CliError> {
+  current_playback(spotify)
+    .await?
+    .ok_or(CliError::NothingPlaying)
+}
+
+/// Splits a `+N`/`-N` offset from an absolute value, parsed by `parse`.
+fn parse_offset(value: &str, parse: fn(&str) -> Option<i64>) -> Result<(bool, i64), CliError> {
+  let invalid = || CliError::InvalidArgument(format!("'{value}' is not a valid value"));
+
+  let (offset, sign, value) = match value.as_bytes().first() {
+    Some(b'+') => (true, 1, &value[1..]),
+    Some(b'-') => (true, -1, &value[1..]),
+    _ => (false, 1, value),
+  };
+
+  parse(value)
+    .filter(|value| *value >= 0)
+    .map(|value| (offset, sign * value))
+    .ok_or_else(invalid)
+}
+
+/// Parses `SS` or `MM:SS` into milliseconds.
+fn parse_position(position: &str) -> Option<i64> {
+  let seconds = match position.split_once(':') {
+    Some((minutes, seconds)) => minutes.parse::<i64>().ok()? * 60 + seconds.parse::<i64>().ok()?,
+    None => position.parse().ok()?,
+  };
+  Some(seconds * 1000)
+}
+
+fn progress_ms(context: &CurrentPlaybackContext) -> i64 {
+  context
+    .progress
+    .map(|progress| progress.num_milliseconds())
+    .unwrap_or_default()
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), CliError> {
+  println!("{}", serde_json::to_string_pretty(value)?);
+  Ok(())
+}
+
+fn print_status(context: &CurrentPlaybackContext) {
+  let (name, by, duration) = match &context.item {
+    Some(PlayableItem::Track(track)) => (
+      track.name.as_str(),
+      format!(
+        "{} · {}",
+        artists(track.artists.iter().map(|artist| artist.name.as_str())),
+        track.album.name
+      ),
+      track.duration,
+    ),
+    Some(PlayableItem::Episode(episode)) => (
+      episode.name.as_str(),
+      episode.show.name.clone(),
+      episode.duration,
+    ),
+    _ => ("Unknown", String::new(), Default::default()),
+  };
+
+  let icon = match context.is_playing {
+    true => "▶",
+    false => "⏸",
+  };
+  let volume = context
+    .device
+    .volume_percent
+    .map(|volume| format!(" ({volume}%)"))
+    .unwrap_or_default();
+  let repeat: &str = context.repeat_state.into();
+
+  println!("{icon} {name}");
+  if !by.is_empty() {
+    println!("  {by}");
+  }
+  println!(
+    "  {} / {} · on {}{volume} · shuffle {} · repeat {repeat}",
+    timestamp(progress_ms(context)),
+    timestamp(duration.num_milliseconds()),
+    context.device.name,
+    if context.shuffle_state { "on" } else { "off" },
+  );
+}
+
+fn print_device(device: &Device) {
+  let active = if device.is_active { "*" } else { " " };
+  let volume = device
+    .volume_percent
+    .map(|volume| format!(", {volume}%"))
+    .unwrap_or_default();
+
+  println!(
+    "{active} {} ({:?}{volume})  {}",
+    device.name,
+    device._type,
+    device.id.as_deref().unwrap_or("-")
+  );
+}
+
+fn print_search(result: &SearchResult) {
+  let rows: Vec<(String, String, String)> = match result {
+    SearchResult::Tracks(page) => page
+      .items
+      .iter()
+      .map(|track| {
+        (
+          track.name.clone(),
+          artists(track.artists.iter().map(|artist| artist.name.as_str())),
+          track.id.as_ref().map(|id| id.uri()).unwrap_or_default(),
+        )
+      })
+      .collect(),
+    SearchResult::Albums(page) => page
+      .items
+      .iter()
+      .map(|album| {
+        (
+          album.name.clone(),
+          artists(album.artists.iter().map(|artist| artist.name.as_str())),
+          album.id.as_ref().map(|id| id.uri()).unwrap_or_default(),
+        )
+      })
+      .collect(),
+    SearchResult::Artists(page) => page
+      .items
+      .iter()
+      .map(|artist| (artist.name.clone(), String::new(), artist.id.uri()))
+      .collect(),
+    SearchResult::Playlists(page) => page
+      .items
+      .iter()
+      .map(|playlist| {
+        (
+          playlist.name.clone(),
+          playlist.owner.display_name.clone().unwrap_or_default(),
+          playlist.id.uri(),
+        )
+      })
+      .collect(),
+    SearchResult::Shows(page) => page
+      .items
+      .iter()
+      .map(|show| (show.name.clone(), show.publisher.clone(), show.id.uri()))
+      .collect(),
+    SearchResult::Episodes(page) => page
+      .items
+      .iter()
+      .map(|episode| (episode.name.clone(), String::new(), episode.id.uri()))
+      .collect(),
+  };
+
+  for (name, by, uri) in rows {
+    match by.is_empty() {
+      true => println!("{name}  {uri}"),
+      false => println!("{name} — {by}  {uri}"),
+    }
+  }
+}
+
+fn artists<'a>(names: impl Iterator<Item = &'a str>) -> String {
+  names.collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn positions_are_seconds_or_minutes_and_seconds() {
+    assert_eq!(parse_position("90"), Some(90_000));
+    assert_eq!(parse_position("1:30"), Some(90_000));
+    assert_eq!(parse_position("0:05"), Some(5_000));
+    assert_eq!(parse_position("1:3x"), None);
+    assert_eq!(parse_position(""), None);
+  }
+
+  #[test]
+  fn offsets_are_signed() {
+    let parse = |value| parse_offset(value, parse_position).ok();
+
+    assert_eq!(parse("1:30"), Some((false, 90_000)));
+    assert_eq!(parse("+10"), Some((true, 10_000)));
+    assert_eq!(parse("-0:30"), Some((true, -30_000)));
+    assert_eq!(parse("--10"), None);
+    assert_eq!(parse("-"), None);
+    assert!(matches!(
+      parse_offset("loud", |level| level.parse().ok()),
+      Err(CliError::InvalidArgument(_))
+    ));
+  }
+}
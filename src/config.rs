@@ -1,20 +1,264 @@
 //! User configuration.
+//!
+//! The configuration is read from `config.toml` in the
+//! [config directory](crate::auth::config_dir). Every field is optional
+//! and falls back to its default when missing.
+//!
+//! Named [profiles](crate::auth::profile::Profile) can override any of
+//! them in their own `config.toml`, merged over the base one.
 
-use crate::ui::style::Theme;
-use std::time::Duration;
+use crate::{
+  auth::{self, AuthMode, profile::Profile},
+  cli::Cli,
+  ui::style::{IconMode, ImageProtocol, Theme},
+};
+use serde::{Deserialize, Deserializer};
+use std::{
+  path::{Path, PathBuf},
+  time::Duration,
+};
+use thiserror::Error;
+use toml::Table;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct Config {
   pub theme: Theme,
+  /// Glyph set used to render icons.
+  pub icons: IconMode,
   /// Duration in milliseconds between tick events.
+  #[serde(deserialize_with = "millis")]
   pub tick_rate: Duration,
+  /// Listen on a [socket](crate::ipc) so that scripts can control the running instance.
+  pub socket: bool,
+  /// Expose the playback over MPRIS, when built with the `mpris` feature.
+  pub mpris: bool,
+  /// How the album art is drawn, when built with the `album-art` feature.
+  pub album_art: ImageProtocol,
+  /// Tint the accent to the album art playing, when built with the `album-art` feature.
+  pub dynamic_accent: bool,
+  pub auth: AuthConfig,
+  pub now_playing: NowPlayingConfig,
+  pub hooks: HooksConfig,
+  pub notifications: NotificationsConfig,
+  pub lyrics: LyricsConfig,
+}
+
+/// Authentication preferences, under the `[auth]` table.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+  pub mode: AuthMode,
+  /// Print a QR code of the authorization URL when authorizing headlessly.
+  pub qr: bool,
+  /// Encrypt the credentials and the token cache with a passphrase.
+  pub encrypt: bool,
+}
+
+/// Where the [now playing](crate::now_playing) line is exported, under the `[now_playing]` table.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct NowPlayingConfig {
+  /// File or FIFO rewritten whenever the playback changes.
+  pub path: Option<PathBuf>,
+  /// Command started with orpheus, getting a line on its stdin whenever the playback changes.
+  pub command: Option<String>,
+  /// Template of the line, like `{artist} - {title}`, which is JSON when unset.
+  pub format: Option<String>,
+}
+
+impl NowPlayingConfig {
+  pub fn is_enabled(&self) -> bool {
+    self.path.is_some() || self.command.is_some()
+  }
+}
+
+/// Shell commands run on [events](crate::hooks::Hook), under the `[hooks]` table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+  pub track_changed: Option<String>,
+  pub paused: Option<String>,
+  pub resumed: Option<String>,
+  pub device_changed: Option<String>,
+  pub playlist_modified: Option<String>,
+  /// Duration in milliseconds after which a hook still running is killed.
+  #[serde(deserialize_with = "millis")]
+  pub timeout: Duration,
+}
+
+impl HooksConfig {
+  pub fn is_enabled(&self) -> bool {
+    [
+      &self.track_changed,
+      &self.paused,
+      &self.resumed,
+      &self.device_changed,
+      &self.playlist_modified,
+    ]
+    .iter()
+    .any(|hook| hook.is_some())
+  }
+}
+
+impl Default for HooksConfig {
+  fn default() -> Self {
+    Self {
+      track_changed: None,
+      paused: None,
+      resumed: None,
+      device_changed: None,
+      playlist_modified: None,
+      timeout: Duration::from_secs(10),
+    }
+  }
+}
+
+/// Desktop notifications of the track changes, under the `[notifications]` table.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+  /// Notify when the playing item changes, when built with the `notifications` feature.
+  pub enabled: bool,
+  /// Show the album art, or the cover of the show, in the notification.
+  pub album_art: bool,
+  /// Minimum duration in milliseconds between two notifications, so skipping
+  /// through tracks only notifies of the one it stops on.
+  #[serde(deserialize_with = "millis")]
+  pub interval: Duration,
+}
+
+impl Default for NotificationsConfig {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      album_art: true,
+      interval: Duration::from_secs(2),
+    }
+  }
+}
+
+/// Where the [lyrics](crate::lyrics) are looked up, under the `[lyrics]` table.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct LyricsConfig {
+  /// Directory of the `.lrc` and `.txt` files, the `lyrics` folder of the config directory if unset.
+  pub dir: Option<PathBuf>,
+}
+
+impl LyricsConfig {
+  pub fn dir(&self) -> Option<PathBuf> {
+    match &self.dir {
+      Some(dir) => Some(dir.clone()),
+      None => auth::config_dir().ok().map(|dir| dir.join("lyrics")),
+    }
+  }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+  #[error(transparent)]
+  Auth(#[from] auth::AuthError),
+  #[error("Failed to read the config file: {0}")]
+  Io(#[from] std::io::Error),
+  #[error("Invalid config file: {0}")]
+  Parse(#[from] toml::de::Error),
+}
+
+impl Config {
+  /// Loads the configuration of `profile`, using the defaults if there's no config file.
+  pub fn load(profile: &Profile) -> Result<Self, ConfigError> {
+    let mut table = read(&Profile::default().config_path()?)?;
+    if profile != &Profile::default() {
+      merge_tables(&mut table, read(&profile.config_path()?)?);
+    }
+
+    Ok(table.try_into()?)
+  }
+
+  /// Overrides the fields given as command line flags.
+  pub fn merge(&mut self, cli: &Cli) {
+    if let Some(mode) = cli.auth {
+      self.auth.mode = mode;
+    }
+
+    self.auth.qr |= cli.qr;
+  }
 }
 
 impl Default for Config {
   fn default() -> Self {
     Self {
       tick_rate: Duration::from_millis(250),
+      icons: Default::default(),
       theme: Default::default(),
+      socket: true,
+      mpris: true,
+      album_art: Default::default(),
+      dynamic_accent: false,
+      auth: Default::default(),
+      now_playing: Default::default(),
+      hooks: Default::default(),
+      notifications: Default::default(),
+      lyrics: Default::default(),
+    }
+  }
+}
+
+fn read(path: &Path) -> Result<Table, ConfigError> {
+  match path.exists() {
+    true => Ok(std::fs::read_to_string(path)?.parse()?),
+    false => Ok(Table::new()),
+  }
+}
+
+/// Merges `overrides` into `base`, recursing into tables so that a profile
+/// can override a single field of a table.
+fn merge_tables(base: &mut Table, overrides: Table) {
+  for (key, value) in overrides {
+    match (base.get_mut(&key), value) {
+      (Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => {
+        merge_tables(base, overrides)
+      }
+      (_, value) => {
+        base.insert(key, value);
+      }
     }
   }
 }
+
+fn millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+  u64::deserialize(deserializer).map(Duration::from_millis)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(text: &str) -> Result<Config, toml::de::Error> {
+    toml::from_str(text)
+  }
+
+  #[test]
+  fn icons_are_nerd_font_glyphs_unless_configured() {
+    assert_eq!(parse("").unwrap().icons, IconMode::Nerd);
+    assert_eq!(
+      parse("icons = \"unicode\"").unwrap().icons,
+      IconMode::Unicode
+    );
+    assert_eq!(parse("icons = \"ascii\"").unwrap().icons, IconMode::Ascii);
+    assert!(parse("icons = \"emoji\"").is_err());
+  }
+
+  #[test]
+  fn profiles_override_single_fields_of_tables() {
+    let mut base: Table = "icons = \"unicode\"\n[auth]\nqr = true".parse().unwrap();
+    let overrides: Table = "icons = \"ascii\"\n[auth]\nencrypt = true".parse().unwrap();
+    merge_tables(&mut base, overrides);
+
+    let config: Config = base.try_into().unwrap();
+    assert_eq!(config.icons, IconMode::Ascii);
+    assert!(config.auth.qr);
+    assert!(config.auth.encrypt);
+  }
+}
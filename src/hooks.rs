@@ -0,0 +1,442 @@
+//! User hooks, shell commands run on playback and app events.
+//!
+//! Hooks are set under the `[hooks]` [config](crate::config::HooksConfig) table,
+//! one command per [event](self::Hook):
+//!
+//! ```toml
+//! [hooks]
+//! track_changed = 'notify-send "$ORPHEUS_TITLE" "$ORPHEUS_ARTIST"'
+//! ```
+//!
+//! The playback is given in `ORPHEUS_*` environment variables, and as the
+//! [now playing](crate::now_playing) JSON on stdin along with the event.
+//! Hooks run in the background so they never hold the [io](crate::io::Io) up,
+//! and are killed once past the `timeout`.
+
+use crate::{
+  config::HooksConfig,
+  now_playing::NowPlaying,
+  state::{Change, State},
+};
+use rspotify::{model::SimplifiedPlaylist, prelude::Id};
+use serde::Serialize;
+use std::{collections::HashMap, process::Stdio, sync::Arc, time::Duration};
+use tokio::{
+  io::AsyncWriteExt,
+  process::Command,
+  sync::{Mutex, broadcast::error::RecvError},
+  task::JoinHandle,
+};
+
+/// An event a hook can be set for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Hook {
+  TrackChanged,
+  Paused,
+  Resumed,
+  DeviceChanged,
+  /// A playlist of the user was changed, in orpheus or elsewhere.
+  PlaylistModified,
+}
+
+impl Hook {
+  const ALL: [Self; 5] = [
+    Self::TrackChanged,
+    Self::Paused,
+    Self::Resumed,
+    Self::DeviceChanged,
+    Self::PlaylistModified,
+  ];
+
+  /// The hook named `name`, like `track_changed`.
+  pub fn parse(name: &str) -> Option<Self> {
+    Self::ALL.into_iter().find(|hook| hook.name() == name)
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::TrackChanged => "track_changed",
+      Self::Paused => "paused",
+      Self::Resumed => "resumed",
+      Self::DeviceChanged => "device_changed",
+      Self::PlaylistModified => "playlist_modified",
+    }
+  }
+
+  fn command(self, config: &HooksConfig) -> Option<&str> {
+    match self {
+      Self::TrackChanged => &config.track_changed,
+      Self::Paused => &config.paused,
+      Self::Resumed => &config.resumed,
+      Self::DeviceChanged => &config.device_changed,
+      Self::PlaylistModified => &config.playlist_modified,
+    }
+    .as_deref()
+  }
+}
+
+/// The JSON given on stdin.
+#[derive(Debug, Serialize)]
+struct Payload<'a> {
+  event: &'static str,
+  #[serde(flatten)]
+  playback: &'a NowPlaying,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  playlist: Option<&'a Playlist>,
+}
+
+/// The playlist of a [modification](Hook::PlaylistModified).
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Playlist {
+  pub id: String,
+  pub name: String,
+  pub uri: String,
+  pub snapshot_id: String,
+}
+
+impl From<&SimplifiedPlaylist> for Playlist {
+  fn from(playlist: &SimplifiedPlaylist) -> Self {
+    Self {
+      id: playlist.id.id().to_string(),
+      name: playlist.name.clone(),
+      uri: playlist.id.uri(),
+      snapshot_id: playlist.snapshot_id.clone(),
+    }
+  }
+}
+
+/// Compares the state with what it was, to know which hooks are due.
+///
+/// Also used by the [scripts](crate::scripts) for their automations.
+pub(crate) struct Watcher {
+  pub playback: NowPlaying,
+  /// Snapshot of each playlist, unknown until they're first fetched.
+  playlists: Option<HashMap<String, String>>,
+}
+
+impl Watcher {
+  pub fn new(state: &State) -> Self {
+    Self {
+      playback: NowPlaying::of(state),
+      playlists: None,
+    }
+  }
+
+  /// Follows a `change` of `state`, returning the hooks due with their playlist.
+  pub fn update(&mut self, change: Change, state: &State) -> Vec<(Hook, Option<Playlist>)> {
+    match change {
+      Change::Playback => {
+        let playback = NowPlaying::of(state);
+        self
+          .playback_changed(playback)
+          .into_iter()
+          .map(|hook| (hook, None))
+          .collect()
+      }
+      Change::Playlists => match &state.playlists {
+        Some(playlists) => self
+          .playlists_changed(&playlists.items)
+          .into_iter()
+          .map(|playlist| (Hook::PlaylistModified, Some(playlist)))
+          .collect(),
+        None => vec![],
+      },
+      Change::Seeked => vec![],
+      // another account isn't a change of track or playlists
+      Change::Profile => {
+        self.playback = NowPlaying::new(None);
+        self.playlists = None;
+        vec![]
+      }
+    }
+  }
+
+  fn playback_changed(&mut self, playback: NowPlaying) -> Vec<Hook> {
+    let previous = std::mem::replace(&mut self.playback, playback);
+    let current = &self.playback;
+
+    let mut hooks = vec![];
+    if current.title.is_some() && (&current.uri, &current.title) != (&previous.uri, &previous.title)
+    {
+      hooks.push(Hook::TrackChanged);
+    }
+    match (previous.status == "playing", current.status == "playing") {
+      (false, true) => hooks.push(Hook::Resumed),
+      (true, false) => hooks.push(Hook::Paused),
+      _ => {}
+    }
+    if previous.device.is_some() && current.device.is_some() && previous.device != current.device {
+      hooks.push(Hook::DeviceChanged);
+    }
+    hooks
+  }
+
+  fn playlists_changed(&mut self, playlists: &[SimplifiedPlaylist]) -> Vec<Playlist> {
+    let snapshots = playlists
+      .iter()
+      .map(|playlist| (playlist.id.id().to_string(), playlist.snapshot_id.clone()))
+      .collect();
+
+    // the first fetch is no modification
+    let Some(previous) = self.playlists.replace(snapshots) else {
+      return vec![];
+    };
+
+    playlists
+      .iter()
+      .filter(|playlist| {
+        previous
+          .get(playlist.id.id())
+          .is_some_and(|snapshot| *snapshot != playlist.snapshot_id)
+      })
+      .map(Playlist::from)
+      .collect()
+  }
+}
+
+/// Starts running the hooks of `config` on the changes of `state`.
+pub(crate) fn spawn(config: HooksConfig, state: &Arc<Mutex<State>>) -> JoinHandle<()> {
+  let state = state.clone();
+
+  tokio::spawn(async move {
+    let mut changes = state.lock().await.subscribe();
+    let mut watcher = Watcher::new(&*state.lock().await);
+
+    loop {
+      let change = match changes.recv().await {
+        Ok(change) => change,
+        Err(RecvError::Lagged(_)) => Change::Playback,
+        Err(RecvError::Closed) => return,
+      };
+
+      let due = watcher.update(change, &*state.lock().await);
+      for (hook, playlist) in due {
+        run(&config, hook, &watcher.playback, playlist.as_ref());
+      }
+    }
+  })
+}
+
+fn run(config: &HooksConfig, hook: Hook, playback: &NowPlaying, playlist: Option<&Playlist>) {
+  let Some(command) = hook.command(config) else {
+    return;
+  };
+
+  let mut env = environment(hook, playback);
+  if let Some(playlist) = playlist {
+    env.extend([
+      ("ORPHEUS_PLAYLIST_ID", playlist.id.clone()),
+      ("ORPHEUS_PLAYLIST_NAME", playlist.name.clone()),
+      ("ORPHEUS_PLAYLIST_URI", playlist.uri.clone()),
+    ]);
+  }
+
+  let payload = Payload {
+    event: hook.name(),
+    playback,
+    playlist,
+  };
+  let payload = serde_json::to_string(&payload).unwrap_or_default();
+
+  tracing::info!("running the {} hook", hook.name());
+  tokio::spawn(execute(command.to_string(), env, payload, config.timeout));
+}
+
+/// A command run by the user's shell.
+pub(crate) fn shell(command: &str) -> Command {
+  let (shell, flag) = match cfg!(windows) {
+    true => ("cmd", "/C"),
+    false => ("sh", "-c"),
+  };
+
+  let mut shell = Command::new(shell);
+  shell.args([flag, command]);
+  shell
+}
+
+fn environment(hook: Hook, playback: &NowPlaying) -> Vec<(&'static str, String)> {
+  let text = |value: &Option<String>| value.clone().unwrap_or_default();
+
+  vec![
+    ("ORPHEUS_EVENT", hook.name().to_string()),
+    ("ORPHEUS_STATUS", playback.status.to_string()),
+    ("ORPHEUS_TITLE", text(&playback.title)),
+    ("ORPHEUS_ARTIST", text(&playback.artist)),
+    ("ORPHEUS_ALBUM", text(&playback.album)),
+    ("ORPHEUS_URI", text(&playback.uri)),
+    ("ORPHEUS_PROGRESS_MS", playback.progress_ms.to_string()),
+    ("ORPHEUS_DURATION_MS", playback.duration_ms.to_string()),
+    ("ORPHEUS_DEVICE", text(&playback.device)),
+  ]
+}
+
+async fn execute(
+  command: String,
+  env: Vec<(&'static str, String)>,
+  payload: String,
+  timeout: Duration,
+) {
+  let run = async {
+    let mut child = shell(&command)
+      .envs(env)
+      .stdin(Stdio::piped())
+      // its output would draw over the TUI
+      .stdout(Stdio::null())
+      .stderr(Stdio::piped())
+      .kill_on_drop(true)
+      .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+      // hooks are free not to read it
+      let _ = stdin.write_all(payload.as_bytes()).await;
+    }
+
+    child.wait_with_output().await
+  };
+
+  match tokio::time::timeout(timeout, run).await {
+    Ok(Ok(output)) if output.status.success() => {}
+    Ok(Ok(output)) => tracing::warn!(
+      "hook `{command}` failed with {}: {}",
+      output.status,
+      String::from_utf8_lossy(&output.stderr).trim()
+    ),
+    Ok(Err(err)) => tracing::warn!("failed to run hook `{command}`: {err}"),
+    Err(_) => tracing::warn!("hook `{command}` was killed after {timeout:?}"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{auth::profile::Profile, config::Config, io::backend::fake};
+  use rspotify::model::Page;
+
+  fn state() -> State {
+    let (sender, _events) = std::sync::mpsc::channel();
+    State::new(Config::default(), Profile::rooted("unused"), sender)
+  }
+
+  /// The hooks due once `state` plays `track`.
+  fn play(watcher: &mut Watcher, state: &mut State, track: &str, is_playing: bool) -> Vec<Hook> {
+    let playback = fake::playback(fake::track(track, track), is_playing);
+    set_playback(watcher, state, playback)
+  }
+
+  fn set_playback(
+    watcher: &mut Watcher,
+    state: &mut State,
+    playback: rspotify::model::CurrentPlaybackContext,
+  ) -> Vec<Hook> {
+    state.set_playback(Some(playback));
+    let due = watcher.update(Change::Playback, state);
+    due.into_iter().map(|(hook, _)| hook).collect()
+  }
+
+  /// The playlists due once `state` has `playlists`, by id.
+  fn fetch(watcher: &mut Watcher, state: &mut State, playlists: &[(&str, &str)]) -> Vec<String> {
+    let items = playlists
+      .iter()
+      .map(|(id, snapshot_id)| fake::playlist(id, snapshot_id))
+      .collect::<Vec<_>>();
+    state.playlists = Some(Page {
+      href: String::new(),
+      limit: 50,
+      next: None,
+      offset: 0,
+      previous: None,
+      total: items.len() as u32,
+      items,
+    });
+
+    let due = watcher.update(Change::Playlists, state);
+    due
+      .into_iter()
+      .map(|(hook, playlist)| {
+        assert_eq!(hook, Hook::PlaylistModified);
+        playlist.unwrap().id
+      })
+      .collect()
+  }
+
+  #[test]
+  fn track_changes_pauses_and_resumes_are_due() {
+    let mut state = state();
+    let mut watcher = Watcher::new(&state);
+
+    assert_eq!(
+      play(&mut watcher, &mut state, "first", true),
+      [Hook::TrackChanged, Hook::Resumed]
+    );
+    assert_eq!(play(&mut watcher, &mut state, "first", true), []);
+    assert_eq!(
+      play(&mut watcher, &mut state, "first", false),
+      [Hook::Paused]
+    );
+    assert_eq!(
+      play(&mut watcher, &mut state, "first", true),
+      [Hook::Resumed]
+    );
+    assert_eq!(
+      play(&mut watcher, &mut state, "second", true),
+      [Hook::TrackChanged]
+    );
+  }
+
+  #[test]
+  fn only_moves_between_devices_are_due() {
+    let mut state = state();
+    let mut watcher = Watcher::new(&state);
+    // from no device at all
+    assert!(!play(&mut watcher, &mut state, "first", true).contains(&Hook::DeviceChanged));
+
+    let mut playback = fake::playback(fake::track("first", "first"), true);
+    playback.device.name = "Phone".to_string();
+    assert_eq!(
+      set_playback(&mut watcher, &mut state, playback),
+      [Hook::DeviceChanged]
+    );
+  }
+
+  #[test]
+  fn modified_playlists_are_due_once_first_fetched() {
+    let mut state = state();
+    let mut watcher = Watcher::new(&state);
+
+    assert!(fetch(&mut watcher, &mut state, &[("focus", "1"), ("gym", "1")]).is_empty());
+    assert!(fetch(&mut watcher, &mut state, &[("focus", "1"), ("gym", "1")]).is_empty());
+
+    let due = fetch(
+      &mut watcher,
+      &mut state,
+      &[("focus", "1"), ("gym", "2"), ("new", "1")],
+    );
+    assert_eq!(due, ["gym"]);
+  }
+
+  #[test]
+  fn profile_switches_start_over() {
+    let mut state = state();
+    let mut watcher = Watcher::new(&state);
+    play(&mut watcher, &mut state, "first", true);
+    fetch(&mut watcher, &mut state, &[("focus", "1")]);
+
+    assert!(watcher.update(Change::Profile, &state).is_empty());
+
+    // the other account's playlists and playback are first seen
+    assert!(fetch(&mut watcher, &mut state, &[("focus", "2")]).is_empty());
+    assert_eq!(
+      play(&mut watcher, &mut state, "first", true),
+      [Hook::TrackChanged, Hook::Resumed]
+    );
+  }
+
+  #[test]
+  fn seeks_are_no_hook() {
+    let mut state = state();
+    let mut watcher = Watcher::new(&state);
+    play(&mut watcher, &mut state, "first", true);
+
+    assert!(watcher.update(Change::Seeked, &state).is_empty());
+  }
+}
@@ -0,0 +1,54 @@
+//! Images of Spotify, like the album art, downloaded in the
+//! [cache](crate::auth::profile::Profile::cache_dir).
+
+use rspotify::model::Image;
+#[cfg(any(
+  all(target_os = "linux", feature = "notifications"),
+  feature = "album-art"
+))]
+use std::path::{Path, PathBuf};
+
+/// Width of the cover picked among the sizes Spotify has.
+const COVER_WIDTH: u32 = 300;
+
+/// URL of the image closest to [COVER_WIDTH], big enough for a notification
+/// or a header but not more.
+pub(crate) fn cover(images: &[Image]) -> Option<String> {
+  images
+    .iter()
+    .min_by_key(|image| image.width.unwrap_or(0).abs_diff(COVER_WIDTH))
+    .map(|image| image.url.clone())
+}
+
+/// Downloads the image at `url` in `dir`, unless it's already there.
+#[cfg(any(
+  all(target_os = "linux", feature = "notifications"),
+  feature = "album-art"
+))]
+pub(crate) async fn download(dir: &Path, url: &str) -> Option<PathBuf> {
+  // Spotify's image URLs end with an id
+  let name = url.rsplit('/').next().filter(|name| !name.is_empty())?;
+  let path = dir.join(name);
+  if path.exists() {
+    return Some(path);
+  }
+
+  let fetch = async {
+    let bytes = reqwest::get(url).await?.error_for_status()?.bytes().await?;
+
+    // renamed once complete, so an interrupted download isn't taken for a cover
+    let partial = path.with_extension("part");
+    tokio::fs::create_dir_all(dir).await?;
+    tokio::fs::write(&partial, bytes).await?;
+    tokio::fs::rename(&partial, &path).await?;
+    Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+  };
+
+  match fetch.await {
+    Ok(_) => Some(path),
+    Err(err) => {
+      tracing::warn!("failed to download the cover {url}: {err}");
+      None
+    }
+  }
+}
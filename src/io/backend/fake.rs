@@ -0,0 +1,370 @@
+//! Backend keeping everything in memory, seeded with fixtures.
+//!
+//! Its clones share the same [fixtures](Fixtures), which the tests change or
+//! inspect while the [io](crate::io::Io) uses it. The functions at the end
+//! build the models it serves, as Spotify would send them.
+
+use crate::{
+  auth::{AuthError, callback::CallbackError, profile::Profile, session::Session},
+  io::{IoError, backend::Backend, target::PlayTarget},
+  state::State,
+};
+use chrono::Utc;
+use reqwest::ResponseBuilderExt;
+use rspotify::{
+  ClientError, ClientResult,
+  http::HttpError,
+  model::{
+    ArtistId, CurrentPlaybackContext, FullTrack, Page, PlayableId, PlayableItem, PlaylistId,
+    PlaylistItem, PrivateUser, RepeatState, SimplifiedPlaylist, TrackId,
+  },
+  prelude::Id,
+};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::{
+  collections::{HashMap, VecDeque},
+  future::Future,
+  sync::{Arc, Mutex, MutexGuard},
+};
+use tokio::task::JoinHandle;
+
+/// Items in a page of a playlist, as with Spotify.
+const PAGE_SIZE: usize = 100;
+
+/// What the [fake backend](Fake) serves and what was changed through it.
+#[derive(Debug, Default)]
+pub(crate) struct Fixtures {
+  /// The signed in user, `None` once signed out.
+  pub user: Option<PrivateUser>,
+  pub playlists: Vec<SimplifiedPlaylist>,
+  /// Items of the playlists, by playlist id.
+  pub playlist_items: HashMap<String, Vec<PlaylistItem>>,
+  /// Items added to the playlists, by playlist id.
+  pub added_items: HashMap<String, Vec<PlayableId<'static>>>,
+  pub saved_tracks: Vec<TrackId<'static>>,
+  pub followed_artists: Vec<ArtistId<'static>>,
+  pub playback: Option<CurrentPlaybackContext>,
+  /// What was played, `None` when the playback was resumed.
+  pub played: Vec<Option<PlayTarget>>,
+  pub queue: Vec<PlayableId<'static>>,
+  /// Fails every request as if the source couldn't be reached.
+  pub is_offline: bool,
+  /// Errors the next requests fail with, one each, before the others are served.
+  pub errors: VecDeque<ClientError>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Fake {
+  fixtures: Arc<Mutex<Fixtures>>,
+}
+
+impl Fake {
+  pub fn new(fixtures: Fixtures) -> Self {
+    Self {
+      fixtures: Arc::new(Mutex::new(fixtures)),
+    }
+  }
+
+  pub fn fixtures(&self) -> MutexGuard<'_, Fixtures> {
+    self.fixtures.lock().unwrap()
+  }
+
+  /// The fixtures, unless they're offline or the request is to fail.
+  fn reach(&self) -> ClientResult<MutexGuard<'_, Fixtures>> {
+    let mut fixtures = self.fixtures();
+    if let Some(err) = fixtures.errors.pop_front() {
+      return Err(err);
+    }
+    match fixtures.is_offline {
+      // mapped to a network error by the io
+      true => Err(ClientError::Io(std::io::Error::new(
+        std::io::ErrorKind::NotConnected,
+        "the fake backend is offline",
+      ))),
+      false => Ok(fixtures),
+    }
+  }
+
+  /// Changes the playback, if there's one.
+  fn update_playback(&self, update: impl FnOnce(&mut CurrentPlaybackContext)) -> ClientResult<()> {
+    if let Some(playback) = &mut self.reach()?.playback {
+      update(playback);
+    }
+    Ok(())
+  }
+}
+
+impl Backend for Fake {
+  async fn switch(&self, _profile: &Profile) -> Result<Self, AuthError> {
+    Ok(self.clone())
+  }
+
+  fn keep_alive(&self, _state: Arc<tokio::sync::Mutex<State>>) -> JoinHandle<()> {
+    tokio::spawn(async {})
+  }
+
+  async fn refresh(&self) -> Result<(), IoError> {
+    match self.fixtures().user {
+      Some(_) => Ok(()),
+      None => Err(IoError::Unauthorized),
+    }
+  }
+
+  fn authorize_url(&mut self) -> ClientResult<String> {
+    Ok("https://accounts.example.com/authorize".to_string())
+  }
+
+  fn callback(&self) -> impl Future<Output = Result<String, CallbackError>> + Send + 'static {
+    // never captured, the code has to be entered
+    std::future::pending()
+  }
+
+  fn parse_code(&self, input: &str) -> Option<String> {
+    let input = input.trim();
+    (!input.is_empty()).then(|| input.to_string())
+  }
+
+  async fn sign_in(&self, _code: &str) -> ClientResult<()> {
+    Ok(())
+  }
+
+  async fn sign_out(&self) {
+    self.fixtures().user = None;
+  }
+
+  async fn session(&self, user: PrivateUser) -> Option<Session> {
+    Some(Session {
+      user_id: user.id.id().to_string(),
+      display_name: user.display_name,
+      client_id: "fake".to_string(),
+      scopes: vec![],
+      expires_at: None,
+    })
+  }
+
+  async fn user(&self) -> ClientResult<PrivateUser> {
+    self.reach()?.user.clone().ok_or(ClientError::InvalidToken)
+  }
+
+  async fn playlists(&self, limit: u32, offset: u32) -> ClientResult<Page<SimplifiedPlaylist>> {
+    Ok(page(&self.reach()?.playlists, limit as usize, offset))
+  }
+
+  async fn playlist_items(
+    &self,
+    id: PlaylistId<'_>,
+    offset: u32,
+  ) -> ClientResult<Page<PlaylistItem>> {
+    let fixtures = self.reach()?;
+    let items = fixtures.playlist_items.get(id.id()).map(Vec::as_slice);
+    Ok(page(items.unwrap_or_default(), PAGE_SIZE, offset))
+  }
+
+  async fn add_to_playlist(
+    &self,
+    playlist: PlaylistId<'_>,
+    items: &[PlayableId<'_>],
+  ) -> ClientResult<()> {
+    let mut fixtures = self.reach()?;
+    let added = fixtures
+      .added_items
+      .entry(playlist.id().to_string())
+      .or_default();
+    added.extend(items.iter().map(|item| item.clone_static()));
+    let count = added.len();
+
+    // like with Spotify, every change makes a new snapshot
+    if let Some(playlist) = fixtures.playlists.iter_mut().find(|p| p.id == playlist) {
+      playlist.snapshot_id = format!("{}+{count}", playlist.snapshot_id);
+    }
+    Ok(())
+  }
+
+  async fn save_track(&self, track: TrackId<'_>) -> ClientResult<()> {
+    self.reach()?.saved_tracks.push(track.into_static());
+    Ok(())
+  }
+
+  async fn follow_artist(&self, artist: ArtistId<'_>) -> ClientResult<()> {
+    self.reach()?.followed_artists.push(artist.into_static());
+    Ok(())
+  }
+
+  async fn playback(&self) -> ClientResult<Option<CurrentPlaybackContext>> {
+    Ok(self.reach()?.playback.clone())
+  }
+
+  async fn seek(&self, position: chrono::Duration) -> ClientResult<()> {
+    self.update_playback(|playback| playback.progress = Some(position))
+  }
+
+  async fn next(&self) -> ClientResult<()> {
+    self.update_playback(|playback| playback.progress = Some(chrono::Duration::zero()))
+  }
+
+  async fn previous(&self) -> ClientResult<()> {
+    self.update_playback(|playback| playback.progress = Some(chrono::Duration::zero()))
+  }
+
+  async fn play(&self, target: Option<&PlayTarget>) -> ClientResult<()> {
+    self.reach()?.played.push(target.cloned());
+    self.update_playback(|playback| playback.is_playing = true)
+  }
+
+  async fn pause(&self) -> ClientResult<()> {
+    self.update_playback(|playback| playback.is_playing = false)
+  }
+
+  async fn queue(&self, id: PlayableId<'_>) -> ClientResult<()> {
+    self.reach()?.queue.push(id.clone_static());
+    Ok(())
+  }
+
+  async fn set_volume(&self, level: u8) -> ClientResult<()> {
+    self.update_playback(|playback| playback.device.volume_percent = Some(level.into()))
+  }
+
+  async fn set_shuffle(&self, shuffle: bool) -> ClientResult<()> {
+    self.update_playback(|playback| playback.shuffle_state = shuffle)
+  }
+
+  async fn set_repeat(&self, repeat: RepeatState) -> ClientResult<()> {
+    self.update_playback(|playback| playback.repeat_state = repeat)
+  }
+}
+
+/// The page of `items` at `offset`.
+fn page<T: Clone + DeserializeOwned>(items: &[T], limit: usize, offset: u32) -> Page<T> {
+  let start = (offset as usize).min(items.len());
+  let end = (start + limit).min(items.len());
+
+  Page {
+    href: "fake".to_string(),
+    items: items[start..end].to_vec(),
+    limit: limit as u32,
+    next: (end < items.len()).then(|| "fake".to_string()),
+    offset,
+    previous: (start > 0).then(|| "fake".to_string()),
+    total: items.len() as u32,
+  }
+}
+
+fn from_json<T: DeserializeOwned>(value: serde_json::Value) -> T {
+  serde_json::from_value(value).expect("fixtures match Spotify's models")
+}
+
+pub fn user(id: &str) -> PrivateUser {
+  from_json(json!({
+    "display_name": id,
+    "external_urls": {},
+    "href": format!("https://api.spotify.com/v1/users/{id}"),
+    "id": id,
+  }))
+}
+
+pub fn playlist(id: &str, snapshot_id: &str) -> SimplifiedPlaylist {
+  from_json(json!({
+    "collaborative": false,
+    "external_urls": {},
+    "href": format!("https://api.spotify.com/v1/playlists/{id}"),
+    "id": id,
+    "images": [],
+    "name": format!("Playlist {id}"),
+    "owner": {
+      "external_urls": {},
+      "href": "https://api.spotify.com/v1/users/fake",
+      "id": "fake",
+    },
+    "public": true,
+    "snapshot_id": snapshot_id,
+    "tracks": { "href": "", "total": 0 },
+  }))
+}
+
+pub fn track(id: &str, name: &str) -> FullTrack {
+  from_json(json!({
+    "album": {
+      "album_type": "album",
+      "artists": [],
+      "external_urls": {},
+      "href": null,
+      "id": null,
+      "images": [{ "height": 640, "url": format!("https://i.scdn.co/image/{id}"), "width": 640 }],
+      "name": "Album",
+    },
+    "artists": [{ "external_urls": {}, "href": null, "id": null, "name": "Artist" }],
+    "disc_number": 1,
+    "duration_ms": 180_000,
+    "explicit": false,
+    "external_ids": {},
+    "external_urls": {},
+    "href": null,
+    "id": id,
+    "is_local": false,
+    "name": name,
+    "popularity": 50,
+    "preview_url": null,
+    "track_number": 1,
+    "type": "track",
+  }))
+}
+
+pub fn playlist_item(track: FullTrack) -> PlaylistItem {
+  PlaylistItem {
+    added_at: None,
+    added_by: None,
+    is_local: false,
+    track: Some(PlayableItem::Track(track)),
+  }
+}
+
+/// The error of a request `host` answered with `status`, waiting for the
+/// `retry_after` seconds if any.
+pub fn status_error(host: &str, status: u16, retry_after: Option<&str>) -> ClientError {
+  let mut response = http::Response::builder()
+    .status(status)
+    .url(format!("https://{host}/").parse().unwrap());
+  if let Some(seconds) = retry_after {
+    response = response.header("Retry-After", seconds);
+  }
+
+  let response = reqwest::Response::from(response.body("").unwrap());
+  ClientError::Http(Box::new(HttpError::StatusCode(response)))
+}
+
+/// The error of a request that didn't reach its host.
+pub fn network_error() -> ClientError {
+  // the request can't even be built, which fails like the connection would
+  let err = reqwest::Client::new()
+    .get("unreachable")
+    .build()
+    .unwrap_err();
+  ClientError::Http(Box::new(HttpError::Client(err)))
+}
+
+/// The playback of `track`, 1 minute in.
+pub fn playback(track: FullTrack, is_playing: bool) -> CurrentPlaybackContext {
+  let mut playback: CurrentPlaybackContext = from_json(json!({
+    "device": {
+      "id": "device",
+      "is_active": true,
+      "is_private_session": false,
+      "is_restricted": false,
+      "name": "Speaker",
+      "type": "Speaker",
+      "volume_percent": 50,
+    },
+    "repeat_state": "off",
+    "shuffle_state": false,
+    "context": null,
+    "timestamp": Utc::now().timestamp_millis(),
+    "progress_ms": 60_000,
+    "is_playing": is_playing,
+    "item": null,
+    "currently_playing_type": "track",
+    "actions": { "disallows": {} },
+  }));
+  playback.item = Some(PlayableItem::Track(track));
+  playback
+}
@@ -0,0 +1,102 @@
+//! Source of the library and the playback behind the [IO manager](super::Io).
+//!
+//! Orpheus runs with [Spotify's Web API](self::spotify), through rspotify. The
+//! [fake](self::fake) backend serves fixtures from memory instead, so that the
+//! [io](super::Io) and the [state](crate::state::State) can be exercised without
+//! network. Other sources implement the trait to be plugged in.
+//!
+//! Requests fail with rspotify's errors, whatever the backend, so that they go
+//! through the same [retry policy](super::retry).
+
+#[cfg(test)]
+pub(crate) mod fake;
+mod spotify;
+
+use crate::{
+  auth::{AuthError, callback::CallbackError, profile::Profile, session::Session},
+  io::{IoError, target::PlayTarget},
+  state::State,
+};
+use rspotify::{
+  ClientResult,
+  model::{
+    ArtistId, CurrentPlaybackContext, Page, PlayableId, PlaylistId, PlaylistItem, PrivateUser,
+    RepeatState, SimplifiedPlaylist, TrackId,
+  },
+};
+use std::{future::Future, sync::Arc};
+use tokio::{sync::Mutex, task::JoinHandle};
+
+pub(crate) trait Backend: Sized {
+  /// The backend of another `profile`, to switch to it.
+  async fn switch(&self, profile: &Profile) -> Result<Self, AuthError>;
+
+  /// Keeps the session alive in the background, asking the user to authorize
+  /// orpheus again through the `state` once it can't be.
+  fn keep_alive(&self, state: Arc<Mutex<State>>) -> JoinHandle<()>;
+
+  /// Refreshes the session after a request was rejected, failing with
+  /// [`IoError::Unauthorized`] if the user has to authorize orpheus again.
+  async fn refresh(&self) -> Result<(), IoError>;
+
+  /// URL where the user authorizes orpheus.
+  fn authorize_url(&mut self) -> ClientResult<String>;
+
+  /// Waits for the authorization code, once the user authorized orpheus.
+  fn callback(&self) -> impl Future<Output = Result<String, CallbackError>> + Send + 'static;
+
+  /// The authorization code in the URL the user was redirected to, or the code itself.
+  fn parse_code(&self, input: &str) -> Option<String>;
+
+  /// Signs in with the authorization `code`.
+  async fn sign_in(&self, code: &str) -> ClientResult<()>;
+
+  /// Forgets the session, so that nothing can be requested until signing in again.
+  async fn sign_out(&self);
+
+  /// The session the `user` is signed in with.
+  async fn session(&self, user: PrivateUser) -> Option<Session>;
+
+  async fn user(&self) -> ClientResult<PrivateUser>;
+
+  async fn playlists(&self, limit: u32, offset: u32) -> ClientResult<Page<SimplifiedPlaylist>>;
+
+  async fn playlist_items(
+    &self,
+    id: PlaylistId<'_>,
+    offset: u32,
+  ) -> ClientResult<Page<PlaylistItem>>;
+
+  async fn add_to_playlist(
+    &self,
+    playlist: PlaylistId<'_>,
+    items: &[PlayableId<'_>],
+  ) -> ClientResult<()>;
+
+  async fn save_track(&self, track: TrackId<'_>) -> ClientResult<()>;
+
+  async fn follow_artist(&self, artist: ArtistId<'_>) -> ClientResult<()>;
+
+  async fn playback(&self) -> ClientResult<Option<CurrentPlaybackContext>>;
+
+  async fn seek(&self, position: chrono::Duration) -> ClientResult<()>;
+
+  async fn next(&self) -> ClientResult<()>;
+
+  async fn previous(&self) -> ClientResult<()>;
+
+  /// Resumes the playback, or plays the `target`.
+  async fn play(&self, target: Option<&PlayTarget>) -> ClientResult<()>;
+
+  async fn pause(&self) -> ClientResult<()>;
+
+  /// Adds the item to the end of the playback queue.
+  async fn queue(&self, id: PlayableId<'_>) -> ClientResult<()>;
+
+  /// Sets the volume, from 0 to 100.
+  async fn set_volume(&self, level: u8) -> ClientResult<()>;
+
+  async fn set_shuffle(&self, shuffle: bool) -> ClientResult<()>;
+
+  async fn set_repeat(&self, repeat: RepeatState) -> ClientResult<()>;
+}
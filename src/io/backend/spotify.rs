@@ -0,0 +1,156 @@
+//! Spotify's Web API, the backend orpheus runs with.
+
+use crate::{
+  auth::{self, AuthError, callback, callback::CallbackError, profile::Profile, session::Session},
+  io::{IoError, backend::Backend, refresh, target::PlayTarget},
+  state::State,
+};
+use rspotify::{
+  AuthCodePkceSpotify as Spotify, ClientResult,
+  model::{
+    AdditionalType, ArtistId, CurrentPlaybackContext, Page, PlayableId, PlaylistId, PlaylistItem,
+    PrivateUser, RepeatState, SimplifiedPlaylist, TrackId,
+  },
+  prelude::{BaseClient, OAuthClient},
+};
+use std::{future::Future, sync::Arc};
+use tokio::{sync::Mutex, task::JoinHandle};
+
+impl Backend for Spotify {
+  async fn switch(&self, profile: &Profile) -> Result<Self, AuthError> {
+    auth::restore(profile).await
+  }
+
+  fn keep_alive(&self, state: Arc<Mutex<State>>) -> JoinHandle<()> {
+    refresh::spawn(self.clone(), state)
+  }
+
+  async fn refresh(&self) -> Result<(), IoError> {
+    let has_refresh_token = self
+      .token
+      .lock()
+      .await
+      .unwrap()
+      .as_ref()
+      .is_some_and(|token| token.refresh_token.is_some());
+
+    match has_refresh_token {
+      true => Ok(self.refresh_token().await?),
+      false => Err(IoError::Unauthorized),
+    }
+  }
+
+  fn authorize_url(&mut self) -> ClientResult<String> {
+    self.get_authorize_url(None)
+  }
+
+  fn callback(&self) -> impl Future<Output = Result<String, CallbackError>> + Send + 'static {
+    let redirect_uri = self.oauth.redirect_uri.clone();
+    let oauth_state = self.oauth.state.clone();
+
+    async move { callback::listen(&redirect_uri, &oauth_state).await }
+  }
+
+  fn parse_code(&self, input: &str) -> Option<String> {
+    auth::parse_code(self, input)
+  }
+
+  async fn sign_in(&self, code: &str) -> ClientResult<()> {
+    self.request_token(code).await
+  }
+
+  async fn sign_out(&self) {
+    *self.token.lock().await.unwrap() = None;
+  }
+
+  async fn session(&self, user: PrivateUser) -> Option<Session> {
+    Session::of(self, user).await
+  }
+
+  async fn user(&self) -> ClientResult<PrivateUser> {
+    self.current_user().await
+  }
+
+  async fn playlists(&self, limit: u32, offset: u32) -> ClientResult<Page<SimplifiedPlaylist>> {
+    self
+      .current_user_playlists_manual(Some(limit), Some(offset))
+      .await
+  }
+
+  async fn playlist_items(
+    &self,
+    id: PlaylistId<'_>,
+    offset: u32,
+  ) -> ClientResult<Page<PlaylistItem>> {
+    self
+      .playlist_items_manual(id, None, None, None, Some(offset))
+      .await
+  }
+
+  async fn add_to_playlist(
+    &self,
+    playlist: PlaylistId<'_>,
+    items: &[PlayableId<'_>],
+  ) -> ClientResult<()> {
+    self
+      .playlist_add_items(playlist, items.iter().map(PlayableId::as_ref), None)
+      .await
+      .map(|_| ())
+  }
+
+  async fn save_track(&self, track: TrackId<'_>) -> ClientResult<()> {
+    self.current_user_saved_tracks_add([track]).await
+  }
+
+  async fn follow_artist(&self, artist: ArtistId<'_>) -> ClientResult<()> {
+    self.user_follow_artists([artist]).await
+  }
+
+  async fn playback(&self) -> ClientResult<Option<CurrentPlaybackContext>> {
+    self
+      .current_playback(
+        None,
+        Some(vec![&AdditionalType::Episode, &AdditionalType::Track]),
+      )
+      .await
+  }
+
+  async fn seek(&self, position: chrono::Duration) -> ClientResult<()> {
+    self.seek_track(position, None).await
+  }
+
+  async fn next(&self) -> ClientResult<()> {
+    self.next_track(None).await
+  }
+
+  async fn previous(&self) -> ClientResult<()> {
+    self.previous_track(None).await
+  }
+
+  async fn play(&self, target: Option<&PlayTarget>) -> ClientResult<()> {
+    match target {
+      Some(target) => target.play(self).await,
+      None => self.resume_playback(None, None).await,
+    }
+  }
+
+  async fn pause(&self) -> ClientResult<()> {
+    self.pause_playback(None).await
+  }
+
+  async fn queue(&self, id: PlayableId<'_>) -> ClientResult<()> {
+    self.add_item_to_queue(id, None).await
+  }
+
+  async fn set_volume(&self, level: u8) -> ClientResult<()> {
+    self.volume(level, None).await
+  }
+
+  async fn set_shuffle(&self, shuffle: bool) -> ClientResult<()> {
+    self.shuffle(shuffle, None).await
+  }
+
+  async fn set_repeat(&self, repeat: RepeatState) -> ClientResult<()> {
+    self.repeat(repeat, None).await
+  }
+}
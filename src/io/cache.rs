@@ -0,0 +1,161 @@
+//! On-disk cache of the library, so that it shows right away at startup.
+//!
+//! The user's playlists and the pages of their items are kept as JSON in the
+//! `library` folder of the profile's [cache](crate::auth::profile::Profile::cache_dir):
+//!
+//! ```text
+//! library/playlists.json
+//! library/playlists/<id>/snapshot
+//! library/playlists/<id>/<offset>.json
+//! ```
+//!
+//! The items of a playlist are only valid for the
+//! [snapshot](rspotify::model::SimplifiedPlaylist::snapshot_id) they were
+//! fetched at, which changes with every modification of the playlist.
+
+use crate::auth::profile::Profile;
+use rspotify::{
+  model::{Page, PlaylistItem, SimplifiedPlaylist},
+  prelude::Id,
+};
+use serde::{Serialize, de::DeserializeOwned};
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+};
+
+pub(crate) struct Cache {
+  /// `None` when there's no cache directory, so nothing is cached.
+  dir: Option<PathBuf>,
+}
+
+impl Cache {
+  pub fn new(profile: &Profile) -> Self {
+    Self {
+      dir: profile.cache_dir().ok().map(|dir| dir.join("library")),
+    }
+  }
+
+  pub async fn playlists(&self) -> Option<Page<SimplifiedPlaylist>> {
+    read(&self.dir.as_ref()?.join("playlists.json")).await
+  }
+
+  /// Stores the user's `playlists`, dropping the items of those that changed or are gone.
+  pub async fn store_playlists(&self, playlists: &Page<SimplifiedPlaylist>) {
+    let Some(dir) = &self.dir else {
+      return;
+    };
+    write(&dir.join("playlists.json"), playlists).await;
+
+    let snapshots: HashMap<String, &str> = playlists
+      .items
+      .iter()
+      .map(|playlist| (playlist.id.id().to_string(), playlist.snapshot_id.as_str()))
+      .collect();
+
+    let Ok(mut entries) = tokio::fs::read_dir(dir.join("playlists")).await else {
+      return;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+      let id = entry.file_name().to_string_lossy().to_string();
+      let snapshot = tokio::fs::read_to_string(entry.path().join("snapshot")).await;
+
+      let is_valid = match (snapshots.get(&id), snapshot) {
+        (Some(current), Ok(snapshot)) => *current == snapshot,
+        _ => false,
+      };
+      if !is_valid {
+        remove(&entry.path()).await;
+      }
+    }
+  }
+
+  /// The page of the items of playlist `id` at `offset`, if it was stored at `snapshot_id`.
+  pub async fn playlist_items(
+    &self,
+    id: &str,
+    snapshot_id: &str,
+    offset: u32,
+  ) -> Option<Page<PlaylistItem>> {
+    let dir = self.playlist_dir(id)?;
+    let snapshot = tokio::fs::read_to_string(dir.join("snapshot")).await.ok()?;
+    if snapshot != snapshot_id {
+      return None;
+    }
+
+    read(&dir.join(format!("{offset}.json"))).await
+  }
+
+  /// Stores the page of the items of playlist `id` at `offset`, fetched at `snapshot_id`.
+  pub async fn store_playlist_items(
+    &self,
+    id: &str,
+    snapshot_id: &str,
+    offset: u32,
+    items: &Page<PlaylistItem>,
+  ) {
+    let Some(dir) = self.playlist_dir(id) else {
+      return;
+    };
+
+    // the pages of another snapshot would mix two versions of the playlist
+    let snapshot = dir.join("snapshot");
+    if tokio::fs::read_to_string(&snapshot).await.ok().as_deref() != Some(snapshot_id) {
+      remove(&dir).await;
+      if let Err(err) = write_atomic(&snapshot, snapshot_id.as_bytes()).await {
+        tracing::warn!("failed to cache {}: {err}", snapshot.display());
+      }
+    }
+
+    write(&dir.join(format!("{offset}.json")), items).await;
+  }
+
+  fn playlist_dir(&self, id: &str) -> Option<PathBuf> {
+    // ids are base62, anything else would escape the cache
+    let is_valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric());
+    is_valid.then(|| self.dir.as_ref().map(|dir| dir.join("playlists").join(id)))?
+  }
+}
+
+async fn read<T: DeserializeOwned>(path: &Path) -> Option<T> {
+  let text = tokio::fs::read_to_string(path).await.ok()?;
+
+  match serde_json::from_str(&text) {
+    Ok(value) => Some(value),
+    Err(err) => {
+      tracing::warn!("ignoring the cache {}: {err}", path.display());
+      None
+    }
+  }
+}
+
+async fn write<T: Serialize>(path: &Path, value: &T) {
+  let result = match serde_json::to_vec(value) {
+    Ok(json) => write_atomic(path, &json).await,
+    Err(err) => Err(err.into()),
+  };
+
+  if let Err(err) = result {
+    tracing::warn!("failed to cache {}: {err}", path.display());
+  }
+}
+
+/// Writes `path` through a temporary file, so that it's never read half written.
+pub(super) async fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+  let mut temporary = path.as_os_str().to_owned();
+  temporary.push(".tmp");
+
+  if let Some(parent) = path.parent() {
+    tokio::fs::create_dir_all(parent).await?;
+  }
+  tokio::fs::write(&temporary, contents).await?;
+  tokio::fs::rename(&temporary, path).await
+}
+
+async fn remove(dir: &Path) {
+  if let Err(err) = tokio::fs::remove_dir_all(dir).await
+    && err.kind() != std::io::ErrorKind::NotFound
+  {
+    tracing::warn!("failed to clear the cache {}: {err}", dir.display());
+  }
+}
@@ -15,6 +15,7 @@ pub(crate) enum Key {
   Esc,
   Enter,
   Backspace,
+  Tab,
 
   Left,
   Up,
@@ -26,6 +27,29 @@ pub(crate) enum Key {
   Unmapped,
 }
 
+impl Key {
+  /// Parses a key name like `q`, `enter` or `up`, as bound by [scripts](crate::scripts).
+  pub fn parse(name: &str) -> Option<Self> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+      return Some(Self::Char(c));
+    }
+
+    match name.to_lowercase().as_str() {
+      "esc" => Some(Self::Esc),
+      "enter" => Some(Self::Enter),
+      "backspace" => Some(Self::Backspace),
+      "tab" => Some(Self::Tab),
+      "left" => Some(Self::Left),
+      "up" => Some(Self::Up),
+      "right" => Some(Self::Right),
+      "down" => Some(Self::Down),
+      "space" => Some(Self::Char(' ')),
+      _ => None,
+    }
+  }
+}
+
 pub(crate) enum Event<I> {
   Input(I),
   Tick,
@@ -45,11 +69,11 @@ impl EventHandler {
 
     std::thread::spawn(move || {
       loop {
-        if event::poll(config.tick_rate).unwrap() {
-          if let event::Event::Key(key) = event::read().unwrap() {
-            let key = Key::from(key);
-            event_sender.send(Event::Input(key)).unwrap();
-          }
+        if event::poll(config.tick_rate).unwrap()
+          && let event::Event::Key(key) = event::read().unwrap()
+        {
+          let key = Key::from(key);
+          event_sender.send(Event::Input(key)).unwrap();
         }
 
         event_sender.send(Event::Tick).unwrap();
@@ -81,6 +105,9 @@ impl From<KeyEvent> for Key {
       KeyEvent {
         code: KeyCode::Esc, ..
       } => Self::Esc,
+      KeyEvent {
+        code: KeyCode::Tab, ..
+      } => Self::Tab,
 
       KeyEvent {
         code: KeyCode::Left,
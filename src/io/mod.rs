@@ -1,24 +1,57 @@
 //! Async IO operations.
 
+pub(crate) mod backend;
+pub(crate) mod cache;
 pub(crate) mod key;
+pub(crate) mod operation;
+mod refresh;
+mod retry;
+pub(crate) mod target;
 
-use crate::state::{State, handler::ViewId};
+use crate::{
+  auth::{self, AuthMode, profile::Profile},
+  config::Config,
+  io::{
+    backend::Backend,
+    cache::Cache,
+    operation::{Operation, Operations},
+    retry::Retry,
+    target::PlayTarget,
+  },
+  state::{AuthPrompt, State, Status},
+};
 use rspotify::{
-  AuthCodePkceSpotify as Spotify,
-  model::{AdditionalType, PlayableItem, PlaylistId},
-  prelude::{BaseClient, OAuthClient},
+  AuthCodePkceSpotify as Spotify, ClientError, ClientResult,
+  http::HttpError,
+  model::{ArtistId, PlayableId, PlaylistId, RepeatState, TrackId},
+  prelude::Id,
 };
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::{future::Future, sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio::{sync::Mutex, task::JoinHandle};
 
-pub(crate) struct Io<'io> {
-  spotify: Spotify,
+pub(crate) struct Io<'io, B = Spotify> {
+  /// Where the library and the playback come from, Spotify unless testing.
+  backend: B,
   state: &'io Arc<Mutex<State>>,
+  /// Background task [keeping the session alive](self::backend::Backend::keep_alive),
+  /// like [refreshing](self::refresh) the access token.
+  refresher: JoinHandle<()>,
+  /// Background task waiting for the [OAuth callback](crate::auth::callback).
+  listener: Option<JoinHandle<()>>,
+  /// Changes to the library not sent yet, [logged](self::operation) on disk.
+  operations: Operations,
+  /// The page of a playlist shown from the cache at the snapshot restored from
+  /// it, to be fetched again if the playlist changed since.
+  cached_page: Option<(PlaylistId<'static>, u32, String)>,
+  /// Background task sending the operations again once no longer rate limited.
+  retry: Option<JoinHandle<()>>,
 }
 
 /// IO events that are layzily sent to the queue
 /// of the [IO manager](self::Io) to be asynchronously executed.
 #[allow(unused)]
+#[derive(Clone)]
 pub(crate) enum Event {
   /// Get the current logged user's playlists.
   UserPlaylists,
@@ -29,81 +62,956 @@ pub(crate) enum Event {
 
   Seek(u32),
   NextTrack,
+  PreviousTrack,
+  /// Resume the playback, or play the given target.
+  Play(Option<PlayTarget>),
+  Pause,
+  /// Add the item to the end of the playback queue.
+  Queue(PlayableId<'static>),
+  /// Set the volume, from 0 to 100.
+  Volume(u8),
+  Shuffle(bool),
+  Repeat(RepeatState),
+
+  /// Add the tracks or episodes to the end of the playlist.
+  AddToPlaylist(PlaylistId<'static>, Vec<PlayableId<'static>>),
+  /// Save the track to the user's Liked Songs.
+  SaveTrack(TrackId<'static>),
+  FollowArtist(ArtistId<'static>),
+  /// Send the logged changes to the library again, after being rate limited.
+  SendOperations,
+
+  /// Ask the user to authorize orpheus again, as the session can't be refreshed.
+  Reauthenticate,
+  /// Finish the re-authentication with the URL Spotify redirected to, or the code in it.
+  FinishReauthentication(String),
+  /// Finish the re-authentication with the code captured by the callback listener.
+  AuthorizationCode(String),
+
+  /// Sign in with the account of another profile.
+  SwitchProfile(Profile),
+  /// Show the signed in account, its scopes and token expiry.
+  Account,
+  /// Sign out of the current profile and ask to authorize again.
+  Logout,
+}
+
+impl Event {
+  /// Whether the event changes the playback, so it's turned down while offline
+  /// rather than applied out of the blue once back online.
+  ///
+  /// Changes to the library are [operations](self::operation) instead, which
+  /// are kept until they're sent.
+  pub fn is_playback_change(&self) -> bool {
+    matches!(
+      self,
+      Self::Seek(_)
+        | Self::NextTrack
+        | Self::PreviousTrack
+        | Self::Play(_)
+        | Self::Pause
+        | Self::Queue(_)
+        | Self::Volume(_)
+        | Self::Shuffle(_)
+        | Self::Repeat(_)
+    )
+  }
+}
+
+/// Errors of the [IO manager](self::Io) handlers.
+///
+/// Those are not fatal: they're pushed into the [state](crate::state::State)
+/// to be shown to the user while the previous data is kept on screen.
+#[derive(Debug, Clone, Error)]
+pub(crate) enum IoError {
+  #[error("Could not reach Spotify: {0}")]
+  Network(String),
+  #[error("Offline, the playback can't be changed until Spotify can be reached")]
+  Offline,
+  #[error("Session expired, please re-authenticate")]
+  Unauthorized,
+  #[error("Could not find the authorization code in the given input")]
+  InvalidRedirect,
+  #[error("Failed to authorize with Spotify: {0}")]
+  Authorization(String),
+  #[error("Rate limited by Spotify, try again in {0}s")]
+  RateLimited(u64),
+  #[error("Spotify returned status {0}")]
+  Status(u16),
+  #[error("Unexpected response from Spotify: {0}")]
+  Client(String),
+  #[error("Could not switch profile: {0}")]
+  Profile(String),
+  #[error("Could not log out: {0}")]
+  Logout(String),
+  #[error("Could not {0}: {1}")]
+  Operation(String, String),
+  /// What became of each of the operations sent together, in order.
+  #[error("{failed} of {} change(s) failed: {}", outcomes.len(), outcomes.join("; "))]
+  Operations {
+    failed: usize,
+    outcomes: Vec<String>,
+  },
+  #[error("Failed to load {} script(s): {}", .0.len(), .0.join("; "))]
+  Scripts(Vec<String>),
+  #[error("Could not read the changes not sent yet, they were kept in {0}")]
+  CorruptOperations(String),
+}
+
+impl From<ClientError> for IoError {
+  fn from(value: ClientError) -> Self {
+    if retry::is_revoked(&value) {
+      return Self::Unauthorized;
+    }
+
+    match value {
+      ClientError::Http(err) => match *err {
+        HttpError::Client(err) => Self::Network(err.to_string()),
+        HttpError::StatusCode(ref response) => match response.status().as_u16() {
+          401 => Self::Unauthorized,
+          429 => Self::RateLimited(retry::retry_after(&err).unwrap_or_default().as_secs()),
+          status => Self::Status(status),
+        },
+      },
+      ClientError::InvalidToken => Self::Unauthorized,
+      // what backends without HTTP fail with when they can't reach their source
+      ClientError::Io(err) => Self::Network(err.to_string()),
+      err => Self::Client(err.to_string()),
+    }
+  }
 }
 
 #[allow(unused)]
-impl<'io> Io<'io> {
-  pub fn new(spotify: Spotify, state: &'io Arc<Mutex<State>>) -> Self {
-    Self { spotify, state }
+impl<'io, B: Backend> Io<'io, B> {
+  /// Creates the IO manager, which must happen inside of a Tokio runtime.
+  pub fn new(backend: B, state: &'io Arc<Mutex<State>>) -> Self {
+    let refresher = backend.keep_alive(state.clone());
+
+    Self {
+      backend,
+      state,
+      refresher,
+      listener: None,
+      operations: Operations::default(),
+      cached_page: None,
+      retry: None,
+    }
+  }
+
+  /// Shows the library [cached](self::cache) by the last session while it's fetched
+  /// again, and sends the [operations](self::operation) it didn't get to.
+  pub async fn restore(&mut self) {
+    let profile = self.state.lock().await.profile.clone();
+
+    if let Some(playlists) = Cache::new(&profile).playlists().await {
+      self.state.lock().await.restore_playlists(playlists);
+    }
+
+    let (operations, corrupt) = Operations::load(&profile).await;
+    self.operations = operations;
+    if let Some(err) = corrupt {
+      self.state.lock().await.set_error(err);
+    }
+    if !self.operations.is_empty()
+      && let Err(err) = self.send_operations().await
+    {
+      self.state.lock().await.set_error(err);
+    }
   }
 
   pub async fn handle_event(&mut self, event: Event) {
-    match event {
+    let was_offline = self.state.lock().await.is_offline;
+    if was_offline && event.is_playback_change() {
+      self.state.lock().await.set_error(IoError::Offline);
+      return;
+    }
+
+    let result = match event {
       Event::UserPlaylists => self.current_user_playlists().await,
       Event::GetCurrentPlayback => self.current_playback().await,
       Event::PlaylistTracks(id, offset) => self.playlist_tracks(id, offset).await,
 
-      Event::Seek(_) => todo!(),
-      Event::NextTrack => todo!(),
+      Event::Seek(ms) => self.seek(ms).await,
+      Event::NextTrack => self.next_track().await,
+      Event::PreviousTrack => self.previous_track().await,
+      Event::Play(target) => self.play(target).await,
+      Event::Pause => self.pause().await,
+      Event::Queue(id) => self.queue(id).await,
+      Event::Volume(level) => self.volume(level).await,
+      Event::Shuffle(shuffle) => self.shuffle(shuffle).await,
+      Event::Repeat(repeat) => self.repeat(repeat).await,
+
+      Event::AddToPlaylist(playlist, items) => {
+        self
+          .apply(Operation::AddToPlaylist { playlist, items })
+          .await
+      }
+      Event::SaveTrack(track) => self.apply(Operation::SaveTrack { track }).await,
+      Event::FollowArtist(artist) => self.apply(Operation::FollowArtist { artist }).await,
+      Event::SendOperations => self.send_operations().await,
+
+      Event::Reauthenticate => self.reauthenticate().await,
+      Event::FinishReauthentication(input) => self.finish_reauthentication(&input).await,
+      Event::AuthorizationCode(code) => self.authorize(&code).await,
+
+      Event::SwitchProfile(profile) => self.switch_profile(profile).await,
+      Event::Account => self.account().await,
+      Event::Logout => self.logout().await,
+    };
+
+    if let Err(IoError::Network(err)) = &result {
+      tracing::warn!("offline: {err}");
+      // the cached library stays on screen with the offline indicator
+      if was_offline {
+        return;
+      }
+    }
+
+    if let Err(err) = result {
+      tracing::error!("{err}");
+
+      let unauthorized = matches!(err, IoError::Unauthorized);
+      self.state.lock().await.set_error(err);
+
+      if unauthorized && let Err(err) = self.reauthenticate().await {
+        self.state.lock().await.set_error(err);
+      }
+    }
+
+    if was_offline && !self.state.lock().await.is_offline {
+      self.reconnect().await;
+    }
+  }
+
+  async fn report_pending(&self) {
+    self.state.lock().await.set_info(format!(
+      "Offline, {} change(s) will be sent once back online",
+      self.operations.len()
+    ));
+  }
+
+  /// Sends the changes to the library logged while offline, then catches up with what changed meanwhile.
+  async fn reconnect(&mut self) {
+    tracing::info!("back online, sending {} change(s)", self.operations.len());
+    self.state.lock().await.set_info("Back online");
+
+    if let Err(err) = self.send_operations().await {
+      self.state.lock().await.set_error(err);
+    }
+
+    self.state.lock().await.dispatch(Event::UserPlaylists);
+  }
+
+  /// Logs the `operation`, then sends it after those logged before.
+  async fn apply(&mut self, operation: Operation) -> Result<(), IoError> {
+    self.operations.push(operation).await;
+    self.send_operations().await
+  }
+
+  /// Sends the logged operations in order until one can't be sent for now,
+  /// then reports what became of them.
+  async fn send_operations(&mut self) -> Result<(), IoError> {
+    let mut outcomes = vec![];
+
+    let result = loop {
+      let Some(operation) = self.operations.first().cloned() else {
+        break Ok(());
+      };
+      // kept until the playback polls find Spotify reachable again
+      if self.state.lock().await.is_offline {
+        break Err(IoError::Offline);
+      }
+
+      match self.request(false, |backend| operation.send(backend)).await {
+        Ok(()) => {
+          tracing::info!("{}", operation.outcome());
+          self.operations.remove_first().await;
+
+          if let Operation::AddToPlaylist { .. } = operation {
+            self.state.lock().await.dispatch(Event::UserPlaylists);
+          }
+          outcomes.push(Ok(operation.outcome()));
+        }
+        // kept to be sent again once possible
+        Err(err @ (IoError::Network(_) | IoError::Unauthorized | IoError::RateLimited(_))) => {
+          break Err(err);
+        }
+        Err(err) => {
+          let err = IoError::Operation(operation.action(), err.to_string());
+          tracing::error!("{err}");
+          self.operations.remove_first().await;
+          outcomes.push(Err(err));
+        }
+      }
+    };
+
+    // the outcome isn't hidden by what keeps the others from being sent
+    let has_outcome = !outcomes.is_empty();
+    if has_outcome {
+      self.report(outcomes).await;
+    }
+
+    match result {
+      Err(IoError::Network(err)) => {
+        tracing::warn!("offline: {err}");
+        if !has_outcome {
+          self.report_pending().await;
+        }
+        Ok(())
+      }
+      Err(IoError::Offline) => {
+        if !has_outcome {
+          self.report_pending().await;
+        }
+        Ok(())
+      }
+      Err(err @ IoError::RateLimited(seconds)) => {
+        self.retry_operations(seconds);
+        if !has_outcome {
+          self.state.lock().await.set_error(err);
+        }
+        Ok(())
+      }
+      result => result,
+    }
+  }
+
+  /// Shows what became of each of the operations sent together, in one status.
+  async fn report(&self, mut outcomes: Vec<Result<String, IoError>>) {
+    let failed = outcomes.iter().filter(|outcome| outcome.is_err()).count();
+    let mut state = self.state.lock().await;
+
+    match (outcomes.len(), failed) {
+      (1, _) => match outcomes.remove(0) {
+        Ok(outcome) => state.set_info(outcome),
+        Err(err) => state.set_error(err),
+      },
+      (count, 0) => {
+        let sent: Vec<_> = outcomes.into_iter().flatten().collect();
+        state.set_info(format!("Sent {count} changes: {}", sent.join(", ")));
+      }
+      _ => state.set_error(IoError::Operations {
+        failed,
+        outcomes: outcomes
+          .into_iter()
+          .map(|outcome| outcome.unwrap_or_else(|err| err.to_string()))
+          .collect(),
+      }),
+    }
+  }
+
+  /// Sends the operations again after `seconds`, once Spotify stops rate limiting orpheus.
+  fn retry_operations(&mut self, seconds: u64) {
+    let state = self.state.clone();
+    let delay = Duration::from_secs(seconds.max(1));
+
+    self.abort_retry();
+    self.retry = Some(tokio::spawn(async move {
+      tokio::time::sleep(delay).await;
+      state.lock().await.dispatch(Event::SendOperations);
+    }));
+  }
+
+  async fn request<'a, T, F, Fut>(&'a self, idempotent: bool, request: F) -> Result<T, IoError>
+  where
+    F: Fn(&'a B) -> Fut,
+    Fut: Future<Output = ClientResult<T>>,
+  {
+    // while offline, requests only probe whether Spotify is reachable again
+    let is_offline = self.state.lock().await.is_offline;
+    let result = send(
+      &self.backend,
+      Some(self.state),
+      idempotent && !is_offline,
+      request,
+    )
+    .await;
+
+    match &result {
+      Ok(_) => self.state.lock().await.is_offline = false,
+      Err(IoError::Network(_)) => self.state.lock().await.is_offline = true,
+      Err(_) => {}
     }
+    result
+  }
+
+  /// The cache of the current profile.
+  async fn cache(&self) -> Cache {
+    Cache::new(&self.state.lock().await.profile)
   }
 
-  async fn current_user_playlists(&mut self) {
+  async fn current_user_playlists(&mut self) -> Result<(), IoError> {
     let playlists = self
-      .spotify
-      .current_user_playlists_manual(Some(25), None)
-      .await;
+      .request(true, |backend| backend.playlists(25, 0))
+      .await?;
+
+    self.cache().await.store_playlists(&playlists).await;
+
+    let mut state = self.state.lock().await;
+    if let Some((id, offset, snapshot_id)) = self.cached_page.take()
+      && let Some(playlist) = playlists.items.iter().find(|playlist| playlist.id == id)
+      && playlist.snapshot_id != snapshot_id
+    {
+      state.dispatch(Event::PlaylistTracks(id, offset));
+    }
+    state.set_playlists(playlists);
+    Ok(())
+  }
+
+  async fn current_playback(&mut self) -> Result<(), IoError> {
+    let context = self.request(true, |backend| backend.playback()).await;
+
+    let mut state = self.state.lock().await;
+    state.finish_fetching_playback();
+    state.set_playback(context?);
+    Ok(())
+  }
+
+  async fn playlist_tracks(&mut self, id: PlaylistId<'_>, offset: u32) -> Result<(), IoError> {
+    let (snapshot_id, is_cached) = {
+      let state = self.state.lock().await;
+      let snapshot_id = state.playlists.as_ref().and_then(|playlists| {
+        let playlist = playlists.items.iter().find(|playlist| playlist.id == id)?;
+        Some(playlist.snapshot_id.clone())
+      });
+      (snapshot_id, state.playlists_are_cached())
+    };
+
+    // the items are still those of the cache while the playlist is unchanged
+    let cache = self.cache().await;
+    if let Some(snapshot_id) = &snapshot_id
+      && let Some(tracks) = cache.playlist_items(id.id(), snapshot_id, offset).await
+    {
+      // the snapshot may be outdated until the playlists are fetched
+      self.cached_page = is_cached.then(|| (id.clone_static(), offset, snapshot_id.clone()));
+      self.state.lock().await.playlist_tracks = Some(tracks);
+      return Ok(());
+    }
+    self.cached_page = None;
+
+    let tracks = self
+      .request(true, |backend| backend.playlist_items(id.as_ref(), offset))
+      .await?;
+
+    if let Some(snapshot_id) = &snapshot_id {
+      cache
+        .store_playlist_items(id.id(), snapshot_id, offset, &tracks)
+        .await;
+    }
+
+    let mut state = self.state.lock().await;
+    state.playlist_tracks = Some(tracks);
+    // TODO: push the view to stack
+    Ok(())
+  }
+
+  async fn seek(&mut self, ms: u32) -> Result<(), IoError> {
+    let position = chrono::Duration::milliseconds(ms.into());
+    self.request(true, |backend| backend.seek(position)).await?;
+    self.playback_changed().await;
+    Ok(())
+  }
+
+  async fn next_track(&mut self) -> Result<(), IoError> {
+    self.request(false, |backend| backend.next()).await?;
+    self.playback_changed().await;
+    Ok(())
+  }
+
+  async fn previous_track(&mut self) -> Result<(), IoError> {
+    self.request(false, |backend| backend.previous()).await?;
+    self.playback_changed().await;
+    Ok(())
+  }
+
+  async fn play(&mut self, target: Option<PlayTarget>) -> Result<(), IoError> {
+    self
+      .request(true, |backend| backend.play(target.as_ref()))
+      .await?;
+    self.playback_changed().await;
+    Ok(())
+  }
 
-    // TODO: handle those errors
-    match playlists {
-      Ok(playlists) => {
-        let mut state = self.state.lock().await;
-        state.playlists = Some(playlists);
+  async fn pause(&mut self) -> Result<(), IoError> {
+    self.request(true, |backend| backend.pause()).await?;
+    self.playback_changed().await;
+    Ok(())
+  }
+
+  async fn queue(&mut self, id: PlayableId<'static>) -> Result<(), IoError> {
+    self
+      .request(false, |backend| backend.queue(id.as_ref()))
+      .await?;
+    Ok(())
+  }
+
+  async fn volume(&mut self, level: u8) -> Result<(), IoError> {
+    self
+      .request(true, |backend| backend.set_volume(level))
+      .await?;
+    self.playback_changed().await;
+    Ok(())
+  }
+
+  async fn shuffle(&mut self, shuffle: bool) -> Result<(), IoError> {
+    self
+      .request(true, |backend| backend.set_shuffle(shuffle))
+      .await?;
+    self.playback_changed().await;
+    Ok(())
+  }
+
+  async fn repeat(&mut self, repeat: RepeatState) -> Result<(), IoError> {
+    self
+      .request(true, |backend| backend.set_repeat(repeat))
+      .await?;
+    self.playback_changed().await;
+    Ok(())
+  }
+
+  /// Fetches the playback after changing it, so it's up to date right away.
+  async fn playback_changed(&self) {
+    self.state.lock().await.dispatch(Event::GetCurrentPlayback);
+  }
+
+  async fn reauthenticate(&mut self) -> Result<(), IoError> {
+    if self.state.lock().await.auth_prompt.is_some() {
+      return Ok(());
+    }
+
+    let url = self.backend.authorize_url()?;
+
+    let mut state = self.state.lock().await;
+    state.auth_prompt = Some(AuthPrompt::new(url.clone()));
+    if state.config.auth.mode == AuthMode::Headless {
+      return Ok(());
+    }
+    drop(state);
+
+    if let Err(err) = webbrowser::open(&url) {
+      tracing::warn!("failed to open the browser: {err}");
+    }
+
+    let callback = self.backend.callback();
+    let state = self.state.clone();
+
+    self.abort_listener();
+    self.listener = Some(tokio::spawn(async move {
+      match callback.await {
+        Ok(code) => state.lock().await.dispatch(Event::AuthorizationCode(code)),
+        Err(err) => tracing::warn!("OAuth callback listener stopped: {err}"),
       }
-      _ => unreachable!(),
+    }));
+
+    Ok(())
+  }
+
+  async fn finish_reauthentication(&mut self, input: &str) -> Result<(), IoError> {
+    let code = self
+      .backend
+      .parse_code(input)
+      .ok_or(IoError::InvalidRedirect)?;
+
+    self.authorize(&code).await
+  }
+
+  async fn authorize(&mut self, code: &str) -> Result<(), IoError> {
+    self.abort_listener();
+    self
+      .backend
+      .sign_in(code)
+      .await
+      .map_err(|err| IoError::Authorization(err.to_string()))?;
+
+    if self.refresher.is_finished() {
+      self.refresher = self.backend.keep_alive(self.state.clone());
     }
+
+    let mut state = self.state.lock().await;
+    state.auth_prompt = None;
+    state.set_info("Signed in to Spotify again");
+    state.dispatch(Event::UserPlaylists);
+    drop(state);
+
+    self.send_operations().await
   }
 
-  async fn current_playback(&mut self) {
-    let context = self
-      .spotify
-      .current_playback(
-        None,
-        Some(vec![&AdditionalType::Episode, &AdditionalType::Track]),
-      )
-      .await;
+  async fn switch_profile(&mut self, profile: Profile) -> Result<(), IoError> {
+    let config = Config::load(&profile).map_err(|err| IoError::Profile(err.to_string()))?;
+    let backend = self
+      .backend
+      .switch(&profile)
+      .await
+      .map_err(|err| IoError::Profile(err.to_string()))?;
+
+    self.abort_listener();
+    self.abort_retry();
+    self.refresher.abort();
+    self.cached_page = None;
+    self.backend = backend;
+    self.refresher = self.backend.keep_alive(self.state.clone());
+
+    let mut state = self.state.lock().await;
+    state.set_info(format!("Switched to profile '{}'", profile.name()));
+    state.switch_profile(profile, config);
+    state.dispatch(Event::UserPlaylists);
+    drop(state);
+
+    self.restore().await;
+    Ok(())
+  }
+
+  async fn account(&mut self) -> Result<(), IoError> {
+    let user = self.request(true, |backend| backend.user()).await?;
+    let session = self.backend.session(user).await;
+
+    self.state.lock().await.session = session;
+    Ok(())
+  }
+
+  async fn logout(&mut self) -> Result<(), IoError> {
+    let profile = self.state.lock().await.profile.clone();
+    auth::logout(&profile).map_err(|err| IoError::Logout(err.to_string()))?;
+
+    self.abort_listener();
+    self.abort_retry();
+    self.refresher.abort();
+    self.cached_page = None;
+    // the next account to sign in may not be the same
+    self.operations.clear().await;
+    self.backend.sign_out().await;
+
+    let mut state = self.state.lock().await;
+    state.clear_account_data();
+    state.set_info(format!("Logged out of profile '{}'", profile.name()));
+    drop(state);
 
-    match context {
-      Ok(Some(context)) => {
-        let mut state = self.state.lock().await;
-        state.current_playback_context = Some(context.clone());
+    self.reauthenticate().await
+  }
+
+  fn abort_retry(&mut self) {
+    if let Some(retry) = self.retry.take() {
+      retry.abort();
+    }
+  }
+
+  fn abort_listener(&mut self) {
+    if let Some(listener) = self.listener.take() {
+      listener.abort();
+    }
+  }
+}
+
+/// Sends a request to the `backend`, Spotify unless testing, retrying it
+/// according to the [retry policy](self::retry).
+///
+/// Only `idempotent` requests are retried on network errors and `5xx`,
+/// while rate-limited ones are always retried after the `Retry-After`.
+/// A rejected token is refreshed and the request is sent once more.
+///
+/// Retries are reported in the status line of the `state`, if there's one.
+pub(crate) async fn send<'a, B, T, F, Fut>(
+  backend: &'a B,
+  state: Option<&Arc<Mutex<State>>>,
+  idempotent: bool,
+  request: F,
+) -> Result<T, IoError>
+where
+  B: Backend,
+  F: Fn(&'a B) -> Fut,
+  Fut: Future<Output = ClientResult<T>>,
+{
+  let mut attempt = 0;
+  let mut refreshed = false;
 
-        match context.item {
-          Some(PlayableItem::Track(_)) => {}
-          Some(PlayableItem::Episode(_)) => {}
-          _ => {}
+  loop {
+    let err = match request(backend).await {
+      Ok(response) => {
+        if attempt > 0
+          && let Some(state) = state
+        {
+          let mut state = state.lock().await;
+          if matches!(state.status, Some(Status::Info(_))) {
+            state.dismiss_status();
+          }
         }
+        return Ok(response);
       }
+      Err(err) => err,
+    };
 
-      Ok(None) => {}
+    let delay = match Retry::from_error(&err, attempt, idempotent) {
+      Retry::RateLimited(delay) => {
+        let message = format!("Rate limited, retrying in {}s", delay.as_secs().max(1));
+        match state {
+          Some(state) => state.lock().await.set_info(message),
+          None => eprintln!("{message}"),
+        }
+        delay
+      }
+      Retry::Backoff(delay) => delay,
+      Retry::Refresh if !refreshed => {
+        tracing::warn!("request unauthorized ({err}), refreshing the access token");
+        backend.refresh().await?;
+        refreshed = true;
+        continue;
+      }
+      Retry::Refresh | Retry::Abort => return Err(err.into()),
+    };
 
-      Err(_) => unreachable!(),
+    attempt += 1;
+    tracing::warn!("request failed ({err}), attempt {attempt} retrying in {delay:?}");
+    tokio::time::sleep(delay).await;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::backend::fake::{self, Fake, Fixtures};
+  use std::{
+    collections::HashMap,
+    sync::mpsc::{Receiver, channel},
+  };
+  use tempfile::TempDir;
+
+  /// A state on a profile of its own, with the events it dispatches.
+  fn state() -> (TempDir, Arc<Mutex<State>>, Receiver<Event>) {
+    let dir = TempDir::new().unwrap();
+    let (sender, events) = channel();
+    let state = State::new(Config::default(), Profile::rooted(dir.path()), sender);
+    (dir, Arc::new(Mutex::new(state)), events)
+  }
+
+  fn fixtures() -> Fixtures {
+    Fixtures {
+      user: Some(fake::user("orpheus")),
+      playlists: vec![fake::playlist("focus", "1")],
+      playlist_items: HashMap::from([(
+        "focus".to_string(),
+        vec![fake::playlist_item(fake::track("first", "First"))],
+      )]),
+      ..Fixtures::default()
     }
   }
 
-  async fn playlist_tracks(&mut self, id: PlaylistId<'_>, offset: u32) {
-    if let Ok(tracks) = self
-      .spotify
-      .playlist_items_manual(id, None, None, None, Some(offset))
-      .await
-    {
-      let mut state = self.state.lock().await;
-      state.playlist_tracks = Some(tracks);
-      // TODO: push the view to stack
+  /// Handles the events dispatched while handling the previous ones.
+  async fn handle_dispatched<B: Backend>(io: &mut Io<'_, B>, events: &Receiver<Event>) {
+    while let Ok(event) = events.try_recv() {
+      io.handle_event(event).await;
+    }
+  }
+
+  #[tokio::test]
+  async fn user_playlists_are_stored_in_the_state() {
+    let (_dir, state, _events) = state();
+    let mut io = Io::new(Fake::new(fixtures()), &state);
+
+    io.handle_event(Event::UserPlaylists).await;
+
+    let state = state.lock().await;
+    let playlists = state.playlists.as_ref().unwrap();
+    assert_eq!(playlists.items, [fake::playlist("focus", "1")]);
+    assert!(!state.playlists_are_cached());
+    assert!(state.status.is_none());
+  }
+
+  #[tokio::test]
+  async fn operations_made_offline_are_sent_once_back_online() {
+    let (_dir, state, events) = state();
+    let backend = Fake::new(fixtures());
+    let mut io = Io::new(backend.clone(), &state);
+    io.restore().await;
+    let track = TrackId::from_id("first").unwrap();
+
+    backend.fixtures().is_offline = true;
+    io.handle_event(Event::SaveTrack(track.clone())).await;
+
+    assert!(state.lock().await.is_offline);
+    assert!(backend.fixtures().saved_tracks.is_empty());
+    assert_eq!(
+      io.operations.first(),
+      Some(&Operation::SaveTrack {
+        track: track.clone()
+      })
+    );
+
+    // survives a restart
+    let mut io = Io::new(backend.clone(), &state);
+    io.restore().await;
+    assert_eq!(io.operations.len(), 1);
+    assert!(backend.fixtures().saved_tracks.is_empty());
+
+    // the playback polls find out Spotify is back
+    backend.fixtures().is_offline = false;
+    io.handle_event(Event::GetCurrentPlayback).await;
+    handle_dispatched(&mut io, &events).await;
+
+    assert!(!state.lock().await.is_offline);
+    assert_eq!(backend.fixtures().saved_tracks, [track]);
+    assert!(io.operations.is_empty());
+
+    let (operations, corrupt) = Operations::load(&state.lock().await.profile).await;
+    assert!(operations.is_empty());
+    assert!(corrupt.is_none());
+  }
+
+  #[tokio::test]
+  async fn every_operation_sent_together_is_reported() {
+    let (_dir, state, _events) = state();
+    let backend = Fake::new(fixtures());
+    let mut io = Io::new(backend.clone(), &state);
+    io.restore().await;
+
+    backend.fixtures().is_offline = true;
+    io.handle_event(Event::SaveTrack(TrackId::from_id("first").unwrap()))
+      .await;
+    assert_eq!(io.operations.len(), 1);
+
+    // sent along with the next one once back online, but turned down for good
+    backend.fixtures().is_offline = false;
+    state.lock().await.is_offline = false;
+    backend
+      .fixtures()
+      .errors
+      .push_back(ClientError::Cli("rejected".to_string()));
+    io.handle_event(Event::FollowArtist(ArtistId::from_id("artist").unwrap()))
+      .await;
+
+    assert!(io.operations.is_empty());
+    let state = state.lock().await;
+    let Some(Status::Error(IoError::Operations { failed, outcomes })) = &state.status else {
+      panic!("unexpected status {:?}", state.status);
     };
+    assert_eq!(*failed, 1);
+    assert_eq!(
+      outcomes,
+      &[
+        "Could not like the track: Unexpected response from Spotify: cli error: rejected",
+        "Followed the artist",
+      ]
+    );
   }
 
-  async fn seek(&mut self, ms: u32) {
-    todo!()
+  #[tokio::test]
+  async fn requests_are_sent_again_until_they_succeed() {
+    let (_dir, state, _events) = state();
+    let backend = Fake::new(fixtures());
+    // right away, to keep the test fast
+    let rate_limited = || fake::status_error("api.spotify.com", 429, Some("0"));
+    backend
+      .fixtures()
+      .errors
+      .extend((1..retry::MAX_ATTEMPTS).map(|_| rate_limited()));
+
+    let user = send(&backend, Some(&state), false, |backend| backend.user()).await;
+
+    assert_eq!(user.unwrap(), fake::user("orpheus"));
+    assert!(backend.fixtures().errors.is_empty());
+    // the retries aren't reported anymore
+    assert!(state.lock().await.status.is_none());
+
+    backend
+      .fixtures()
+      .errors
+      .extend((0..=retry::MAX_ATTEMPTS).map(|_| rate_limited()));
+    let user = send(&backend, None, false, |backend| backend.user()).await;
+
+    assert!(matches!(user, Err(IoError::RateLimited(0))));
+    assert_eq!(backend.fixtures().errors.len(), 1);
+  }
+
+  #[tokio::test]
+  async fn only_idempotent_requests_are_sent_again_after_server_errors() {
+    let backend = Fake::new(fixtures());
+    let unavailable = || fake::status_error("api.spotify.com", 503, None);
+
+    backend.fixtures().errors.push_back(unavailable());
+    let saved = send(&backend, None, false, |backend| {
+      backend.save_track(TrackId::from_id("first").unwrap())
+    })
+    .await;
+
+    assert!(matches!(saved, Err(IoError::Status(503))));
+    assert!(backend.fixtures().saved_tracks.is_empty());
+
+    backend.fixtures().errors.push_back(unavailable());
+    let playback = send(&backend, None, true, |backend| backend.playback()).await;
+
+    assert!(playback.unwrap().is_none());
+    assert!(backend.fixtures().errors.is_empty());
+  }
+
+  #[tokio::test]
+  async fn playback_changes_are_turned_down_offline() {
+    let (_dir, state, events) = state();
+    let mut fixtures = fixtures();
+    fixtures.playback = Some(fake::playback(fake::track("first", "First"), true));
+    let backend = Fake::new(fixtures);
+    let mut io = Io::new(backend.clone(), &state);
+
+    backend.fixtures().is_offline = true;
+    io.handle_event(Event::GetCurrentPlayback).await;
+    io.handle_event(Event::Pause).await;
+    assert!(matches!(
+      state.lock().await.status,
+      Some(Status::Error(IoError::Offline))
+    ));
+
+    backend.fixtures().is_offline = false;
+    io.handle_event(Event::GetCurrentPlayback).await;
+    handle_dispatched(&mut io, &events).await;
+
+    assert!(backend.fixtures().playback.as_ref().unwrap().is_playing);
+  }
+
+  #[tokio::test]
+  async fn adding_to_a_playlist_invalidates_its_cached_items() {
+    let (_dir, state, events) = state();
+    let backend = Fake::new(fixtures());
+    let mut io = Io::new(backend.clone(), &state);
+    let playlist = PlaylistId::from_id("focus").unwrap();
+
+    io.handle_event(Event::UserPlaylists).await;
+    io.handle_event(Event::PlaylistTracks(playlist.clone(), 0))
+      .await;
+
+    let cache = io.cache().await;
+    assert!(cache.playlist_items("focus", "1", 0).await.is_some());
+
+    let item = PlayableId::Track(TrackId::from_id("second").unwrap());
+    io.handle_event(Event::AddToPlaylist(playlist, vec![item.clone()]))
+      .await;
+    handle_dispatched(&mut io, &events).await;
+
+    assert_eq!(backend.fixtures().added_items["focus"], [item]);
+    let snapshot_id = state.lock().await.playlists.as_ref().unwrap().items[0]
+      .snapshot_id
+      .clone();
+    assert_ne!(snapshot_id, "1");
+    assert!(cache.playlist_items("focus", "1", 0).await.is_none());
+  }
+
+  #[tokio::test]
+  async fn a_corrupt_operation_log_is_set_aside() {
+    let (dir, state, _events) = state();
+    let profile = state.lock().await.profile.clone();
+    let path = profile.dir().unwrap().join("operations.json");
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, "[{\"operation\": \"unknown\"}]").unwrap();
+
+    let mut io = Io::new(Fake::new(fixtures()), &state);
+    io.restore().await;
+
+    assert!(matches!(
+      state.lock().await.status,
+      Some(Status::Error(IoError::CorruptOperations(_)))
+    ));
+    assert!(!path.exists());
+    let corrupt = dir.path().join("config/operations.json.corrupt");
+    assert_eq!(
+      std::fs::read_to_string(corrupt).unwrap(),
+      "[{\"operation\": \"unknown\"}]"
+    );
   }
 }
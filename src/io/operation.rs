@@ -0,0 +1,211 @@
+//! Log of the changes to the user's library, like adding tracks to a playlist.
+//!
+//! Unlike the playback commands, which make no sense later on, the changes are
+//! written to `operations.json` in the [profile's directory](crate::auth::profile::Profile::dir)
+//! before being sent, and removed once Spotify answered. Those that can't be sent
+//! yet, because Spotify can't be reached or the session expired, survive network
+//! drops and crashes and are sent again in order. A change sent right before a
+//! crash may be sent twice.
+
+use crate::{
+  auth::profile::Profile,
+  io::{IoError, backend::Backend, cache::write_atomic},
+};
+use rspotify::{
+  ClientResult,
+  model::{ArtistId, PlayableId, PlaylistId, TrackId},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, path::PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub(crate) enum Operation {
+  /// Add tracks or episodes to the end of a playlist.
+  AddToPlaylist {
+    playlist: PlaylistId<'static>,
+    #[serde(with = "uris")]
+    items: Vec<PlayableId<'static>>,
+  },
+  /// Save a track to the user's Liked Songs.
+  SaveTrack {
+    track: TrackId<'static>,
+  },
+  FollowArtist {
+    artist: ArtistId<'static>,
+  },
+}
+
+impl Operation {
+  pub async fn send<B: Backend>(&self, backend: &B) -> ClientResult<()> {
+    match self {
+      Self::AddToPlaylist { playlist, items } => {
+        backend.add_to_playlist(playlist.as_ref(), items).await
+      }
+      Self::SaveTrack { track } => backend.save_track(track.as_ref()).await,
+      Self::FollowArtist { artist } => backend.follow_artist(artist.as_ref()).await,
+    }
+  }
+
+  /// What the operation does, like "like the track".
+  pub fn action(&self) -> String {
+    match self {
+      Self::AddToPlaylist { items, .. } => format!("add {} to the playlist", count(items.len())),
+      Self::SaveTrack { .. } => "like the track".to_string(),
+      Self::FollowArtist { .. } => "follow the artist".to_string(),
+    }
+  }
+
+  /// What was done once it's sent, like "Liked the track".
+  pub fn outcome(&self) -> String {
+    match self {
+      Self::AddToPlaylist { items, .. } => format!("Added {} to the playlist", count(items.len())),
+      Self::SaveTrack { .. } => "Liked the track".to_string(),
+      Self::FollowArtist { .. } => "Followed the artist".to_string(),
+    }
+  }
+}
+
+fn count(items: usize) -> String {
+  match items {
+    1 => "1 item".to_string(),
+    items => format!("{items} items"),
+  }
+}
+
+/// The operations not sent yet, oldest first, as stored on disk.
+#[derive(Debug, Default)]
+pub(crate) struct Operations {
+  /// `None` when there's no profile directory, so they're only kept in memory.
+  path: Option<PathBuf>,
+  queue: VecDeque<Operation>,
+}
+
+impl Operations {
+  /// Loads the operations of `profile` that the last session didn't get to send.
+  ///
+  /// A log that can't be read is moved to `operations.json.corrupt` rather than
+  /// overwritten, which is returned as an error alongside the empty queue.
+  pub async fn load(profile: &Profile) -> (Self, Option<IoError>) {
+    let Some(path) = profile.dir().ok().map(|dir| dir.join("operations.json")) else {
+      return (Self::default(), None);
+    };
+    let Ok(text) = tokio::fs::read_to_string(&path).await else {
+      return (Self::new(path), None);
+    };
+
+    let err = match serde_json::from_str(&text) {
+      Ok(queue) => {
+        let path = Some(path);
+        return (Self { path, queue }, None);
+      }
+      Err(err) => err,
+    };
+    tracing::error!("failed to read the operations {}: {err}", path.display());
+
+    let mut corrupt = path.as_os_str().to_owned();
+    corrupt.push(".corrupt");
+    let corrupt = PathBuf::from(corrupt);
+
+    match tokio::fs::rename(&path, &corrupt).await {
+      Ok(()) => (
+        Self::new(path),
+        Some(IoError::CorruptOperations(corrupt.display().to_string())),
+      ),
+      // only kept in memory, so that the log isn't lost
+      Err(rename_err) => {
+        tracing::error!("failed to move {}: {rename_err}", path.display());
+        (
+          Self::default(),
+          Some(IoError::CorruptOperations(path.display().to_string())),
+        )
+      }
+    }
+  }
+
+  fn new(path: PathBuf) -> Self {
+    Self {
+      path: Some(path),
+      queue: VecDeque::new(),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.queue.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.queue.is_empty()
+  }
+
+  pub fn first(&self) -> Option<&Operation> {
+    self.queue.front()
+  }
+
+  /// Logs the `operation` to be sent after the others.
+  pub async fn push(&mut self, operation: Operation) {
+    self.queue.push_back(operation);
+    self.save().await;
+  }
+
+  /// Forgets all the operations, which won't be sent.
+  pub async fn clear(&mut self) {
+    self.queue.clear();
+    self.save().await;
+  }
+
+  /// Forgets the first operation, once it was sent or can't be.
+  pub async fn remove_first(&mut self) {
+    self.queue.pop_front();
+    self.save().await;
+  }
+
+  async fn save(&self) {
+    let Some(path) = &self.path else {
+      return;
+    };
+
+    let result = match self.queue.is_empty() {
+      true => match tokio::fs::remove_file(path).await {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+      },
+      false => match serde_json::to_vec_pretty(&self.queue) {
+        Ok(json) => write_atomic(path, &json).await,
+        Err(err) => Err(err.into()),
+      },
+    };
+
+    if let Err(err) = result {
+      tracing::warn!("failed to save the operations {}: {err}", path.display());
+    }
+  }
+}
+
+/// (De)serializes tracks and episodes by URI, as their ids alone are ambiguous.
+mod uris {
+  use crate::io::target::PlayTarget;
+  use rspotify::{model::PlayableId, prelude::Id};
+  use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+  pub fn serialize<S: Serializer>(
+    items: &[PlayableId<'static>],
+    serializer: S,
+  ) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(items.iter().map(|item| item.uri()))
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<Vec<PlayableId<'static>>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+      .into_iter()
+      .map(|uri| match PlayTarget::parse(&uri) {
+        Some(PlayTarget::Item(id)) => Ok(id),
+        _ => Err(D::Error::custom(format!(
+          "'{uri}' is not a track or episode"
+        ))),
+      })
+      .collect()
+  }
+}
@@ -0,0 +1,51 @@
+//! Proactive refresh of the access token.
+//!
+//! Spotify tokens last an hour. Instead of waiting for a request to fail,
+//! the token is refreshed in the background a few minutes before it expires.
+
+use crate::{
+  io::{Event, retry},
+  state::State,
+};
+use chrono::{TimeDelta, Utc};
+use rspotify::{AuthCodePkceSpotify as Spotify, prelude::BaseClient};
+use std::{sync::Arc, time::Duration};
+use tokio::{sync::Mutex, task::JoinHandle};
+
+/// How long before the expiry the token gets refreshed.
+const REFRESH_MARGIN: TimeDelta = TimeDelta::minutes(5);
+/// Delay before trying again when the refresh failed for a transient reason.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Spawns the task that keeps the token of `spotify` fresh.
+///
+/// The client shares its token with the clone, so the [IO manager](super::Io)
+/// always sees the refreshed one. The task stops when there's no longer a
+/// refresh token to use, asking the user to authorize again.
+pub(crate) fn spawn(spotify: Spotify, state: Arc<Mutex<State>>) -> JoinHandle<()> {
+  tokio::spawn(async move {
+    loop {
+      let expires_at = match spotify.token.lock().await.unwrap().as_ref() {
+        Some(token) if token.refresh_token.is_some() => token.expires_at,
+        _ => break,
+      };
+
+      if let Some(expires_at) = expires_at {
+        let wait = (expires_at - REFRESH_MARGIN - Utc::now()).to_std();
+        tokio::time::sleep(wait.unwrap_or_default()).await;
+      }
+
+      match spotify.refresh_token().await {
+        Ok(()) => tracing::info!("access token refreshed"),
+        Err(err) if retry::is_revoked(&err) => break,
+        Err(err) => {
+          tracing::warn!("failed to refresh the access token: {err}");
+          tokio::time::sleep(RETRY_DELAY).await;
+        }
+      }
+    }
+
+    tracing::warn!("access token can no longer be refreshed");
+    state.lock().await.dispatch(Event::Reauthenticate);
+  })
+}
@@ -0,0 +1,204 @@
+//! Retry policy for requests made by the [IO manager](super::Io).
+//!
+//! Spotify rate-limits aggressively, so a `429` is always retried after the
+//! `Retry-After` it sends. Network errors and `5xx` are only retried when the
+//! request is idempotent, with an exponential backoff and full jitter. A `401`
+//! means the access token is no longer valid, so it's refreshed and the
+//! request is sent once more.
+
+use rand::Rng;
+use rspotify::{ClientError, http::HttpError};
+use std::time::Duration;
+
+/// Maximum number of attempts for a single request, including the first one.
+pub(crate) const MAX_ATTEMPTS: u32 = 5;
+
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+/// Longer `Retry-After`s are surfaced to the user instead of blocking the queue.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+const ACCOUNTS_HOST: &str = "accounts.spotify.com";
+
+/// What to do after a failed attempt.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Retry {
+  /// Spotify asked us to wait before trying again.
+  RateLimited(Duration),
+  /// Transient failure, try again after the backoff.
+  Backoff(Duration),
+  /// The access token was rejected, refresh it and try again.
+  Refresh,
+  /// Give up and surface the error.
+  Abort,
+}
+
+impl Retry {
+  /// Decides whether the `attempt`-th (zero-based) failure should be retried.
+  pub fn from_error(err: &ClientError, attempt: u32, idempotent: bool) -> Self {
+    if attempt + 1 >= MAX_ATTEMPTS || is_revoked(err) {
+      return Self::Abort;
+    }
+
+    let err = match err {
+      ClientError::Http(err) => err,
+      ClientError::InvalidToken => return Self::Refresh,
+      _ => return Self::Abort,
+    };
+
+    match err.as_ref() {
+      HttpError::Client(_) if idempotent => Self::Backoff(backoff(attempt)),
+      HttpError::StatusCode(response) => match response.status().as_u16() {
+        401 => Self::Refresh,
+        429 => match retry_after(err) {
+          Some(delay) if delay > MAX_RETRY_AFTER => Self::Abort,
+          Some(delay) => Self::RateLimited(delay),
+          None => Self::RateLimited(backoff(attempt)),
+        },
+        500..=599 if idempotent => Self::Backoff(backoff(attempt)),
+        _ => Self::Abort,
+      },
+      _ => Self::Abort,
+    }
+  }
+}
+
+/// Whether Spotify's accounts service refused to refresh the access token,
+/// meaning the refresh token was revoked and the user has to authorize again.
+pub(crate) fn is_revoked(err: &ClientError) -> bool {
+  let ClientError::Http(err) = err else {
+    return false;
+  };
+
+  match err.as_ref() {
+    HttpError::StatusCode(response) => {
+      matches!(response.status().as_u16(), 400 | 401)
+        && response.url().host_str() == Some(ACCOUNTS_HOST)
+    }
+    _ => false,
+  }
+}
+
+/// The `Retry-After` header of a rate-limited response, if any.
+pub(crate) fn retry_after(err: &HttpError) -> Option<Duration> {
+  let HttpError::StatusCode(response) = err else {
+    return None;
+  };
+
+  response
+    .headers()
+    .get("retry-after")?
+    .to_str()
+    .ok()?
+    .trim()
+    .parse()
+    .ok()
+    .map(Duration::from_secs)
+}
+
+/// Exponential backoff with full jitter, capped at [`MAX_DELAY`].
+fn backoff(attempt: u32) -> Duration {
+  let ceiling = BASE_DELAY
+    .saturating_mul(2u32.saturating_pow(attempt))
+    .min(MAX_DELAY);
+
+  let millis = rand::thread_rng().gen_range(0..=ceiling.as_millis() as u64);
+  Duration::from_millis(millis)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::backend::fake::{network_error, status_error};
+
+  const API_HOST: &str = "api.spotify.com";
+
+  #[test]
+  fn rate_limited_requests_wait_as_asked() {
+    let err = status_error(API_HOST, 429, Some("3"));
+    for idempotent in [true, false] {
+      assert_eq!(
+        Retry::from_error(&err, 0, idempotent),
+        Retry::RateLimited(Duration::from_secs(3))
+      );
+    }
+
+    // too long to block the others
+    let err = status_error(API_HOST, 429, Some("120"));
+    assert_eq!(Retry::from_error(&err, 0, true), Retry::Abort);
+
+    let err = status_error(API_HOST, 429, None);
+    let Retry::RateLimited(delay) = Retry::from_error(&err, 2, false) else {
+      panic!("not retried");
+    };
+    assert!(delay <= BASE_DELAY * 4);
+  }
+
+  #[test]
+  fn transient_failures_are_retried_if_idempotent() {
+    for err in [status_error(API_HOST, 503, None), network_error()] {
+      assert!(matches!(
+        Retry::from_error(&err, 0, true),
+        Retry::Backoff(_)
+      ));
+      assert_eq!(Retry::from_error(&err, 0, false), Retry::Abort);
+    }
+
+    let err = status_error(API_HOST, 404, None);
+    assert_eq!(Retry::from_error(&err, 0, true), Retry::Abort);
+  }
+
+  #[test]
+  fn rejected_tokens_are_refreshed() {
+    let err = status_error(API_HOST, 401, None);
+    assert_eq!(Retry::from_error(&err, 0, false), Retry::Refresh);
+    assert_eq!(
+      Retry::from_error(&ClientError::InvalidToken, 0, false),
+      Retry::Refresh
+    );
+  }
+
+  #[test]
+  fn requests_are_given_up_after_the_last_attempt() {
+    let errors = [
+      status_error(API_HOST, 429, Some("1")),
+      status_error(API_HOST, 503, None),
+      network_error(),
+    ];
+
+    for err in errors {
+      assert_ne!(
+        Retry::from_error(&err, MAX_ATTEMPTS - 2, true),
+        Retry::Abort
+      );
+      assert_eq!(
+        Retry::from_error(&err, MAX_ATTEMPTS - 1, true),
+        Retry::Abort
+      );
+    }
+  }
+
+  #[test]
+  fn only_the_accounts_service_revokes_sessions() {
+    for status in [400, 401] {
+      let err = status_error(ACCOUNTS_HOST, status, None);
+      assert!(is_revoked(&err));
+      assert_eq!(Retry::from_error(&err, 0, true), Retry::Abort);
+
+      assert!(!is_revoked(&status_error(API_HOST, status, None)));
+    }
+
+    assert!(!is_revoked(&status_error(ACCOUNTS_HOST, 503, None)));
+    assert!(!is_revoked(&network_error()));
+  }
+
+  #[test]
+  fn backoffs_stay_under_their_ceiling() {
+    for attempt in 0..40 {
+      let ceiling = (BASE_DELAY * 2u32.saturating_pow(attempt)).min(MAX_DELAY);
+      for _ in 0..100 {
+        assert!(backoff(attempt) <= ceiling);
+      }
+    }
+  }
+}
@@ -0,0 +1,50 @@
+//! What to play, from a Spotify URI given by the user.
+
+use rspotify::{
+  AuthCodePkceSpotify as Spotify, ClientResult,
+  model::{AlbumId, ArtistId, EpisodeId, PlayContextId, PlayableId, PlaylistId, ShowId, TrackId},
+  prelude::OAuthClient,
+};
+
+/// Either a context played from its start, or a single item.
+#[derive(Debug, Clone)]
+pub(crate) enum PlayTarget {
+  Context(PlayContextId<'static>),
+  Item(PlayableId<'static>),
+}
+
+impl PlayTarget {
+  /// Parses URIs like `spotify:track:<id>`, returning `None`
+  /// if it's malformed or can't be played.
+  pub fn parse(uri: &str) -> Option<Self> {
+    let kind = uri.split(':').nth(1)?;
+
+    let target = match kind {
+      "track" => Self::Item(TrackId::from_uri(uri).ok()?.into_static().into()),
+      "episode" => Self::Item(EpisodeId::from_uri(uri).ok()?.into_static().into()),
+      "album" => Self::Context(AlbumId::from_uri(uri).ok()?.into_static().into()),
+      "artist" => Self::Context(ArtistId::from_uri(uri).ok()?.into_static().into()),
+      "playlist" => Self::Context(PlaylistId::from_uri(uri).ok()?.into_static().into()),
+      "show" => Self::Context(ShowId::from_uri(uri).ok()?.into_static().into()),
+      _ => return None,
+    };
+
+    Some(target)
+  }
+
+  /// Starts playing the target on the active device.
+  pub async fn play(&self, spotify: &Spotify) -> ClientResult<()> {
+    match self {
+      Self::Context(id) => {
+        spotify
+          .start_context_playback(id.as_ref(), None, None, None)
+          .await
+      }
+      Self::Item(id) => {
+        spotify
+          .start_uris_playback([id.as_ref()], None, None, None)
+          .await
+      }
+    }
+  }
+}
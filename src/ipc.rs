@@ -0,0 +1,474 @@
+//! Control socket of a running instance.
+//!
+//! Orpheus listens on a Unix socket at the [profile's](crate::auth::profile::Profile::socket_path)
+//! path for newline-delimited JSON requests, each answered with a line:
+//!
+//! ```text
+//! > {"id": 1, "command": "play", "uri": "spotify:track:4uLU6hMCjMI75M1A2tKUQC"}
+//! < {"id": 1, "ok": true}
+//! ```
+//!
+//! The commands are `play` (with an optional `uri`), `pause`, `next`, `previous`,
+//! `seek` (with `position_ms`), `queue` (with a track or episode `uri`),
+//! `add_to_playlist` (with a `playlist` and the `uris` of tracks or episodes),
+//! `like` (with a track `uri`), `follow` (with an artist `uri`), `state`,
+//! `subscribe` and `run` (with the `name` of a [script](crate::scripts) command).
+//! They're dispatched to the [io](crate::io::Io) like the TUI's own events, so
+//! `ok` means they were queued, while failures show up in the TUI.
+//!
+//! After `subscribe`, the connection also gets a line like
+//! `{"event": "playback", "state": {...}}` whenever the state changes, the
+//! event being `playback`, `seeked`, `profile` or `playlists`.
+
+use crate::{
+  auth::profile::Profile,
+  io::{Event, target::PlayTarget},
+  state::{Change, State},
+};
+use rspotify::model::{ArtistId, CurrentPlaybackContext, PlaylistId, TrackId};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{path::Path, sync::Arc};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+  net::{UnixListener, UnixStream, unix::OwnedWriteHalf},
+  sync::{
+    Mutex,
+    broadcast::{self, error::RecvError},
+  },
+  task::JoinHandle,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Command {
+  Play {
+    #[serde(default)]
+    uri: Option<String>,
+  },
+  Pause,
+  Next,
+  Previous,
+  Seek {
+    position_ms: u32,
+  },
+  Queue {
+    uri: String,
+  },
+  AddToPlaylist {
+    playlist: String,
+    uris: Vec<String>,
+  },
+  Like {
+    uri: String,
+  },
+  Follow {
+    uri: String,
+  },
+  State,
+  Subscribe,
+  /// Runs a command of the [scripts](crate::scripts).
+  Run {
+    name: String,
+  },
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Response {
+  /// The `id` of the request, if it had one.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  id: Option<Value>,
+  ok: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  state: Option<Snapshot>,
+}
+
+/// What's exposed of the [state](crate::state::State).
+#[derive(Debug, Serialize)]
+struct Snapshot {
+  profile: String,
+  playback: Option<CurrentPlaybackContext>,
+}
+
+#[derive(Debug, Serialize)]
+struct Notification {
+  event: &'static str,
+  state: Snapshot,
+}
+
+/// Starts listening on the socket of `profile`, unless another instance already is.
+pub(crate) fn spawn(profile: &Profile, state: &Arc<Mutex<State>>) -> Option<JoinHandle<()>> {
+  let path = match profile.socket_path() {
+    Ok(path) => path,
+    Err(err) => {
+      tracing::warn!("not listening for IPC: {err}");
+      return None;
+    }
+  };
+
+  let listener = match bind(&path) {
+    Ok(listener) => listener,
+    Err(err) => {
+      tracing::warn!("not listening for IPC on {}: {err}", path.display());
+      return None;
+    }
+  };
+
+  tracing::info!("listening for IPC on {}", path.display());
+  let state = state.clone();
+
+  Some(tokio::spawn(async move {
+    loop {
+      match listener.accept().await {
+        Ok((stream, _)) => {
+          let state = state.clone();
+          tokio::spawn(async move {
+            if let Err(err) = handle(stream, state).await {
+              tracing::warn!("IPC connection closed: {err}");
+            }
+          });
+        }
+        Err(err) => tracing::warn!("failed to accept an IPC connection: {err}"),
+      }
+    }
+  }))
+}
+
+/// Binds the socket at `path`, replacing the one left by an instance that's gone.
+fn bind(path: &Path) -> std::io::Result<UnixListener> {
+  if path.exists() {
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::AddrInUse,
+        "another instance is running",
+      ));
+    }
+    std::fs::remove_file(path)?;
+  }
+
+  if let Some(parent) = path.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+
+  let listener = UnixListener::bind(path)?;
+
+  use std::os::unix::fs::PermissionsExt;
+  std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+
+  Ok(listener)
+}
+
+async fn handle(stream: UnixStream, state: Arc<Mutex<State>>) -> std::io::Result<()> {
+  let (reader, mut writer) = stream.into_split();
+  let mut lines = BufReader::new(reader).lines();
+  let mut changes = None;
+
+  loop {
+    tokio::select! {
+      line = lines.next_line() => {
+        let Some(line) = line? else {
+          return Ok(());
+        };
+        if line.trim().is_empty() {
+          continue;
+        }
+
+        let (response, subscribe) = respond(&line, &state).await;
+        if subscribe {
+          changes = Some(state.lock().await.subscribe());
+        }
+        write(&mut writer, &response).await?;
+      }
+
+      change = next_change(&mut changes) => match change {
+        Ok(change) => {
+          let event = match change {
+            Change::Playback => "playback",
+            Change::Seeked => "seeked",
+            Change::Profile => "profile",
+            Change::Playlists => "playlists",
+          };
+          let state = snapshot(&state).await;
+          write(&mut writer, &Notification { event, state }).await?;
+        }
+        Err(RecvError::Lagged(_)) => {}
+        Err(RecvError::Closed) => changes = None,
+      },
+    }
+  }
+}
+
+async fn next_change(
+  changes: &mut Option<broadcast::Receiver<Change>>,
+) -> Result<Change, RecvError> {
+  match changes {
+    Some(changes) => changes.recv().await,
+    None => std::future::pending().await,
+  }
+}
+
+/// Answers the request in `line`, and whether it subscribed to the changes.
+async fn respond(line: &str, state: &Arc<Mutex<State>>) -> (Response, bool) {
+  let request = serde_json::from_str::<Value>(line);
+  let id = request
+    .as_ref()
+    .ok()
+    .and_then(|request| request.get("id").cloned());
+
+  let command = request.and_then(serde_json::from_value::<Command>);
+  let result = match command {
+    Ok(command) => execute(command, state).await,
+    Err(err) => Err(format!("Invalid request: {err}")),
+  };
+
+  let subscribe = matches!(result, Ok(Outcome::Subscribed));
+  let response = match result {
+    Ok(Outcome::State(snapshot)) => Response {
+      id,
+      ok: true,
+      state: Some(*snapshot),
+      ..Default::default()
+    },
+    Ok(_) => Response {
+      id,
+      ok: true,
+      ..Default::default()
+    },
+    Err(error) => Response {
+      id,
+      ok: false,
+      error: Some(error),
+      ..Default::default()
+    },
+  };
+
+  (response, subscribe)
+}
+
+enum Outcome {
+  Dispatched,
+  State(Box<Snapshot>),
+  Subscribed,
+}
+
+async fn execute(command: Command, state: &Arc<Mutex<State>>) -> Result<Outcome, String> {
+  let event = match command {
+    Command::State => return Ok(Outcome::State(Box::new(snapshot(state).await))),
+    Command::Subscribe => return Ok(Outcome::Subscribed),
+    Command::Run { name } => {
+      state.lock().await.run_command(&name);
+      return Ok(Outcome::Dispatched);
+    }
+
+    Command::Play { uri: None } => Event::Play(None),
+    Command::Play { uri: Some(uri) } => Event::Play(Some(parse(&uri)?)),
+    Command::Pause => Event::Pause,
+    Command::Next => Event::NextTrack,
+    Command::Previous => Event::PreviousTrack,
+    Command::Seek { position_ms } => Event::Seek(position_ms),
+    Command::Queue { uri } => match parse(&uri)? {
+      PlayTarget::Item(id) => Event::Queue(id),
+      PlayTarget::Context(_) => return Err("Only tracks and episodes can be queued".to_string()),
+    },
+
+    Command::AddToPlaylist { playlist, uris } => {
+      let playlist = PlaylistId::from_id_or_uri(&playlist)
+        .map_err(|_| format!("'{playlist}' is not a Spotify playlist"))?;
+      let items = uris
+        .iter()
+        .map(|uri| match parse(uri)? {
+          PlayTarget::Item(id) => Ok(id),
+          PlayTarget::Context(_) => Err(format!("'{uri}' is not a track or episode")),
+        })
+        .collect::<Result<_, _>>()?;
+      Event::AddToPlaylist(playlist.into_static(), items)
+    }
+    Command::Like { uri } => match TrackId::from_id_or_uri(&uri) {
+      Ok(id) => Event::SaveTrack(id.into_static()),
+      Err(_) => return Err(format!("'{uri}' is not a Spotify track")),
+    },
+    Command::Follow { uri } => match ArtistId::from_id_or_uri(&uri) {
+      Ok(id) => Event::FollowArtist(id.into_static()),
+      Err(_) => return Err(format!("'{uri}' is not a Spotify artist")),
+    },
+  };
+
+  state.lock().await.dispatch(event);
+  Ok(Outcome::Dispatched)
+}
+
+fn parse(uri: &str) -> Result<PlayTarget, String> {
+  PlayTarget::parse(uri).ok_or_else(|| format!("'{uri}' is not a playable Spotify URI"))
+}
+
+async fn snapshot(state: &Arc<Mutex<State>>) -> Snapshot {
+  let state = state.lock().await;
+  Snapshot {
+    profile: state.profile.name().to_string(),
+    playback: state.current_playback_context.clone(),
+  }
+}
+
+async fn write<T: Serialize>(writer: &mut OwnedWriteHalf, message: &T) -> std::io::Result<()> {
+  let mut line = serde_json::to_string(message)?;
+  line.push('\n');
+  writer.write_all(line.as_bytes()).await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{config::Config, io::backend::fake};
+  use std::sync::mpsc::{Receiver, channel};
+  use tempfile::TempDir;
+  use tokio::{
+    io::Lines,
+    net::unix::{OwnedReadHalf, OwnedWriteHalf},
+  };
+
+  /// A client of the server of a profile of its own, with the events it dispatches.
+  struct Client {
+    _dir: TempDir,
+    _server: JoinHandle<()>,
+    state: Arc<Mutex<State>>,
+    events: Receiver<Event>,
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+  }
+
+  impl Client {
+    async fn connect() -> Self {
+      let dir = TempDir::new().unwrap();
+      let profile = Profile::rooted(dir.path());
+      let (sender, events) = channel();
+      let state = Arc::new(Mutex::new(State::new(
+        Config::default(),
+        profile.clone(),
+        sender,
+      )));
+
+      let server = spawn(&profile, &state).unwrap();
+      let stream = UnixStream::connect(profile.socket_path().unwrap())
+        .await
+        .unwrap();
+      let (reader, writer) = stream.into_split();
+
+      Self {
+        _dir: dir,
+        _server: server,
+        state,
+        events,
+        lines: BufReader::new(reader).lines(),
+        writer,
+      }
+    }
+
+    /// Sends the `line`, returning the next one received.
+    async fn send(&mut self, line: &str) -> Value {
+      self
+        .writer
+        .write_all(format!("{line}\n").as_bytes())
+        .await
+        .unwrap();
+      self.receive().await
+    }
+
+    async fn receive(&mut self) -> Value {
+      let line = self.lines.next_line().await.unwrap().unwrap();
+      serde_json::from_str(&line).unwrap()
+    }
+  }
+
+  #[tokio::test]
+  async fn commands_are_dispatched_to_the_io() {
+    let mut client = Client::connect().await;
+
+    let response = client.send(r#"{"id": 1, "command": "pause"}"#).await;
+    assert_eq!(response, serde_json::json!({"id": 1, "ok": true}));
+    assert!(matches!(client.events.try_recv(), Ok(Event::Pause)));
+
+    let response = client
+      .send(r#"{"id": "seek", "command": "seek", "position_ms": 1000}"#)
+      .await;
+    assert_eq!(response, serde_json::json!({"id": "seek", "ok": true}));
+    assert!(matches!(client.events.try_recv(), Ok(Event::Seek(1000))));
+
+    // blank lines are skipped, and requests need no id
+    let response = client
+      .send("\n{\"command\": \"queue\", \"uri\": \"spotify:track:4uLU6hMCjMI75M1A2tKUQC\"}")
+      .await;
+    assert_eq!(response, serde_json::json!({"ok": true}));
+    assert!(matches!(client.events.try_recv(), Ok(Event::Queue(_))));
+  }
+
+  #[tokio::test]
+  async fn malformed_requests_are_answered_with_errors() {
+    let mut client = Client::connect().await;
+
+    let requests = [
+      ("not json", "Invalid request"),
+      (r#"{"id": 2, "command": "rewind"}"#, "Invalid request"),
+      (r#"{"id": 3, "command": "seek"}"#, "Invalid request"),
+      (
+        r#"{"id": 4, "command": "queue", "uri": "spotify:album:4aawyAB9vmqN3uQ7FjRGTy"}"#,
+        "Only tracks and episodes can be queued",
+      ),
+      (
+        r#"{"id": 5, "command": "like", "uri": "spotify:artist:0OdUWJ0sBjDrqHygGUXeCF"}"#,
+        "is not a Spotify track",
+      ),
+      (
+        r#"{"id": 6, "command": "play", "uri": "https://example.com"}"#,
+        "is not a playable Spotify URI",
+      ),
+    ];
+
+    for (request, error) in requests {
+      let response = client.send(request).await;
+      assert_eq!(response["ok"], false, "{request}");
+      assert!(
+        response["error"].as_str().unwrap().contains(error),
+        "{response}"
+      );
+    }
+    assert_eq!(
+      client.send(r#"{"id": 7, "command": "rewind"}"#).await["id"],
+      7
+    );
+    assert!(client.events.try_recv().is_err());
+
+    // the connection is still usable
+    let response = client.send(r#"{"command": "state"}"#).await;
+    assert_eq!(
+      response,
+      serde_json::json!({"ok": true, "state": {"profile": "default", "playback": null}})
+    );
+  }
+
+  #[tokio::test]
+  async fn subscriptions_get_the_changes() {
+    let mut client = Client::connect().await;
+
+    let response = client.send(r#"{"command": "subscribe"}"#).await;
+    assert_eq!(response, serde_json::json!({"ok": true}));
+
+    let playback = fake::playback(fake::track("first", "First"), true);
+    client.state.lock().await.set_playback(Some(playback));
+
+    let notification = client.receive().await;
+    assert_eq!(notification["event"], "playback");
+    assert_eq!(notification["state"]["playback"]["item"]["name"], "First");
+  }
+
+  #[tokio::test]
+  async fn a_second_instance_of_the_profile_isnt_served() {
+    let client = Client::connect().await;
+    let profile = client.state.lock().await.profile.clone();
+
+    assert!(spawn(&profile, &client.state).is_none());
+    // still serving the first one
+    assert!(profile.socket_path().unwrap().exists());
+  }
+}
@@ -0,0 +1,423 @@
+//! Lyrics of the playing track, from local files.
+//!
+//! They're looked up in the `[lyrics]` [directory](crate::config::LyricsConfig::dir)
+//! whenever the track changes, by the first file named after, in that order:
+//!
+//! - its ISRC, like `USUM71703861.lrc`;
+//! - its Spotify id, like `6rqhFgbbKwnb9MLmUQDhG6.lrc`;
+//! - its artist and title, like `Daft Punk - One More Time.lrc`, compared
+//!   without case, punctuation or extra spaces.
+//!
+//! [LRC](https://en.wikipedia.org/wiki/LRC_(file_format)) files are synced, the
+//! line being sung highlighted as the track plays. A `.txt` file is shown as is,
+//! scrolled through as the track plays.
+
+use crate::state::{Change, State};
+use rspotify::{
+  model::{FullTrack, PlayableItem},
+  prelude::Id,
+};
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+use tokio::{
+  sync::{Mutex, broadcast::error::RecvError},
+  task::JoinHandle,
+};
+
+/// Extensions of the lyrics files, the synced first.
+const EXTENSIONS: [&str; 2] = ["lrc", "txt"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Lyrics {
+  /// URI of the track they're the lyrics of.
+  pub uri: String,
+  pub lines: Vec<LyricsLine>,
+  /// Whether the lines have timestamps.
+  pub is_synced: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LyricsLine {
+  /// When the line starts, in milliseconds into the track.
+  pub time: Option<u32>,
+  pub text: String,
+}
+
+impl Lyrics {
+  /// Parses an LRC file, whose lines without timestamps are left out unless none has one.
+  pub fn parse(uri: String, text: &str) -> Self {
+    let mut offset = 0;
+    let mut lines = vec![];
+
+    for line in text.lines() {
+      let (times, text) = timestamps(line.trim());
+
+      if times.is_empty() {
+        // metadata like [ar:Daft Punk], where only the offset matters
+        if let Some(value) = tag(line.trim(), "offset") {
+          offset = value.trim().parse().unwrap_or(0);
+          continue;
+        }
+        if tag(line.trim(), "").is_none() {
+          lines.push(LyricsLine {
+            time: None,
+            text: text.to_string(),
+          });
+        }
+        continue;
+      }
+
+      let text = strip_word_timestamps(text);
+      for time in times {
+        // a positive offset shows the lines sooner
+        let time = (time as i64 - offset).max(0) as u32;
+        lines.push(LyricsLine {
+          time: Some(time),
+          text: text.clone(),
+        });
+      }
+    }
+
+    let is_synced = lines.iter().any(|line| line.time.is_some());
+    if is_synced {
+      lines.retain(|line| line.time.is_some());
+      lines.sort_by_key(|line| line.time);
+    }
+
+    Self {
+      uri,
+      lines,
+      is_synced,
+    }
+  }
+
+  /// Plain text lyrics, not synced.
+  pub fn plain(uri: String, text: &str) -> Self {
+    Self {
+      uri,
+      lines: text
+        .lines()
+        .map(|line| LyricsLine {
+          time: None,
+          text: line.trim_end().to_string(),
+        })
+        .collect(),
+      is_synced: false,
+    }
+  }
+
+  /// Index of the line sung `progress_ms` into the track.
+  pub fn current(&self, progress_ms: u32) -> Option<usize> {
+    if !self.is_synced {
+      return None;
+    }
+
+    let next = self
+      .lines
+      .partition_point(|line| line.time.is_some_and(|time| time <= progress_ms));
+    next.checked_sub(1)
+  }
+
+  /// First line to show in `height` rows, `progress_ms` into a track of `duration_ms`.
+  ///
+  /// The line being sung is kept in the middle, while plain lyrics, which can't
+  /// follow the singing, go by at the pace of the track.
+  pub fn scroll(&self, progress_ms: u32, duration_ms: u32, height: usize) -> usize {
+    match self.current(progress_ms) {
+      Some(current) => current.saturating_sub(height / 2),
+      None if self.is_synced || duration_ms == 0 => 0,
+      None => {
+        let hidden = self.lines.len().saturating_sub(height);
+        hidden * progress_ms.min(duration_ms) as usize / duration_ms as usize
+      }
+    }
+  }
+}
+
+/// Starts loading the lyrics of the tracks played in `state` from `dir`.
+pub(crate) fn spawn(dir: PathBuf, state: &Arc<Mutex<State>>) -> JoinHandle<()> {
+  let state = state.clone();
+
+  tokio::spawn(async move {
+    let mut changes = state.lock().await.subscribe();
+    let mut loaded: Option<String> = None;
+
+    loop {
+      let track = {
+        let state = state.lock().await;
+        match state
+          .current_playback_context
+          .as_ref()
+          .and_then(|context| context.item.as_ref())
+        {
+          Some(PlayableItem::Track(track)) => Some(track.clone()),
+          _ => None,
+        }
+      };
+      let uri = track.as_ref().map(uri);
+
+      if uri != loaded {
+        let lyrics = match &track {
+          Some(track) => load(&dir, track).await,
+          None => None,
+        };
+        state.lock().await.lyrics = lyrics;
+        loaded = uri;
+      }
+
+      loop {
+        match changes.recv().await {
+          Ok(Change::Playlists | Change::Seeked) => continue,
+          // the lyrics of the previous account were cleared
+          Ok(Change::Profile) => loaded = None,
+          Ok(Change::Playback) | Err(RecvError::Lagged(_)) => {}
+          Err(RecvError::Closed) => return,
+        }
+        break;
+      }
+    }
+  })
+}
+
+/// The URI of `track`, or its name for local files.
+pub(crate) fn uri(track: &FullTrack) -> String {
+  match &track.id {
+    Some(id) => id.uri(),
+    None => track.name.clone(),
+  }
+}
+
+async fn load(dir: &Path, track: &FullTrack) -> Option<Lyrics> {
+  let path = find(dir, track).await?;
+  let text = match tokio::fs::read_to_string(&path).await {
+    Ok(text) => text,
+    Err(err) => {
+      tracing::warn!("failed to read the lyrics {}: {err}", path.display());
+      return None;
+    }
+  };
+
+  tracing::info!("loaded the lyrics {}", path.display());
+  match path.extension().is_some_and(|extension| extension == "lrc") {
+    true => Some(Lyrics::parse(uri(track), &text)),
+    false => Some(Lyrics::plain(uri(track), &text)),
+  }
+}
+
+/// The lyrics file of `track` in `dir`.
+async fn find(dir: &Path, track: &FullTrack) -> Option<PathBuf> {
+  // files by extension and normalized name
+  let mut files: HashMap<(String, String), PathBuf> = HashMap::new();
+  let mut entries = tokio::fs::read_dir(dir).await.ok()?;
+  while let Ok(Some(entry)) = entries.next_entry().await {
+    let path = entry.path();
+    let (Some(stem), Some(extension)) = (path.file_stem(), path.extension()) else {
+      continue;
+    };
+    let extension = extension.to_string_lossy().to_lowercase();
+    files.insert((extension, normalize(&stem.to_string_lossy())), path);
+  }
+
+  let artists: Vec<&str> = track.artists.iter().map(|a| a.name.as_str()).collect();
+  let names = [
+    track.external_ids.get("isrc").cloned(),
+    track.id.as_ref().map(|id| id.id().to_string()),
+    artists
+      .first()
+      .map(|artist| format!("{artist} - {}", track.name)),
+    Some(format!("{} - {}", artists.join(", "), track.name)),
+  ];
+
+  EXTENSIONS.iter().find_map(|extension| {
+    names.iter().flatten().find_map(|name| {
+      files
+        .get(&(extension.to_string(), normalize(name)))
+        .cloned()
+    })
+  })
+}
+
+/// Lowercase letters and digits, the words separated by a single space.
+fn normalize(name: &str) -> String {
+  name
+    .to_lowercase()
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|word| !word.is_empty())
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// The timestamps at the start of `line`, like `[01:02.50]`, in milliseconds, and the rest of it.
+fn timestamps(mut line: &str) -> (Vec<u32>, &str) {
+  let mut times = vec![];
+
+  while let Some(rest) = line.strip_prefix('[')
+    && let Some((stamp, rest)) = rest.split_once(']')
+    && let Some(time) = parse_time(stamp)
+  {
+    times.push(time);
+    line = rest;
+  }
+
+  (times, line.trim())
+}
+
+/// `mm:ss`, `mm:ss.xx` or `mm:ss.xxx` in milliseconds.
+fn parse_time(stamp: &str) -> Option<u32> {
+  let (minutes, seconds) = stamp.split_once(':')?;
+  let minutes: u32 = minutes.trim().parse().ok()?;
+  let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, "0"));
+  let seconds: u32 = seconds.trim().parse().ok()?;
+
+  // some editors write `[01:02.]` for a whole second
+  let fraction = match fraction {
+    "" => "0",
+    fraction => fraction,
+  };
+  let digits = fraction.len().min(3);
+  let fraction: u32 = fraction.get(..digits)?.parse().ok()?;
+  let millis = fraction * 10u32.pow(3 - digits as u32);
+
+  Some((minutes * 60 + seconds) * 1000 + millis)
+}
+
+/// The value of an ID tag like `[offset:+500]`, or of any tag when `name` is empty.
+fn tag<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+  let (key, value) = line.strip_prefix('[')?.strip_suffix(']')?.split_once(':')?;
+  (name.is_empty() || key.trim().eq_ignore_ascii_case(name)).then_some(value)
+}
+
+/// Removes the word timestamps of enhanced LRC, like `<00:12.30>`.
+fn strip_word_timestamps(text: &str) -> String {
+  let mut stripped = String::with_capacity(text.len());
+  let mut rest = text;
+
+  while let Some(start) = rest.find('<') {
+    match rest[start..].find('>') {
+      Some(end) if parse_time(&rest[start + 1..start + end]).is_some() => {
+        stripped.push_str(&rest[..start]);
+        rest = &rest[start + end + 1..];
+      }
+      _ => {
+        stripped.push_str(&rest[..=start]);
+        rest = &rest[start + 1..];
+      }
+    }
+  }
+  stripped.push_str(rest);
+
+  stripped.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn times(lyrics: &Lyrics) -> Vec<(Option<u32>, &str)> {
+    lyrics
+      .lines
+      .iter()
+      .map(|line| (line.time, line.text.as_str()))
+      .collect()
+  }
+
+  #[test]
+  fn timestamps_take_fractions_of_up_to_3_digits() {
+    assert_eq!(parse_time("01:02"), Some(62_000));
+    assert_eq!(parse_time("01:02.5"), Some(62_500));
+    assert_eq!(parse_time("01:02.50"), Some(62_500));
+    assert_eq!(parse_time("01:02.505"), Some(62_505));
+    assert_eq!(parse_time("01:02."), Some(62_000));
+    assert_eq!(parse_time("ar:Daft Punk"), None);
+    assert_eq!(parse_time("01:xx.00"), None);
+  }
+
+  #[test]
+  fn lines_with_several_timestamps_are_repeated_in_order() {
+    let lyrics = Lyrics::parse(
+      "uri".to_string(),
+      "[00:10.00][00:30.00]Chorus\n[00:20.00]Verse\n[00:40.]Outro",
+    );
+
+    assert!(lyrics.is_synced);
+    assert_eq!(
+      times(&lyrics),
+      [
+        (Some(10_000), "Chorus"),
+        (Some(20_000), "Verse"),
+        (Some(30_000), "Chorus"),
+        (Some(40_000), "Outro"),
+      ]
+    );
+  }
+
+  #[test]
+  fn metadata_is_dropped_and_the_offset_applied() {
+    let text = "[ar:Daft Punk]\n[ti:One More Time]\n[offset:+500]\n[00:01.00]One\n[00:10.00]More";
+    let lyrics = Lyrics::parse("uri".to_string(), text);
+    assert_eq!(times(&lyrics), [(Some(500), "One"), (Some(9_500), "More")]);
+
+    let lyrics = Lyrics::parse("uri".to_string(), "[offset:-250]\n[00:01.00]One");
+    assert_eq!(times(&lyrics), [(Some(1_250), "One")]);
+  }
+
+  #[test]
+  fn word_timestamps_are_stripped() {
+    let lyrics = Lyrics::parse(
+      "uri".to_string(),
+      "[00:12.00]<00:12.00> One <00:12.50> more <00:13.00> time <3",
+    );
+    assert_eq!(times(&lyrics), [(Some(12_000), "One more time <3")]);
+  }
+
+  #[test]
+  fn lines_without_timestamps_are_kept_if_none_has_one() {
+    let lyrics = Lyrics::parse("uri".to_string(), "[ar:Daft Punk]\nOne\nMore");
+
+    assert!(!lyrics.is_synced);
+    assert_eq!(times(&lyrics), [(None, "One"), (None, "More")]);
+    assert_eq!(lyrics.current(5_000), None);
+  }
+
+  #[test]
+  fn the_current_line_is_the_last_started() {
+    let lyrics = Lyrics::parse("uri".to_string(), "[00:10.00]One\n[00:20.00]More");
+
+    assert_eq!(lyrics.current(0), None);
+    assert_eq!(lyrics.current(10_000), Some(0));
+    assert_eq!(lyrics.current(19_999), Some(0));
+    assert_eq!(lyrics.current(60_000), Some(1));
+  }
+
+  #[test]
+  fn plain_lyrics_scroll_with_the_track() {
+    let text = (0..30).map(|i| i.to_string()).collect::<Vec<_>>();
+    let lyrics = Lyrics::plain("uri".to_string(), &text.join("\n"));
+
+    assert_eq!(lyrics.scroll(0, 100_000, 10), 0);
+    assert_eq!(lyrics.scroll(50_000, 100_000, 10), 10);
+    assert_eq!(lyrics.scroll(200_000, 100_000, 10), 20);
+    assert_eq!(lyrics.scroll(50_000, 100_000, 40), 0);
+  }
+
+  #[test]
+  fn synced_lyrics_keep_the_current_line_in_the_middle() {
+    let text: Vec<String> = (0..30).map(|i| format!("[00:{i:02}.00]{i}")).collect();
+    let lyrics = Lyrics::parse("uri".to_string(), &text.join("\n"));
+
+    assert_eq!(lyrics.scroll(3_000, 100_000, 10), 0);
+    assert_eq!(lyrics.scroll(20_000, 100_000, 10), 15);
+  }
+
+  #[test]
+  fn names_are_compared_without_case_or_punctuation() {
+    assert_eq!(
+      normalize("Daft Punk - One  More Time (Radio Edit)"),
+      "daft punk one more time radio edit"
+    );
+    assert_eq!(normalize("AC/DC — T.N.T."), "ac dc t n t");
+  }
+}
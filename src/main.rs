@@ -1,22 +1,49 @@
 mod auth;
+mod cli;
 mod config;
+mod hooks;
+mod images;
 mod io;
+#[cfg(unix)]
+mod ipc;
+mod lyrics;
+#[cfg(all(target_os = "linux", feature = "mpris"))]
+mod mpris;
+#[cfg(all(target_os = "linux", feature = "notifications"))]
+mod notifications;
+mod now_playing;
+mod onboarding;
+#[cfg(all(
+  test,
+  target_os = "linux",
+  any(feature = "mpris", feature = "notifications")
+))]
+mod private_bus;
+mod scripts;
 mod state;
 mod terminal;
 mod ui;
 
 use crate::{
+  auth::{AuthError, profile::Profile},
+  cli::Cli,
   config::Config,
-  io::{Event, Io},
+  io::{Event, Io, IoError},
+  scripts::Scripts,
   state::State,
+  terminal::Tui,
 };
+use clap::Parser;
+use rspotify::AuthCodePkceSpotify;
 use std::sync::{
   Arc,
   mpsc::{Receiver, channel},
 };
 use thiserror::Error;
-use tokio::sync::Mutex;
-use tracing_subscriber;
+use tokio::sync::{
+  Mutex,
+  mpsc::{UnboundedReceiver, unbounded_channel},
+};
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -26,8 +53,57 @@ pub enum Error {
 
 #[tokio::main]
 async fn main() {
-  let spotify = match auth::authenticate().await {
-    Ok(client) => client,
+  let cli = Cli::parse();
+
+  let profile = match Profile::named(&cli.profile) {
+    Ok(profile) => profile,
+    Err(e) => {
+      eprintln!("{}", e);
+      std::process::exit(1);
+    }
+  };
+
+  let mut config = match Config::load(&profile) {
+    Ok(config) => config,
+    Err(e) => {
+      eprintln!("Failed to load config: {}", e);
+      std::process::exit(1);
+    }
+  };
+
+  config.merge(&cli);
+
+  if let Some(command) = &cli.command {
+    if let Err(e) = command.run(&profile, &config).await {
+      eprintln!("{}", e);
+      std::process::exit(e.exit_code());
+    }
+    return;
+  }
+
+  let (spotify, tui) = match onboarding::is_needed(&profile) {
+    Ok(true) => match onboard(&profile, &config).await {
+      Some((client, tui)) => (client, Some(tui)),
+      None => return,
+    },
+    Ok(false) => match auth::authenticate(&profile, &config.auth).await {
+      Ok(client) => (client, None),
+      // the cached library is shown until Spotify can be reached to authorize
+      Err(AuthError::Offline(e)) => match auth::open(&profile).await {
+        Ok(client) => {
+          eprintln!("Could not reach Spotify ({e}), starting offline");
+          (client, None)
+        }
+        Err(e) => {
+          eprintln!("Authentication failed: {}", e);
+          std::process::exit(1);
+        }
+      },
+      Err(e) => {
+        eprintln!("Authentication failed: {}", e);
+        std::process::exit(1);
+      }
+    },
     Err(e) => {
       eprintln!("Authentication failed: {}", e);
       std::process::exit(1);
@@ -46,20 +122,103 @@ async fn main() {
 
   let (sender, receiver) = channel::<Event>();
 
-  let config = Config::default();
-  let state = Arc::new(Mutex::new(State::new(config, sender)));
-  let outer_state = state.clone();
+  let scripts = Scripts::load(sender.clone());
+  let mut state = State::new(config.clone(), profile, sender);
 
-  std::thread::spawn(move || {
-    let mut io = Io::new(spotify, &state);
-    start(receiver, &mut io);
+  let scripts = scripts.map(|scripts| {
+    let (commands, receiver) = unbounded_channel();
+    state.set_scripts(scripts.bindings(), commands);
+    if !scripts.errors.is_empty() {
+      state.set_error(IoError::Scripts(scripts.errors.clone()));
+    }
+    (scripts, receiver)
   });
 
-  terminal::start(&config, &outer_state).await.unwrap();
+  let state = Arc::new(Mutex::new(state));
+  let outer_state = state.clone();
+
+  std::thread::spawn(move || start(receiver, spotify, &state, scripts));
+
+  let mut tui = tui.unwrap_or_else(|| Tui::enter(&config).unwrap());
+  terminal::start(&mut tui, &outer_state).await.unwrap();
+  tui.leave().unwrap();
+}
+
+/// Runs the [onboarding](crate::onboarding) in the TUI, which is kept
+/// for the main screen. Returns `None` if the user quit.
+async fn onboard(profile: &Profile, config: &Config) -> Option<(AuthCodePkceSpotify, Tui)> {
+  let mut tui = match Tui::enter(config) {
+    Ok(tui) => tui,
+    Err(e) => {
+      eprintln!("{}", e);
+      std::process::exit(1);
+    }
+  };
+
+  match onboarding::run(&mut tui, profile, config).await {
+    Ok(Some(client)) => Some((client, tui)),
+    Ok(None) => {
+      tui.leave().unwrap();
+      None
+    }
+    Err(e) => {
+      tui.leave().unwrap();
+      eprintln!("Onboarding failed: {}", e);
+      std::process::exit(1);
+    }
+  }
 }
 
 #[tokio::main]
-async fn start<'io>(receiver: Receiver<Event>, io: &mut Io) {
+async fn start(
+  receiver: Receiver<Event>,
+  spotify: AuthCodePkceSpotify,
+  state: &Arc<Mutex<State>>,
+  scripts: Option<(Scripts, UnboundedReceiver<String>)>,
+) {
+  let mut io = Io::new(spotify, state);
+  io.restore().await;
+
+  #[cfg(unix)]
+  let _server = {
+    let (socket, profile) = {
+      let state = state.lock().await;
+      (state.config.socket, state.profile.clone())
+    };
+    socket.then(|| ipc::spawn(&profile, state)).flatten()
+  };
+
+  #[cfg(all(target_os = "linux", feature = "mpris"))]
+  let _mpris = match state.lock().await.config.mpris {
+    true => Some(mpris::spawn(state)),
+    false => None,
+  };
+
+  #[cfg(all(target_os = "linux", feature = "notifications"))]
+  let _notifications = {
+    let (config, covers) = {
+      let state = state.lock().await;
+      let covers = state.profile.cache_dir().ok().map(|dir| dir.join("covers"));
+      (state.config.notifications, covers)
+    };
+    config
+      .enabled
+      .then(|| notifications::spawn(config, covers, state))
+  };
+
+  let now_playing = state.lock().await.config.now_playing.clone();
+  let _exporter = now_playing
+    .is_enabled()
+    .then(|| now_playing::spawn(now_playing, state));
+
+  let lyrics = state.lock().await.config.lyrics.dir();
+  let _lyrics = lyrics.map(|dir| lyrics::spawn(dir, state));
+
+  let hooks = state.lock().await.config.hooks.clone();
+  let _hooks = hooks.is_enabled().then(|| hooks::spawn(hooks, state));
+
+  let _scripts = scripts.map(|(scripts, commands)| scripts::spawn(scripts, commands, state));
+
   while let Ok(event) = receiver.recv() {
     io.handle_event(event).await
   }
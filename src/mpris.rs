@@ -0,0 +1,614 @@
+//! MPRIS interface, for desktop media keys, `playerctl` and status bars.
+//!
+//! Orpheus owns `org.mpris.MediaPlayer2.orpheus` on the session bus, or
+//! `org.mpris.MediaPlayer2.orpheus.instance<pid>` if another instance has it,
+//! and serves the `org.mpris.MediaPlayer2` and `org.mpris.MediaPlayer2.Player`
+//! interfaces at `/org/mpris/MediaPlayer2`.
+//!
+//! Methods are dispatched to the [io](crate::io::Io) like the TUI's own events,
+//! and properties are read from the [state](crate::state::State), with a
+//! `PropertiesChanged` signal whenever the playback changes and a `Seeked`
+//! one once the position is seen to jump in the polls.
+//!
+//! The bus comes from `DBUS_SESSION_BUS_ADDRESS`, so running orpheus under
+//! `dbus-run-session` keeps it on a private bus.
+
+use crate::{
+  io::{Event, target::PlayTarget},
+  state::{Change, State},
+};
+use chrono::Utc;
+use rspotify::{
+  model::{CurrentPlaybackContext, PlayableItem, RepeatState},
+  prelude::Id,
+};
+use std::{collections::HashMap, sync::Arc};
+use tokio::{
+  sync::{Mutex, broadcast::error::RecvError},
+  task::JoinHandle,
+};
+use zbus::{
+  Connection,
+  fdo::{self, RequestNameFlags},
+  interface,
+  object_server::SignalEmitter,
+  zvariant::{ObjectPath, OwnedValue, Value},
+};
+
+const NAME: &str = "org.mpris.MediaPlayer2.orpheus";
+const PATH: &str = "/org/mpris/MediaPlayer2";
+/// Track id of the spec for when nothing is playing.
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+/// Starts serving the interfaces on the session bus.
+pub(crate) fn spawn(state: &Arc<Mutex<State>>) -> JoinHandle<()> {
+  let state = state.clone();
+
+  tokio::spawn(async move {
+    let connection = match Connection::session().await {
+      Ok(connection) => connection,
+      Err(err) => {
+        tracing::warn!("not serving MPRIS: {err}");
+        return;
+      }
+    };
+
+    if let Err(err) = serve(&connection, state).await {
+      tracing::warn!("stopped serving MPRIS: {err}");
+    }
+  })
+}
+
+/// Serves the interfaces on `connection`, publishing the changes of `state` until it's dropped.
+pub(crate) async fn serve(connection: &Connection, state: Arc<Mutex<State>>) -> zbus::Result<()> {
+  let mut changes = state.lock().await.subscribe();
+
+  let server = connection.object_server();
+  server.at(PATH, MediaPlayer).await?;
+  server
+    .at(
+      PATH,
+      Player {
+        state: state.clone(),
+      },
+    )
+    .await?;
+
+  // without replacement, so a second instance doesn't take over the first one's name
+  let flags = RequestNameFlags::DoNotQueue.into();
+  let name = match connection.request_name_with_flags(NAME, flags).await {
+    Err(zbus::Error::NameTaken) => {
+      let name = format!("{NAME}.instance{}", std::process::id());
+      connection
+        .request_name_with_flags(name.as_str(), flags)
+        .await?;
+      name
+    }
+    result => result.map(|_| NAME.to_string())?,
+  };
+  tracing::info!("serving MPRIS as {name}");
+
+  let player = server.interface::<_, Player>(PATH).await?;
+  loop {
+    match changes.recv().await {
+      Ok(Change::Playlists) => {}
+      Ok(Change::Seeked) => {
+        let position = player.get().await.position().await;
+        Player::seeked(player.signal_emitter(), position).await?;
+      }
+      // a profile switch clears the playback too
+      Ok(_) | Err(RecvError::Lagged(_)) => {
+        let emitter = player.signal_emitter();
+        let player = player.get().await;
+
+        player.playback_status_changed(emitter).await?;
+        player.metadata_changed(emitter).await?;
+        player.loop_status_changed(emitter).await?;
+        player.shuffle_changed(emitter).await?;
+        player.volume_changed(emitter).await?;
+        player.can_play_changed(emitter).await?;
+        player.can_seek_changed(emitter).await?;
+      }
+      Err(RecvError::Closed) => return Ok(()),
+    }
+  }
+}
+
+/// The `org.mpris.MediaPlayer2` interface, describing orpheus itself.
+struct MediaPlayer;
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl MediaPlayer {
+  /// Orpheus runs in a terminal, which it can't bring forward.
+  fn raise(&self) {}
+
+  /// Quitting is left to the TUI.
+  fn quit(&self) {}
+
+  #[zbus(property(emits_changed_signal = "const"))]
+  fn can_quit(&self) -> bool {
+    false
+  }
+
+  #[zbus(property(emits_changed_signal = "const"))]
+  fn can_raise(&self) -> bool {
+    false
+  }
+
+  #[zbus(property(emits_changed_signal = "const"))]
+  fn has_track_list(&self) -> bool {
+    false
+  }
+
+  #[zbus(property(emits_changed_signal = "const"))]
+  fn identity(&self) -> &str {
+    "Orpheus"
+  }
+
+  #[zbus(property(emits_changed_signal = "const"))]
+  fn supported_uri_schemes(&self) -> Vec<&str> {
+    vec!["spotify"]
+  }
+
+  #[zbus(property(emits_changed_signal = "const"))]
+  fn supported_mime_types(&self) -> Vec<&str> {
+    vec![]
+  }
+}
+
+/// The `org.mpris.MediaPlayer2.Player` interface, controlling the playback.
+struct Player {
+  state: Arc<Mutex<State>>,
+}
+
+impl Player {
+  async fn dispatch(&self, event: Event) {
+    self.state.lock().await.dispatch(event);
+  }
+
+  /// Reads the current playback with `read`, or `default` if nothing is playing.
+  async fn playback<T>(&self, default: T, read: impl FnOnce(&CurrentPlaybackContext) -> T) -> T {
+    let state = self.state.lock().await;
+    state
+      .current_playback_context
+      .as_ref()
+      .map_or(default, read)
+  }
+
+  /// Seeks to `position` in microseconds, skipping to the next track past its end.
+  ///
+  /// `Seeked` is signaled once the playback polled after it moved.
+  async fn seek_to(&self, position: i64) {
+    let Some(length) = self.playback(None, length).await else {
+      return;
+    };
+
+    match position < length {
+      true => {
+        let position = position.max(0);
+        self.dispatch(Event::Seek((position / 1000) as u32)).await;
+      }
+      false => self.dispatch(Event::NextTrack).await,
+    }
+  }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+  async fn next(&self) {
+    self.dispatch(Event::NextTrack).await
+  }
+
+  async fn previous(&self) {
+    self.dispatch(Event::PreviousTrack).await
+  }
+
+  async fn pause(&self) {
+    self.dispatch(Event::Pause).await
+  }
+
+  async fn play_pause(&self) {
+    let event = match self.playback(false, |context| context.is_playing).await {
+      true => Event::Pause,
+      false => Event::Play(None),
+    };
+    self.dispatch(event).await
+  }
+
+  /// Spotify has no stop, so it pauses.
+  async fn stop(&self) {
+    self.dispatch(Event::Pause).await
+  }
+
+  async fn play(&self) {
+    self.dispatch(Event::Play(None)).await
+  }
+
+  /// Seeks by `offset` microseconds.
+  async fn seek(&self, offset: i64) {
+    let position = self.playback(0, position).await;
+    self.seek_to(position.saturating_add(offset)).await
+  }
+
+  /// Seeks to `position` microseconds, if `track_id` is still the one playing.
+  async fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {
+    let current = self.playback(String::new(), track_id_of).await;
+    if current != track_id.as_str() || position < 0 {
+      return;
+    }
+    self.seek_to(position).await
+  }
+
+  /// Plays the track, album, playlist, artist or show of a Spotify URI.
+  async fn open_uri(&self, uri: &str) -> fdo::Result<()> {
+    let target = PlayTarget::parse(uri)
+      .ok_or_else(|| fdo::Error::InvalidArgs(format!("'{uri}' is not a playable Spotify URI")))?;
+    self.dispatch(Event::Play(Some(target))).await;
+    Ok(())
+  }
+
+  #[zbus(signal)]
+  async fn seeked(emitter: &SignalEmitter<'_>, position: i64) -> zbus::Result<()>;
+
+  #[zbus(property)]
+  async fn playback_status(&self) -> &str {
+    self
+      .playback("Stopped", |context| match context.is_playing {
+        true => "Playing",
+        false => "Paused",
+      })
+      .await
+  }
+
+  #[zbus(property)]
+  async fn loop_status(&self) -> &str {
+    self
+      .playback("None", |context| match context.repeat_state {
+        RepeatState::Off => "None",
+        RepeatState::Track => "Track",
+        RepeatState::Context => "Playlist",
+      })
+      .await
+  }
+
+  #[zbus(property)]
+  async fn set_loop_status(&self, status: &str) -> fdo::Result<()> {
+    let repeat = match status {
+      "None" => RepeatState::Off,
+      "Track" => RepeatState::Track,
+      "Playlist" => RepeatState::Context,
+      status => {
+        return Err(fdo::Error::InvalidArgs(format!(
+          "'{status}' is not a loop status"
+        )));
+      }
+    };
+    self.dispatch(Event::Repeat(repeat)).await;
+    Ok(())
+  }
+
+  #[zbus(property)]
+  async fn shuffle(&self) -> bool {
+    self.playback(false, |context| context.shuffle_state).await
+  }
+
+  #[zbus(property)]
+  async fn set_shuffle(&self, shuffle: bool) {
+    self.dispatch(Event::Shuffle(shuffle)).await
+  }
+
+  #[zbus(property)]
+  async fn metadata(&self) -> HashMap<String, OwnedValue> {
+    self.playback(no_track(), metadata).await
+  }
+
+  #[zbus(property)]
+  async fn volume(&self) -> f64 {
+    self
+      .playback(0.0, |context| {
+        f64::from(context.device.volume_percent.unwrap_or(0)) / 100.0
+      })
+      .await
+  }
+
+  /// Sets the volume from 0.0 to 1.0, clamping anything outside of it.
+  #[zbus(property)]
+  async fn set_volume(&self, volume: f64) {
+    let level = (volume.clamp(0.0, 1.0) * 100.0).round() as u8;
+    self.dispatch(Event::Volume(level)).await
+  }
+
+  /// Clients are expected to poll it, so it's never signaled.
+  #[zbus(property(emits_changed_signal = "false"))]
+  async fn position(&self) -> i64 {
+    self.playback(0, position).await
+  }
+
+  #[zbus(property(emits_changed_signal = "const"))]
+  fn rate(&self) -> f64 {
+    1.0
+  }
+
+  #[zbus(property(emits_changed_signal = "const"))]
+  fn minimum_rate(&self) -> f64 {
+    1.0
+  }
+
+  #[zbus(property(emits_changed_signal = "const"))]
+  fn maximum_rate(&self) -> f64 {
+    1.0
+  }
+
+  #[zbus(property)]
+  async fn can_play(&self) -> bool {
+    self.playback(false, |context| context.item.is_some()).await
+  }
+
+  #[zbus(property(emits_changed_signal = "const"))]
+  fn can_pause(&self) -> bool {
+    true
+  }
+
+  #[zbus(property(emits_changed_signal = "const"))]
+  fn can_go_next(&self) -> bool {
+    true
+  }
+
+  #[zbus(property(emits_changed_signal = "const"))]
+  fn can_go_previous(&self) -> bool {
+    true
+  }
+
+  #[zbus(property)]
+  async fn can_seek(&self) -> bool {
+    self.playback(None, length).await.is_some()
+  }
+
+  #[zbus(property(emits_changed_signal = "const"))]
+  fn can_control(&self) -> bool {
+    true
+  }
+}
+
+/// Length of the playing item in microseconds.
+fn length(context: &CurrentPlaybackContext) -> Option<i64> {
+  let duration = match context.item.as_ref()? {
+    PlayableItem::Track(track) => track.duration,
+    PlayableItem::Episode(episode) => episode.duration,
+    _ => return None,
+  };
+  duration.num_microseconds()
+}
+
+/// Position in microseconds, moved forward by the time since it was fetched while playing.
+fn position(context: &CurrentPlaybackContext) -> i64 {
+  let mut progress = context.progress.unwrap_or_default();
+  if context.is_playing {
+    progress += (Utc::now() - context.timestamp).max(Default::default());
+  }
+
+  let position = progress.num_microseconds().unwrap_or_default();
+  match length(context) {
+    Some(length) => position.min(length),
+    None => position,
+  }
+}
+
+fn track_id_of(context: &CurrentPlaybackContext) -> String {
+  let id = match &context.item {
+    Some(PlayableItem::Track(track)) => track.id.as_ref().map(|id| id.id().to_string()),
+    Some(PlayableItem::Episode(episode)) => Some(episode.id.id().to_string()),
+    _ => None,
+  };
+
+  match id {
+    // Spotify ids are base62, which is valid in an object path
+    Some(id) => format!("/org/orpheus/track/{id}"),
+    None => NO_TRACK.to_string(),
+  }
+}
+
+fn no_track() -> HashMap<String, OwnedValue> {
+  let mut metadata = HashMap::new();
+  insert(
+    &mut metadata,
+    "mpris:trackid",
+    ObjectPath::from_static_str_unchecked(NO_TRACK),
+  );
+  metadata
+}
+
+fn metadata(context: &CurrentPlaybackContext) -> HashMap<String, OwnedValue> {
+  let mut metadata = HashMap::new();
+
+  let track_id = track_id_of(context);
+  if let Ok(path) = ObjectPath::try_from(track_id.as_str()) {
+    insert(&mut metadata, "mpris:trackid", path);
+  }
+  if let Some(length) = length(context) {
+    insert(&mut metadata, "mpris:length", length);
+  }
+
+  match &context.item {
+    Some(PlayableItem::Track(track)) => {
+      let artists: Vec<&str> = track.artists.iter().map(|a| a.name.as_str()).collect();
+      let album_artists: Vec<&str> = track
+        .album
+        .artists
+        .iter()
+        .map(|a| a.name.as_str())
+        .collect();
+
+      insert(&mut metadata, "xesam:title", track.name.as_str());
+      insert(&mut metadata, "xesam:album", track.album.name.as_str());
+      insert(&mut metadata, "xesam:artist", artists);
+      insert(&mut metadata, "xesam:albumArtist", album_artists);
+      insert(
+        &mut metadata,
+        "xesam:trackNumber",
+        track.track_number as i32,
+      );
+      insert(&mut metadata, "xesam:discNumber", track.disc_number);
+      if let Some(image) = track.album.images.first() {
+        insert(&mut metadata, "mpris:artUrl", image.url.as_str());
+      }
+      if let Some(url) = track.external_urls.get("spotify") {
+        insert(&mut metadata, "xesam:url", url.as_str());
+      }
+    }
+    Some(PlayableItem::Episode(episode)) => {
+      insert(&mut metadata, "xesam:title", episode.name.as_str());
+      insert(&mut metadata, "xesam:album", episode.show.name.as_str());
+      insert(
+        &mut metadata,
+        "xesam:artist",
+        vec![episode.show.publisher.as_str()],
+      );
+      if let Some(image) = episode.images.first() {
+        insert(&mut metadata, "mpris:artUrl", image.url.as_str());
+      }
+      if let Some(url) = episode.external_urls.get("spotify") {
+        insert(&mut metadata, "xesam:url", url.as_str());
+      }
+    }
+    _ => {}
+  }
+
+  metadata
+}
+
+fn insert<'a>(metadata: &mut HashMap<String, OwnedValue>, key: &str, value: impl Into<Value<'a>>) {
+  // only values holding file descriptors can fail to be owned
+  if let Ok(value) = OwnedValue::try_from(value.into()) {
+    metadata.insert(key.to_string(), value);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{auth::profile::Profile, config::Config, io::backend::fake, private_bus::PrivateBus};
+  use futures_util::StreamExt;
+  use std::{
+    sync::mpsc::{Receiver, channel},
+    time::Duration,
+  };
+  use tempfile::TempDir;
+  use zbus::{Proxy, proxy::CacheProperties};
+
+  const PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+
+  /// Serves a playing track on a private bus, with its state, a proxy to the
+  /// player and the events it dispatches.
+  async fn player(
+    bus: &PrivateBus,
+  ) -> (TempDir, Arc<Mutex<State>>, Proxy<'static>, Receiver<Event>) {
+    let dir = TempDir::new().unwrap();
+    let (sender, events) = channel();
+    let mut state = State::new(Config::default(), Profile::rooted(dir.path()), sender);
+    state.set_playback(Some(fake::playback(fake::track("first", "First"), true)));
+    let state = Arc::new(Mutex::new(state));
+
+    let connection = bus.connect().await;
+    let served = state.clone();
+    tokio::spawn(async move { serve(&connection, served).await });
+
+    let client = bus.connect().await;
+    let dbus = fdo::DBusProxy::new(&client).await.unwrap();
+    while !dbus.name_has_owner(NAME.try_into().unwrap()).await.unwrap() {
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let proxy = zbus::proxy::Builder::new(&client)
+      .destination(NAME)
+      .unwrap()
+      .path(PATH)
+      .unwrap()
+      .interface(PLAYER)
+      .unwrap()
+      .cache_properties(CacheProperties::No)
+      .build()
+      .await
+      .unwrap();
+    (dir, state, proxy, events)
+  }
+
+  #[tokio::test]
+  async fn properties_are_read_from_the_state() {
+    let bus = PrivateBus::start();
+    let (_dir, _state, player, _events) = player(&bus).await;
+
+    let status: String = player.get_property("PlaybackStatus").await.unwrap();
+    assert_eq!(status, "Playing");
+
+    let metadata: HashMap<String, OwnedValue> = player.get_property("Metadata").await.unwrap();
+    let title: String = metadata["xesam:title"]
+      .try_clone()
+      .unwrap()
+      .try_into()
+      .unwrap();
+    assert_eq!(title, "First");
+    let track_id: ObjectPath = metadata["mpris:trackid"].downcast_ref().unwrap();
+    assert_eq!(track_id.as_str(), "/org/orpheus/track/first");
+    let length: i64 = metadata["mpris:length"].downcast_ref().unwrap();
+    assert_eq!(length, 180_000_000);
+
+    // 1 minute in, and moving on while playing
+    let position: i64 = player.get_property("Position").await.unwrap();
+    assert!((60_000_000..70_000_000).contains(&position));
+  }
+
+  #[tokio::test]
+  async fn methods_and_setters_dispatch_events() {
+    let bus = PrivateBus::start();
+    let (_dir, _state, player, events) = player(&bus).await;
+
+    player.call_method("PlayPause", &()).await.unwrap();
+    assert!(matches!(events.try_recv(), Ok(Event::Pause)));
+    player.call_method("Next", &()).await.unwrap();
+    assert!(matches!(events.try_recv(), Ok(Event::NextTrack)));
+
+    player.set_property("LoopStatus", "Track").await.unwrap();
+    assert!(matches!(
+      events.try_recv(),
+      Ok(Event::Repeat(RepeatState::Track))
+    ));
+    player.set_property("Shuffle", true).await.unwrap();
+    assert!(matches!(events.try_recv(), Ok(Event::Shuffle(true))));
+    player.set_property("Volume", 0.25).await.unwrap();
+    assert!(matches!(events.try_recv(), Ok(Event::Volume(25))));
+
+    assert!(player.set_property("LoopStatus", "Forever").await.is_err());
+    assert!(events.try_recv().is_err());
+  }
+
+  #[tokio::test]
+  async fn seeking_past_either_end_saturates() {
+    let bus = PrivateBus::start();
+    let (_dir, _state, player, events) = player(&bus).await;
+
+    player.call_method("Seek", &(i64::MAX)).await.unwrap();
+    assert!(matches!(events.try_recv(), Ok(Event::NextTrack)));
+    player.call_method("Seek", &(i64::MIN)).await.unwrap();
+    assert!(matches!(events.try_recv(), Ok(Event::Seek(0))));
+  }
+
+  #[tokio::test]
+  async fn jumps_of_the_polled_position_are_signaled() {
+    let bus = PrivateBus::start();
+    let (_dir, state, player, _events) = player(&bus).await;
+    let mut seeked = player.receive_signal("Seeked").await.unwrap();
+
+    // a poll finding the track further than it would have played to
+    let mut playback = fake::playback(fake::track("first", "First"), true);
+    playback.progress = Some(chrono::Duration::seconds(120));
+    state.lock().await.set_playback(Some(playback));
+
+    let signal = tokio::time::timeout(Duration::from_secs(1), seeked.next())
+      .await
+      .unwrap()
+      .unwrap();
+    let position: i64 = signal.body().deserialize().unwrap();
+    assert!((120_000_000..121_000_000).contains(&position));
+  }
+}
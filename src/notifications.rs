@@ -0,0 +1,293 @@
+//! Desktop notifications of the track changes.
+//!
+//! When the item in successive playback polls has another id, a notification
+//! is sent to `org.freedesktop.Notifications` on the session bus, with the
+//! title, the artists and album or the show, and the cover, downloaded in the
+//! [cache](crate::auth::profile::Profile::cache_dir). Each one replaces the
+//! previous, and they're at least the configured
+//! [interval](crate::config::NotificationsConfig::interval) apart.
+//!
+//! The bus comes from `DBUS_SESSION_BUS_ADDRESS`, so a stand-in notification
+//! service can be run on a private bus.
+
+use crate::{
+  config::NotificationsConfig,
+  images,
+  state::{Change, State},
+};
+use rspotify::{
+  model::{CurrentPlaybackContext, PlayableItem},
+  prelude::Id,
+};
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Instant};
+use tokio::{
+  sync::{Mutex, broadcast::error::RecvError},
+  task::JoinHandle,
+};
+use zbus::{Connection, proxy, zvariant::Value};
+
+#[proxy(
+  interface = "org.freedesktop.Notifications",
+  default_service = "org.freedesktop.Notifications",
+  default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+  #[allow(clippy::too_many_arguments)]
+  fn notify(
+    &self,
+    app_name: &str,
+    replaces_id: u32,
+    app_icon: &str,
+    summary: &str,
+    body: &str,
+    actions: &[&str],
+    hints: HashMap<&str, Value<'_>>,
+    expire_timeout: i32,
+  ) -> zbus::Result<u32>;
+}
+
+/// The playing item, as notified.
+struct Item {
+  id: String,
+  summary: String,
+  body: String,
+  cover: Option<String>,
+}
+
+impl Item {
+  fn new(context: &CurrentPlaybackContext) -> Option<Self> {
+    let item = match context.item.as_ref()? {
+      PlayableItem::Track(track) => {
+        let artists: Vec<&str> = track.artists.iter().map(|a| a.name.as_str()).collect();
+        Self {
+          id: match &track.id {
+            Some(id) => id.uri(),
+            // local files have no id
+            None => track.name.clone(),
+          },
+          summary: track.name.clone(),
+          body: format!("{} — {}", artists.join(", "), track.album.name),
+          cover: images::cover(&track.album.images),
+        }
+      }
+      PlayableItem::Episode(episode) => Self {
+        id: episode.id.uri(),
+        summary: episode.name.clone(),
+        body: episode.show.name.clone(),
+        cover: images::cover(&episode.images),
+      },
+      _ => return None,
+    };
+    Some(item)
+  }
+}
+
+/// Starts notifying of the track changes of `state` on the session bus.
+pub(crate) fn spawn(
+  config: NotificationsConfig,
+  covers: Option<PathBuf>,
+  state: &Arc<Mutex<State>>,
+) -> JoinHandle<()> {
+  let state = state.clone();
+
+  tokio::spawn(async move {
+    let connection = match Connection::session().await {
+      Ok(connection) => connection,
+      Err(err) => {
+        tracing::warn!("not sending notifications: {err}");
+        return;
+      }
+    };
+
+    if let Err(err) = run(&connection, config, covers, state).await {
+      tracing::warn!("stopped sending notifications: {err}");
+    }
+  })
+}
+
+/// Notifies of the track changes of `state` on `connection`, downloading
+/// the covers in `covers`, until the state is dropped.
+pub(crate) async fn run(
+  connection: &Connection,
+  config: NotificationsConfig,
+  covers: Option<PathBuf>,
+  state: Arc<Mutex<State>>,
+) -> zbus::Result<()> {
+  let notifications = NotificationsProxy::new(connection).await?;
+  let mut changes = state.lock().await.subscribe();
+
+  let mut notified: Option<String> = None;
+  let mut notified_at: Option<Instant> = None;
+  let mut replaces_id = 0;
+
+  loop {
+    match changes.recv().await {
+      Ok(Change::Playback) | Err(RecvError::Lagged(_)) => {}
+      Ok(_) => continue,
+      Err(RecvError::Closed) => return Ok(()),
+    }
+
+    // waits out the interval, to notify of what's playing after it
+    if let Some(at) = notified_at {
+      let wait = config.interval.saturating_sub(at.elapsed());
+      tokio::time::sleep(wait).await;
+    }
+
+    let item = {
+      let state = state.lock().await;
+      state.current_playback_context.as_ref().and_then(Item::new)
+    };
+    let Some(item) = item else {
+      continue;
+    };
+    if notified.as_ref() == Some(&item.id) {
+      continue;
+    }
+
+    let cover = match (&covers, &item.cover, config.album_art) {
+      (Some(dir), Some(url), true) => images::download(dir, url).await,
+      _ => None,
+    };
+    let mut hints = HashMap::new();
+    if let Some(cover) = &cover {
+      hints.insert(
+        "image-path",
+        Value::from(cover.to_string_lossy().to_string()),
+      );
+    }
+
+    let result = notifications
+      .notify(
+        "Orpheus",
+        replaces_id,
+        "",
+        &item.summary,
+        &escape(&item.body),
+        &[],
+        hints,
+        -1,
+      )
+      .await;
+
+    match result {
+      Ok(id) => replaces_id = id,
+      Err(err) => tracing::warn!("failed to send a notification: {err}"),
+    }
+    notified = Some(item.id);
+    notified_at = Some(Instant::now());
+  }
+}
+
+/// Escapes the markup that servers may interpret in the body.
+fn escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{auth::profile::Profile, config::Config, io::backend::fake, private_bus::PrivateBus};
+  use std::time::Duration;
+  use tempfile::TempDir;
+  use zbus::interface;
+
+  const INTERVAL: Duration = Duration::from_millis(200);
+
+  /// A notification server recording what it's sent, as `(replaces_id, summary, when)`.
+  #[derive(Clone, Default)]
+  struct Server {
+    notified: Arc<std::sync::Mutex<Vec<(u32, String, Instant)>>>,
+  }
+
+  #[interface(name = "org.freedesktop.Notifications")]
+  impl Server {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+      &self,
+      _app_name: &str,
+      replaces_id: u32,
+      _app_icon: &str,
+      summary: &str,
+      _body: &str,
+      _actions: Vec<&str>,
+      _hints: HashMap<&str, Value<'_>>,
+      _expire_timeout: i32,
+    ) -> u32 {
+      let mut notified = self.notified.lock().unwrap();
+      notified.push((replaces_id, summary.to_string(), Instant::now()));
+      // a new notification gets a new id, a replaced one keeps its own
+      match replaces_id {
+        0 => notified.len() as u32,
+        id => id,
+      }
+    }
+  }
+
+  impl Server {
+    fn notified(&self) -> Vec<(u32, String, Instant)> {
+      self.notified.lock().unwrap().clone()
+    }
+  }
+
+  #[tokio::test]
+  async fn changes_of_the_item_are_notified_once() {
+    let bus = PrivateBus::start();
+    let server = Server::default();
+    let connection = bus.connect().await;
+    connection
+      .object_server()
+      .at("/org/freedesktop/Notifications", server.clone())
+      .await
+      .unwrap();
+    connection
+      .request_name("org.freedesktop.Notifications")
+      .await
+      .unwrap();
+
+    let dir = TempDir::new().unwrap();
+    let (sender, _events) = std::sync::mpsc::channel();
+    let state = State::new(Config::default(), Profile::rooted(dir.path()), sender);
+    let state = Arc::new(Mutex::new(state));
+    let config = NotificationsConfig {
+      enabled: true,
+      album_art: false,
+      interval: INTERVAL,
+    };
+
+    let client = bus.connect().await;
+    let running = state.clone();
+    tokio::spawn(async move { run(&client, config, None, running).await });
+    // until it's subscribed to the changes
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let first = fake::track("first", "First");
+    state
+      .lock()
+      .await
+      .set_playback(Some(fake::playback(first.clone(), true)));
+    state
+      .lock()
+      .await
+      .set_playback(Some(fake::playback(first, false)));
+    tokio::time::sleep(INTERVAL * 2).await;
+    assert_eq!(server.notified().len(), 1);
+
+    let second = fake::track("second", "Second");
+    state
+      .lock()
+      .await
+      .set_playback(Some(fake::playback(second, true)));
+    tokio::time::sleep(INTERVAL * 2).await;
+
+    let notified = server.notified();
+    let summaries: Vec<(u32, &str)> = notified
+      .iter()
+      .map(|(replaces_id, summary, _)| (*replaces_id, summary.as_str()))
+      .collect();
+    assert_eq!(summaries, [(0, "First"), (1, "Second")]);
+    assert!(notified[1].2 - notified[0].2 >= INTERVAL);
+  }
+}
@@ -0,0 +1,305 @@
+//! Export of the playback for status bars like waybar, polybar or tmux.
+//!
+//! Whenever the playback changes, and every second while it plays so that the
+//! progress moves on, a line describing it is written to the targets of the `[now_playing]` [config](crate::config::NowPlayingConfig):
+//!
+//! - `path`, a file rewritten whole so that readers never see half of it,
+//!   or a FIFO, skipped while nothing reads it;
+//! - `command`, started with orpheus and getting the lines on its stdin.
+//!
+//! The line is JSON like `{"status": "playing", "title": "...", ...}`, unless
+//! a `format` template is given, in which `{status}`, `{icon}`, `{title}`,
+//! `{artist}`, `{album}`, `{progress}`, `{duration}`, `{device}`, `{volume}`
+//! and `{uri}` are replaced.
+
+use crate::{
+  config::NowPlayingConfig,
+  hooks,
+  state::{Change, State},
+};
+use rspotify::{
+  model::{CurrentPlaybackContext, PlayableItem},
+  prelude::Id,
+};
+use serde::Serialize;
+use std::{path::Path, process::Stdio, sync::Arc, time::Duration};
+use tokio::{
+  io::AsyncWriteExt,
+  process::{Child, ChildStdin},
+  sync::{Mutex, broadcast::error::RecvError},
+  task::JoinHandle,
+  time::MissedTickBehavior,
+};
+
+/// How often the line is written again while playing, for the progress.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What's exported of the playback, also given to the [hooks](crate::hooks).
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub(crate) struct NowPlaying {
+  /// `playing`, `paused` or `stopped` when there's no playback.
+  pub status: &'static str,
+  pub title: Option<String>,
+  pub artist: Option<String>,
+  pub album: Option<String>,
+  pub uri: Option<String>,
+  pub progress_ms: i64,
+  pub duration_ms: i64,
+  pub device: Option<String>,
+  pub volume: Option<u32>,
+}
+
+impl NowPlaying {
+  /// The playback of `state`, with the progress made since it was fetched.
+  pub fn of(state: &State) -> Self {
+    let mut now = Self::new(state.current_playback_context.as_ref());
+    if let Some(progress) = state.progress_ms() {
+      now.progress_ms = match now.duration_ms {
+        0 => progress.into(),
+        duration => i64::from(progress).min(duration),
+      };
+    }
+    now
+  }
+
+  pub fn new(context: Option<&CurrentPlaybackContext>) -> Self {
+    let Some(context) = context else {
+      return Self {
+        status: "stopped",
+        ..Default::default()
+      };
+    };
+
+    let mut now = Self {
+      status: match context.is_playing {
+        true => "playing",
+        false => "paused",
+      },
+      progress_ms: context
+        .progress
+        .map(|progress| progress.num_milliseconds())
+        .unwrap_or_default(),
+      device: Some(context.device.name.clone()),
+      volume: context.device.volume_percent,
+      ..Default::default()
+    };
+
+    match &context.item {
+      Some(PlayableItem::Track(track)) => {
+        let artists: Vec<&str> = track.artists.iter().map(|a| a.name.as_str()).collect();
+        now.title = Some(track.name.clone());
+        now.artist = Some(artists.join(", "));
+        now.album = Some(track.album.name.clone());
+        now.uri = track.id.as_ref().map(|id| id.uri());
+        now.duration_ms = track.duration.num_milliseconds();
+      }
+      Some(PlayableItem::Episode(episode)) => {
+        now.title = Some(episode.name.clone());
+        now.artist = Some(episode.show.publisher.clone());
+        now.album = Some(episode.show.name.clone());
+        now.uri = Some(episode.id.uri());
+        now.duration_ms = episode.duration.num_milliseconds();
+      }
+      _ => {}
+    }
+
+    now
+  }
+
+  /// Renders the line, as JSON or with the `format` template.
+  fn line(&self, format: Option<&str>) -> String {
+    let Some(format) = format else {
+      return serde_json::to_string(self).unwrap_or_default();
+    };
+
+    let icon = match self.status {
+      "playing" => "▶",
+      "paused" => "⏸",
+      _ => "⏹",
+    };
+    let volume = self.volume.map(|volume| volume.to_string());
+    let text = |value: &Option<String>| value.clone().unwrap_or_default();
+
+    [
+      ("{status}", self.status.to_string()),
+      ("{icon}", icon.to_string()),
+      ("{title}", text(&self.title)),
+      ("{artist}", text(&self.artist)),
+      ("{album}", text(&self.album)),
+      ("{progress}", timestamp(self.progress_ms)),
+      ("{duration}", timestamp(self.duration_ms)),
+      ("{device}", text(&self.device)),
+      ("{volume}", text(&volume)),
+      ("{uri}", text(&self.uri)),
+    ]
+    .iter()
+    .fold(format.to_string(), |line, (placeholder, value)| {
+      line.replace(placeholder, value)
+    })
+  }
+}
+
+/// Writes the lines to the configured targets.
+struct Writer {
+  config: NowPlayingConfig,
+  /// The `command` and its stdin, started again if it exited.
+  helper: Option<(Child, ChildStdin)>,
+}
+
+impl Writer {
+  async fn write(&mut self, line: &str) {
+    if let Some(path) = &self.config.path
+      && let Err(err) = write_path(path, line).await
+    {
+      tracing::warn!("failed to write the playback to {}: {err}", path.display());
+    }
+
+    if let Some(command) = &self.config.command {
+      if self.helper.is_none() {
+        self.helper = spawn_helper(command);
+      }
+
+      if let Some((_, stdin)) = &mut self.helper
+        && let Err(err) = write_line(stdin, line).await
+      {
+        tracing::warn!("failed to write the playback to `{command}`: {err}");
+        self.helper = None;
+      }
+    }
+  }
+}
+
+/// Starts exporting the playback of `state` to the targets of `config`.
+pub(crate) fn spawn(config: NowPlayingConfig, state: &Arc<Mutex<State>>) -> JoinHandle<()> {
+  let state = state.clone();
+
+  tokio::spawn(async move {
+    let mut changes = state.lock().await.subscribe();
+    let format = config.format.clone();
+    let mut writer = Writer {
+      config,
+      helper: None,
+    };
+
+    let mut ticks = tokio::time::interval(PROGRESS_INTERVAL);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut written = None;
+
+    loop {
+      let now = NowPlaying::of(&*state.lock().await);
+      let line = now.line(format.as_deref());
+      if written.as_ref() != Some(&line) {
+        writer.write(&line).await;
+        written = Some(line);
+      }
+
+      // a profile switch clears the playback too, so it's written as well
+      loop {
+        tokio::select! {
+          change = changes.recv() => match change {
+            Ok(Change::Playlists) => continue,
+            Ok(_) | Err(RecvError::Lagged(_)) => break,
+            Err(RecvError::Closed) => return,
+          },
+          _ = ticks.tick(), if now.status == "playing" => break,
+        }
+      }
+    }
+  })
+}
+
+async fn write_path(path: &Path, line: &str) -> std::io::Result<()> {
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::FileTypeExt;
+
+    if let Ok(metadata) = tokio::fs::metadata(path).await
+      && metadata.file_type().is_fifo()
+    {
+      let mut fifo = match tokio::net::unix::pipe::OpenOptions::new().open_sender(path) {
+        Ok(fifo) => fifo,
+        // ENXIO, nothing is reading the FIFO
+        Err(err) if err.raw_os_error() == Some(6) => return Ok(()),
+        Err(err) => return Err(err),
+      };
+      return write_line(&mut fifo, line).await;
+    }
+  }
+
+  let mut temporary = path.as_os_str().to_owned();
+  temporary.push(".tmp");
+
+  tokio::fs::write(&temporary, format!("{line}\n")).await?;
+  tokio::fs::rename(&temporary, path).await
+}
+
+async fn write_line(writer: &mut (impl AsyncWriteExt + Unpin), line: &str) -> std::io::Result<()> {
+  writer.write_all(format!("{line}\n").as_bytes()).await?;
+  writer.flush().await
+}
+
+fn spawn_helper(command: &str) -> Option<(Child, ChildStdin)> {
+  // its output would draw over the TUI
+  let spawned = hooks::shell(command)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::null())
+    .stderr(Stdio::null())
+    .kill_on_drop(true)
+    .spawn();
+
+  match spawned {
+    Ok(mut child) => {
+      let stdin = child.stdin.take()?;
+      Some((child, stdin))
+    }
+    Err(err) => {
+      tracing::warn!("failed to start `{command}`: {err}");
+      None
+    }
+  }
+}
+
+/// Formats milliseconds as `M:SS`, in the templates and the CLI alike.
+pub(crate) fn timestamp(ms: i64) -> String {
+  let seconds = ms / 1000;
+  format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{auth::profile::Profile, config::Config, io::backend::fake};
+  use tempfile::TempDir;
+
+  #[tokio::test]
+  async fn the_progress_moves_on_while_playing() {
+    let dir = TempDir::new().unwrap();
+    let (sender, _events) = std::sync::mpsc::channel();
+    let mut state = State::new(Config::default(), Profile::rooted(dir.path()), sender);
+    state.set_playback(Some(fake::playback(fake::track("first", "First"), true)));
+    let state = Arc::new(Mutex::new(state));
+
+    let path = dir.path().join("now-playing");
+    let config = NowPlayingConfig {
+      path: Some(path.clone()),
+      command: None,
+      format: Some("{progress} / {duration}".to_string()),
+    };
+    let _exporter = spawn(config, &state);
+
+    // 1 minute in when fetched, without another poll since
+    let mut lines = vec![];
+    for _ in 0..30 {
+      if lines.len() == 2 {
+        break;
+      }
+      tokio::time::sleep(Duration::from_millis(100)).await;
+      if let Ok(line) = tokio::fs::read_to_string(&path).await
+        && lines.last() != Some(&line)
+      {
+        lines.push(line);
+      }
+    }
+    assert_eq!(lines, ["1:00 / 3:00\n", "1:01 / 3:00\n"]);
+  }
+}
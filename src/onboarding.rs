@@ -0,0 +1,403 @@
+//! First-run onboarding.
+//!
+//! When a profile has no credentials yet, a [wizard](self::Wizard) guides the
+//! user through creating the Spotify developer app, entering its client id,
+//! choosing the redirect URI, optionally a passphrase, and authorizing orpheus,
+//! all inside the TUI before the main screen is shown.
+//!
+//! Nothing is stored until the authorization succeeds, so quitting half way
+//! leaves the profile as it was.
+
+use crate::{
+  auth::{
+    self, AuthError, AuthMode, callback,
+    credentials::{self, DEFAULT_REDIRECT_URI, StoredCredentials},
+    profile::Profile,
+    vault::Vault,
+  },
+  config::Config,
+  io::key::{Event, Key},
+  terminal::{TerminalError, Tui},
+  ui::{onboarding::draw, style::Palette},
+};
+use rspotify::{AuthCodePkceSpotify, prelude::OAuthClient};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+
+#[derive(Debug, Error)]
+pub enum OnboardingError {
+  #[error(transparent)]
+  Terminal(#[from] TerminalError),
+  #[error(transparent)]
+  Auth(#[from] AuthError),
+}
+
+/// A step of the [wizard](self::Wizard), in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Step {
+  Welcome,
+  ClientId,
+  RedirectUri,
+  Passphrase,
+  ConfirmPassphrase,
+  Authorize,
+}
+
+/// A line of text typed by the user.
+#[derive(Debug, Default)]
+pub(crate) struct Input {
+  pub value: String,
+  /// Whether the value is hidden behind bullets.
+  pub masked: bool,
+}
+
+impl Input {
+  fn new(value: &str) -> Self {
+    Self {
+      value: value.to_string(),
+      masked: false,
+    }
+  }
+
+  fn masked() -> Self {
+    Self {
+      value: String::new(),
+      masked: true,
+    }
+  }
+
+  /// The value as it's shown on screen.
+  pub fn display(&self) -> String {
+    match self.masked {
+      true => "•".repeat(self.value.chars().count()),
+      false => self.value.clone(),
+    }
+  }
+
+  fn handle(&mut self, key: Key) {
+    match key {
+      Key::Char(c) => self.value.push(c),
+      Key::Backspace => {
+        self.value.pop();
+      }
+      _ => {}
+    }
+  }
+}
+
+/// State of the onboarding screens.
+pub(crate) struct Wizard {
+  pub profile: Profile,
+  pub mode: AuthMode,
+  pub qr: bool,
+  pub step: Step,
+
+  pub client_id: Input,
+  pub redirect_uri: Input,
+  pub passphrase: Input,
+  pub confirmation: Input,
+  /// The redirect URL, or the code in it, pasted on the last step.
+  pub code: Input,
+
+  /// Validation feedback of the current step.
+  pub error: Option<String>,
+  /// Set while the code is being exchanged for a token.
+  pub is_authorizing: bool,
+
+  /// Whether the stored files get encrypted, which adds the passphrase steps.
+  encrypt: bool,
+  spotify: Option<AuthCodePkceSpotify>,
+  pub authorize_url: Option<String>,
+  listener: Option<JoinHandle<Option<String>>>,
+}
+
+/// What the [wizard](self::Wizard) asks of its loop after a key.
+enum Action {
+  None,
+  Quit,
+  StartAuthorization,
+  Authorize(String),
+}
+
+impl Wizard {
+  fn new(profile: &Profile, config: &Config) -> Self {
+    Self {
+      profile: profile.clone(),
+      mode: config.auth.mode,
+      qr: config.auth.qr,
+      step: Step::Welcome,
+      client_id: Input::default(),
+      redirect_uri: Input::new(DEFAULT_REDIRECT_URI),
+      passphrase: Input::masked(),
+      confirmation: Input::masked(),
+      code: Input::default(),
+      error: None,
+      is_authorizing: false,
+      // a passphrase in the environment doesn't need to be chosen
+      encrypt: config.auth.encrypt && Vault::from_env().is_none(),
+      spotify: None,
+      authorize_url: None,
+      listener: None,
+    }
+  }
+
+  /// Position of the current step and the number of steps, for the progress.
+  pub fn progress(&self) -> (usize, usize) {
+    let steps = self.steps();
+    let index = steps.iter().position(|step| *step == self.step);
+    (index.unwrap_or(0) + 1, steps.len())
+  }
+
+  fn steps(&self) -> Vec<Step> {
+    let mut steps = vec![Step::Welcome, Step::ClientId, Step::RedirectUri];
+    if self.encrypt {
+      steps.extend([Step::Passphrase, Step::ConfirmPassphrase]);
+    }
+    steps.push(Step::Authorize);
+    steps
+  }
+
+  fn go(&mut self, forward: bool) {
+    let steps = self.steps();
+    let index = steps
+      .iter()
+      .position(|step| *step == self.step)
+      .unwrap_or(0);
+    let next = match forward {
+      true => steps.get(index + 1),
+      false => index.checked_sub(1).and_then(|index| steps.get(index)),
+    };
+
+    if self.step == Step::Authorize {
+      self.cancel_authorization();
+    }
+    if let Some(next) = next {
+      self.step = *next;
+    }
+    self.error = None;
+  }
+
+  fn handle(&mut self, key: Key) -> Action {
+    if self.is_authorizing {
+      return Action::None;
+    }
+
+    match (self.step, key) {
+      (Step::Welcome, Key::Esc | Key::Char('q')) => return Action::Quit,
+      (_, Key::Esc) => self.go(false),
+
+      (Step::Welcome, Key::Enter) => self.go(true),
+
+      (Step::Passphrase | Step::ConfirmPassphrase, Key::Tab) => {
+        self.passphrase.masked = !self.passphrase.masked;
+        self.confirmation.masked = !self.confirmation.masked;
+      }
+
+      (Step::ClientId, Key::Enter) => {
+        let client_id = self.client_id.value.trim().to_string();
+        match credentials::validate_client_id(&client_id) {
+          Ok(_) => {
+            self.client_id.value = client_id;
+            self.go(true);
+          }
+          Err(err) => self.error = Some(err.to_string()),
+        }
+      }
+
+      (Step::RedirectUri, Key::Enter) => {
+        let redirect_uri = self.redirect_uri.value.trim().to_string();
+        match credentials::validate_redirect_uri(&redirect_uri) {
+          Ok(_) => {
+            self.redirect_uri.value = redirect_uri;
+            self.go(true);
+            if self.step == Step::Authorize {
+              return Action::StartAuthorization;
+            }
+          }
+          Err(err) => self.error = Some(err.to_string()),
+        }
+      }
+
+      (Step::Passphrase, Key::Enter) => match self.passphrase.value.is_empty() {
+        true => self.error = Some("The passphrase cannot be empty".to_string()),
+        false => self.go(true),
+      },
+
+      (Step::ConfirmPassphrase, Key::Enter) => {
+        match self.confirmation.value == self.passphrase.value {
+          true => {
+            self.go(true);
+            return Action::StartAuthorization;
+          }
+          false => {
+            self.confirmation.value.clear();
+            self.error = Some("The passphrases do not match".to_string());
+          }
+        }
+      }
+
+      (Step::Authorize, Key::Enter) if !self.code.value.is_empty() => {
+        let input = std::mem::take(&mut self.code.value);
+        let code = self
+          .spotify
+          .as_ref()
+          .and_then(|spotify| auth::parse_code(spotify, &input));
+
+        match code {
+          Some(code) => return Action::Authorize(code),
+          None => {
+            self.error = Some("Could not find an authorization code in the input".to_string())
+          }
+        }
+      }
+
+      (step, key) => {
+        let input = match step {
+          Step::ClientId => &mut self.client_id,
+          Step::RedirectUri => &mut self.redirect_uri,
+          Step::Passphrase => &mut self.passphrase,
+          Step::ConfirmPassphrase => &mut self.confirmation,
+          Step::Authorize => &mut self.code,
+          Step::Welcome => return Action::None,
+        };
+        input.handle(key);
+      }
+    }
+
+    Action::None
+  }
+
+  fn credentials(&self) -> StoredCredentials {
+    StoredCredentials::new(
+      self.client_id.value.clone(),
+      self.redirect_uri.value.clone(),
+    )
+  }
+
+  fn vault(&self) -> Option<Vault> {
+    match self.encrypt {
+      true => Some(Vault::new(self.passphrase.value.clone())),
+      false => Vault::from_env(),
+    }
+  }
+
+  /// Creates the client and, in browser mode, opens the authorization URL
+  /// and listens for the callback.
+  fn start_authorization(&mut self) -> Result<(), AuthError> {
+    let mut spotify = auth::client(&self.profile, self.credentials(), self.vault())?;
+    let url = spotify
+      .get_authorize_url(None)
+      .map_err(|e| AuthError::Authentication(e.to_string()))?;
+
+    if self.mode == AuthMode::Browser {
+      if let Err(err) = webbrowser::open(&url) {
+        tracing::warn!("failed to open the browser: {err}");
+      }
+
+      let redirect_uri = spotify.oauth.redirect_uri.clone();
+      let state = spotify.oauth.state.clone();
+      self.listener = Some(tokio::spawn(async move {
+        callback::listen(&redirect_uri, &state).await.ok()
+      }));
+    }
+
+    self.authorize_url = Some(url);
+    self.spotify = Some(spotify);
+    Ok(())
+  }
+
+  fn cancel_authorization(&mut self) {
+    if let Some(listener) = self.listener.take() {
+      listener.abort();
+    }
+    self.spotify = None;
+    self.authorize_url = None;
+    self.code.value.clear();
+  }
+
+  /// The code captured by the callback listener, once it has one.
+  async fn captured_code(&mut self) -> Option<String> {
+    match &self.listener {
+      Some(listener) if listener.is_finished() => {}
+      _ => return None,
+    }
+
+    self.listener.take()?.await.ok().flatten()
+  }
+
+  /// Exchanges the code for a token, storing the credentials once it worked.
+  async fn authorize(&mut self, code: &str) -> Result<Option<AuthCodePkceSpotify>, AuthError> {
+    let Some(spotify) = &self.spotify else {
+      return Ok(None);
+    };
+
+    if let Err(err) = spotify.request_token(code).await {
+      self.error = Some(format!("Failed to authorize with Spotify: {err}"));
+      return Ok(None);
+    }
+
+    credentials::save(&self.profile, &self.credentials(), self.vault().as_ref())?;
+    credentials::remove_legacy(&self.profile)?;
+
+    if let Some(listener) = self.listener.take() {
+      listener.abort();
+    }
+    Ok(self.spotify.take())
+  }
+}
+
+/// Whether `profile` has to be onboarded before starting.
+pub(crate) fn is_needed(profile: &Profile) -> Result<bool, AuthError> {
+  Ok(!credentials::exist(profile)?)
+}
+
+/// Runs the wizard on `tui`, returning the authorized client,
+/// or `None` if the user quit.
+pub(crate) async fn run(
+  tui: &mut Tui,
+  profile: &Profile,
+  config: &Config,
+) -> Result<Option<AuthCodePkceSpotify>, OnboardingError> {
+  let palette = Palette::from(&config.theme);
+  let mut wizard = Wizard::new(profile, config);
+
+  loop {
+    tui
+      .terminal
+      .draw(|f| draw(f, &wizard, &palette))
+      .map_err(TerminalError::from)?;
+
+    let code = match tui.events.next().map_err(TerminalError::from)? {
+      Event::Input(key) => match wizard.handle(key) {
+        Action::None => None,
+        Action::Quit => return Ok(None),
+        Action::StartAuthorization => {
+          if let Err(err) = wizard.start_authorization() {
+            wizard.error = Some(err.to_string());
+          }
+          None
+        }
+        Action::Authorize(code) => Some(code),
+      },
+      Event::Tick => wizard.captured_code().await,
+    };
+
+    let Some(code) = code else {
+      continue;
+    };
+
+    wizard.error = None;
+    wizard.is_authorizing = true;
+    tui
+      .terminal
+      .draw(|f| draw(f, &wizard, &palette))
+      .map_err(TerminalError::from)?;
+
+    let spotify = wizard.authorize(&code).await?;
+    wizard.is_authorizing = false;
+
+    if spotify.is_some() {
+      return Ok(spotify);
+    }
+  }
+}
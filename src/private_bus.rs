@@ -0,0 +1,51 @@
+//! Private D-Bus session bus for the tests of the desktop integration, so
+//! that they neither need nor disturb the user's own.
+//!
+//! It runs `dbus-daemon`, which must be in the `PATH`, until it's dropped.
+
+use std::{
+  io::{BufRead, BufReader},
+  process::{Child, Command, Stdio},
+};
+use zbus::{Connection, connection::Builder};
+
+pub(crate) struct PrivateBus {
+  daemon: Child,
+  address: String,
+}
+
+impl PrivateBus {
+  pub fn start() -> Self {
+    let mut daemon = Command::new("dbus-daemon")
+      .args(["--session", "--nofork", "--print-address"])
+      .stdout(Stdio::piped())
+      .stderr(Stdio::null())
+      .spawn()
+      .expect("dbus-daemon is needed for the D-Bus tests");
+
+    // printed once it listens
+    let mut address = String::new();
+    let stdout = daemon.stdout.take().unwrap();
+    BufReader::new(stdout).read_line(&mut address).unwrap();
+
+    Self {
+      daemon,
+      address: address.trim().to_string(),
+    }
+  }
+
+  pub async fn connect(&self) -> Connection {
+    Builder::address(self.address.as_str())
+      .unwrap()
+      .build()
+      .await
+      .unwrap()
+  }
+}
+
+impl Drop for PrivateBus {
+  fn drop(&mut self) {
+    let _ = self.daemon.kill();
+    let _ = self.daemon.wait();
+  }
+}
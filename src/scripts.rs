@@ -0,0 +1,437 @@
+//! User scripts, for custom commands, key bindings and automations.
+//!
+//! Every `.rhai` file in the `scripts` folder of the [config directory](crate::auth::config_dir)
+//! is run at startup, in name order, and can register:
+//!
+//! ```rhai
+//! command("quiet", || volume(40));
+//! bind("Q", "quiet");
+//!
+//! on("track_changed", |playback| {
+//!   if playback.artist == "Daft Punk" {
+//!     volume(40);
+//!   }
+//! });
+//! ```
+//!
+//! Keys orpheus already handles, like `P` or `enter`, can't be bound.
+//! Handlers of `on` are given the playback, or the playlist for `playlist_modified`,
+//! for the same events as the [hooks](crate::hooks). `state()` returns a read-only
+//! snapshot of the profile, playback and playlists, and `play()`, `play(uri)`,
+//! `pause()`, `next()`, `previous()`, `seek(ms)`, `queue(uri)`, `volume(level)`,
+//! `add_to_playlist(playlist, uri)`, `like(uri)` and `follow(uri)` dispatch
+//! [events](crate::io::Event) to the [io](crate::io::Io). Commands also
+//! run from the [IPC](crate::ipc) `run` command.
+//!
+//! [Rhai](https://rhai.rs) is sandboxed: scripts can't import modules, read files
+//! or start programs, and are stopped past a number of operations.
+
+use crate::{
+  auth,
+  hooks::{Hook, Playlist, Watcher},
+  io::{Event, key::Key, target::PlayTarget},
+  now_playing::NowPlaying,
+  state::{Change, State, handler::BUILT_IN_KEYS},
+};
+use rhai::{
+  AST, Dynamic, Engine, EvalAltResult, FnPtr, INT, Position, module_resolvers::DummyModuleResolver,
+};
+use rspotify::model::{ArtistId, PlaylistId, TrackId};
+use serde::Serialize;
+use std::{
+  collections::HashMap,
+  path::PathBuf,
+  sync::{Arc, mpsc::Sender},
+};
+use tokio::{
+  sync::{Mutex, broadcast::error::RecvError, mpsc::UnboundedReceiver},
+  task::JoinHandle,
+};
+
+/// Operations after which a script is stopped, so a loop can't hang orpheus.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// A function of a script, called with the [AST](rhai::AST) it was defined in.
+#[derive(Clone)]
+struct Callback {
+  script: usize,
+  function: FnPtr,
+}
+
+/// What the scripts registered.
+#[derive(Default)]
+struct Registry {
+  /// Index of the script being run, which the callbacks belong to.
+  script: usize,
+  commands: HashMap<String, Callback>,
+  bindings: HashMap<Key, String>,
+  handlers: Vec<(Hook, Callback)>,
+}
+
+/// The read-only view of the [state](crate::state::State) returned by `state()`.
+#[derive(Serialize)]
+struct Snapshot {
+  profile: String,
+  playback: NowPlaying,
+  playlists: Vec<Playlist>,
+}
+
+pub(crate) struct Scripts {
+  engine: Engine,
+  asts: Vec<AST>,
+  registry: Arc<std::sync::Mutex<Registry>>,
+  /// The [snapshot](self::Snapshot) taken before calling a script.
+  snapshot: Arc<std::sync::Mutex<Dynamic>>,
+  /// Scripts that failed to load, with the reason.
+  pub errors: Vec<String>,
+}
+
+impl Scripts {
+  /// Runs the scripts of the config directory, if there are any.
+  ///
+  /// A script failing doesn't stop the others: it's logged and kept in the [errors](Self::errors).
+  pub fn load(sender: Sender<Event>) -> Option<Self> {
+    let dir = match auth::config_dir() {
+      Ok(dir) => dir.join("scripts"),
+      Err(err) => {
+        tracing::warn!("not loading the scripts: {err}");
+        return None;
+      }
+    };
+
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir)
+      .ok()?
+      .filter_map(|entry| Some(entry.ok()?.path()))
+      .filter(|path| {
+        path
+          .extension()
+          .is_some_and(|extension| extension == "rhai")
+      })
+      .collect();
+    paths.sort();
+
+    if paths.is_empty() {
+      return None;
+    }
+
+    let registry = Arc::default();
+    let snapshot = Arc::new(std::sync::Mutex::new(Dynamic::UNIT));
+    let mut scripts = Self {
+      engine: engine(&registry, &snapshot, sender),
+      asts: vec![],
+      registry,
+      snapshot,
+      errors: vec![],
+    };
+
+    for path in paths {
+      let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+      let ast = match scripts.engine.compile_file(path) {
+        Ok(ast) => ast,
+        Err(err) => {
+          scripts.failed(&name, err);
+          continue;
+        }
+      };
+
+      // kept even if it fails, for what it registered before
+      scripts.registry.lock().unwrap().script = scripts.asts.len();
+      let result = scripts.engine.run_ast(&ast);
+      scripts.asts.push(ast);
+
+      match result {
+        Ok(_) => tracing::info!("loaded the script {name}"),
+        Err(err) => scripts.failed(&name, err),
+      }
+    }
+
+    Some(scripts)
+  }
+
+  fn failed(&mut self, name: &str, err: Box<EvalAltResult>) {
+    tracing::warn!("failed to load the script {name}: {err}");
+    self.errors.push(format!("{name}: {err}"));
+  }
+
+  /// Keys bound to commands by the scripts.
+  pub fn bindings(&self) -> HashMap<Key, String> {
+    self.registry.lock().unwrap().bindings.clone()
+  }
+
+  fn command(&self, name: &str) -> Option<Callback> {
+    self.registry.lock().unwrap().commands.get(name).cloned()
+  }
+
+  fn handlers(&self, hook: Hook) -> Vec<Callback> {
+    let registry = self.registry.lock().unwrap();
+    registry
+      .handlers
+      .iter()
+      .filter(|(handled, _)| *handled == hook)
+      .map(|(_, callback)| callback.clone())
+      .collect()
+  }
+
+  /// Calls `callback` with `args`, `state()` returning `snapshot`.
+  fn call(&self, callback: &Callback, args: Vec<Dynamic>, snapshot: Dynamic) -> ScriptResult<()> {
+    *self.snapshot.lock().unwrap() = snapshot;
+
+    let ast = &self.asts[callback.script];
+    callback
+      .function
+      .call::<Dynamic>(&self.engine, ast, args)
+      .map(|_| ())
+  }
+}
+
+/// Creates the sandboxed engine with the functions given to the scripts.
+fn engine(
+  registry: &Arc<std::sync::Mutex<Registry>>,
+  snapshot: &Arc<std::sync::Mutex<Dynamic>>,
+  sender: Sender<Event>,
+) -> Engine {
+  let mut engine = Engine::new();
+
+  engine
+    .set_module_resolver(DummyModuleResolver::new())
+    .disable_symbol("eval")
+    .set_max_operations(MAX_OPERATIONS)
+    .set_max_call_levels(64)
+    .set_max_expr_depths(64, 32)
+    .set_max_string_size(1 << 20)
+    .set_max_array_size(10_000)
+    .set_max_map_size(10_000)
+    .on_print(|text| tracing::info!("script: {text}"))
+    .on_debug(|text, _, _| tracing::debug!("script: {text}"));
+
+  let commands = registry.clone();
+  engine.register_fn("command", move |name: &str, function: FnPtr| {
+    let mut registry = commands.lock().unwrap();
+    let callback = Callback {
+      script: registry.script,
+      function,
+    };
+    registry.commands.insert(name.to_string(), callback);
+  });
+
+  let bindings = registry.clone();
+  engine.register_fn(
+    "bind",
+    move |name: &str, command: &str| -> ScriptResult<()> {
+      let key = Key::parse(name).ok_or_else(|| error(format!("'{name}' is not a key")))?;
+      if BUILT_IN_KEYS.contains(&key) {
+        return Err(error(format!(
+          "'{name}' is a built-in key, it can't be bound"
+        )));
+      }
+      bindings
+        .lock()
+        .unwrap()
+        .bindings
+        .insert(key, command.to_string());
+      Ok(())
+    },
+  );
+
+  let handlers = registry.clone();
+  engine.register_fn(
+    "on",
+    move |event: &str, function: FnPtr| -> ScriptResult<()> {
+      let hook = Hook::parse(event).ok_or_else(|| error(format!("'{event}' is not an event")))?;
+      let mut registry = handlers.lock().unwrap();
+      let callback = Callback {
+        script: registry.script,
+        function,
+      };
+      registry.handlers.push((hook, callback));
+      Ok(())
+    },
+  );
+
+  let snapshot = snapshot.clone();
+  engine.register_fn("state", move || snapshot.lock().unwrap().clone());
+
+  let dispatch = move |name: &'static str, sender: &Sender<Event>| {
+    let sender = sender.clone();
+    move |event: Event| {
+      // the io only stops when orpheus quits
+      if sender.send(event).is_err() {
+        tracing::debug!("dropped the {name} of a script");
+      }
+    }
+  };
+
+  let play = dispatch("play", &sender);
+  engine.register_fn("play", move || play(Event::Play(None)));
+  let play = dispatch("play", &sender);
+  engine.register_fn("play", move |uri: &str| -> ScriptResult<()> {
+    play(Event::Play(Some(target(uri)?)));
+    Ok(())
+  });
+  let pause = dispatch("pause", &sender);
+  engine.register_fn("pause", move || pause(Event::Pause));
+  let next = dispatch("next", &sender);
+  engine.register_fn("next", move || next(Event::NextTrack));
+  let previous = dispatch("previous", &sender);
+  engine.register_fn("previous", move || previous(Event::PreviousTrack));
+  let seek = dispatch("seek", &sender);
+  engine.register_fn("seek", move |ms: INT| seek(Event::Seek(ms.max(0) as u32)));
+  let queue = dispatch("queue", &sender);
+  engine.register_fn("queue", move |uri: &str| -> ScriptResult<()> {
+    match target(uri)? {
+      PlayTarget::Item(id) => queue(Event::Queue(id)),
+      PlayTarget::Context(_) => return Err(error("only tracks and episodes can be queued")),
+    }
+    Ok(())
+  });
+  let volume = dispatch("volume", &sender);
+  engine.register_fn("volume", move |level: INT| {
+    volume(Event::Volume(level.clamp(0, 100) as u8))
+  });
+  let add_to_playlist = dispatch("add_to_playlist", &sender);
+  engine.register_fn(
+    "add_to_playlist",
+    move |playlist: &str, uri: &str| -> ScriptResult<()> {
+      let playlist = PlaylistId::from_id_or_uri(playlist)
+        .map_err(|_| error(format!("'{playlist}' is not a Spotify playlist")))?;
+      match target(uri)? {
+        PlayTarget::Item(id) => {
+          add_to_playlist(Event::AddToPlaylist(playlist.into_static(), vec![id]))
+        }
+        PlayTarget::Context(_) => return Err(error("only tracks and episodes can be added")),
+      }
+      Ok(())
+    },
+  );
+  let like = dispatch("like", &sender);
+  engine.register_fn("like", move |uri: &str| -> ScriptResult<()> {
+    let id =
+      TrackId::from_id_or_uri(uri).map_err(|_| error(format!("'{uri}' is not a Spotify track")))?;
+    like(Event::SaveTrack(id.into_static()));
+    Ok(())
+  });
+  let follow = dispatch("follow", &sender);
+  engine.register_fn("follow", move |uri: &str| -> ScriptResult<()> {
+    let id = ArtistId::from_id_or_uri(uri)
+      .map_err(|_| error(format!("'{uri}' is not a Spotify artist")))?;
+    follow(Event::FollowArtist(id.into_static()));
+    Ok(())
+  });
+
+  engine
+}
+
+fn target(uri: &str) -> ScriptResult<PlayTarget> {
+  PlayTarget::parse(uri).ok_or_else(|| error(format!("'{uri}' is not a playable Spotify URI")))
+}
+
+fn error(message: impl Into<String>) -> Box<EvalAltResult> {
+  EvalAltResult::ErrorRuntime(message.into().into(), Position::NONE).into()
+}
+
+/// Runs the commands sent to `commands` and the handlers of the changes of `state`.
+pub(crate) fn spawn(
+  scripts: Scripts,
+  mut commands: UnboundedReceiver<String>,
+  state: &Arc<Mutex<State>>,
+) -> JoinHandle<()> {
+  let state = state.clone();
+  let scripts = Arc::new(scripts);
+
+  tokio::spawn(async move {
+    let mut changes = state.lock().await.subscribe();
+    let mut watcher = Watcher::new(&*state.lock().await);
+
+    loop {
+      let calls = tokio::select! {
+        Some(name) = commands.recv() => match scripts.command(&name) {
+          Some(callback) => vec![(name, callback, vec![])],
+          None => {
+            state.lock().await.set_info(format!("No script defines the '{name}' command"));
+            continue;
+          }
+        },
+
+        change = changes.recv() => {
+          let change = match change {
+            Ok(change) => change,
+            Err(RecvError::Lagged(_)) => Change::Playback,
+            Err(RecvError::Closed) => return,
+          };
+
+          let due = watcher.update(change, &*state.lock().await);
+          due
+            .into_iter()
+            .flat_map(|(hook, playlist)| {
+              let argument = match playlist {
+                Some(playlist) => rhai::serde::to_dynamic(playlist),
+                None => rhai::serde::to_dynamic(&watcher.playback),
+              }
+              .unwrap_or_default();
+
+              scripts
+                .handlers(hook)
+                .into_iter()
+                .map(move |callback| (hook.name().to_string(), callback, vec![argument.clone()]))
+            })
+            .collect()
+        }
+      };
+
+      for (name, callback, args) in calls {
+        let snapshot = snapshot(&*state.lock().await);
+        let runner = scripts.clone();
+        let result =
+          tokio::task::spawn_blocking(move || runner.call(&callback, args, snapshot)).await;
+
+        if let Ok(Err(err)) = result {
+          tracing::warn!("script {name} failed: {err}");
+          state
+            .lock()
+            .await
+            .set_info(format!("Script {name} failed: {err}"));
+        }
+      }
+    }
+  })
+}
+
+fn snapshot(state: &State) -> Dynamic {
+  let snapshot = Snapshot {
+    profile: state.profile.name().to_string(),
+    playback: NowPlaying::of(state),
+    playlists: state
+      .playlists
+      .iter()
+      .flat_map(|page| page.items.iter().map(Playlist::from))
+      .collect(),
+  };
+
+  rhai::serde::to_dynamic(snapshot).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn built_in_keys_cant_be_bound() {
+    let registry = Arc::default();
+    let (sender, _events) = std::sync::mpsc::channel();
+    let engine = engine(&registry, &Arc::default(), sender);
+
+    assert!(engine.run(r#"bind("P", "quiet")"#).is_err());
+    assert!(engine.run(r#"bind("enter", "quiet")"#).is_err());
+    engine.run(r#"bind("Q", "quiet")"#).unwrap();
+
+    let bindings = &registry.lock().unwrap().bindings;
+    assert_eq!(bindings.len(), 1);
+    assert_eq!(bindings[&Key::Char('Q')], "quiet");
+  }
+}
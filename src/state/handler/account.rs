@@ -0,0 +1,17 @@
+use crate::{
+  io::{Event, key::Key},
+  state::State,
+};
+
+/// Handler for the account popup, which takes over every key.
+pub fn handler(key: Key, state: &mut State) {
+  match key {
+    Key::Char('l') => {
+      state.session = None;
+      state.dispatch(Event::Logout);
+    }
+
+    Key::Esc => state.session = None,
+    _ => {}
+  }
+}
@@ -0,0 +1,26 @@
+use crate::{
+  io::{Event, key::Key},
+  state::State,
+};
+
+/// Handler for the re-authentication prompt, which takes over every key.
+pub fn handler(key: Key, state: &mut State) {
+  let Some(prompt) = &mut state.auth_prompt else {
+    return;
+  };
+
+  match key {
+    Key::Char(c) => prompt.input.push(c),
+    Key::Backspace => {
+      prompt.input.pop();
+    }
+
+    Key::Enter if !prompt.input.is_empty() => {
+      let url = std::mem::take(&mut prompt.input);
+      state.dispatch(Event::FinishReauthentication(url));
+    }
+
+    Key::Esc => state.auth_prompt = None,
+    _ => {}
+  }
+}
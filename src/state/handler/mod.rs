@@ -4,9 +4,15 @@
 
 #![allow(unused)]
 
+mod account;
+mod auth;
 mod playlist;
+mod profile;
 
-use crate::{io::key::Key, state::State};
+use crate::{
+  io::{Event, key::Key},
+  state::State,
+};
 
 /// Represents the full state of the current view.
 ///
@@ -50,8 +56,42 @@ pub const DEFAULT_VIEW: View = View {
   hovered: Active::Library,
 };
 
+/// Keys handled by orpheus itself on the main screen, which scripts can't bind.
+pub const BUILT_IN_KEYS: &[Key] = &[
+  Key::Char('q'),
+  Key::Char('P'),
+  Key::Char('A'),
+  Key::Char('L'),
+  Key::Esc,
+  Key::Enter,
+  Key::Left,
+  Key::Up,
+  Key::Right,
+  Key::Down,
+];
+
 pub fn handle(key: Key, state: &mut State) {
+  if state.auth_prompt.is_some() {
+    return auth::handler(key, state);
+  }
+
+  if state.profile_switcher.is_some() {
+    return profile::handler(key, state);
+  }
+
+  if state.session.is_some() {
+    return account::handler(key, state);
+  }
+
   match key {
+    Key::Esc if state.status.is_some() => state.dismiss_status(),
+    key if state.bindings.contains_key(&key) => {
+      let command = state.bindings[&key].clone();
+      state.run_command(&command)
+    }
+    Key::Char('P') => state.open_profile_switcher(),
+    Key::Char('A') => state.dispatch(Event::Account),
+    Key::Char('L') => state.toggle_lyrics(),
     Key::Esc => handle_esc(state),
     _ => handle_view(key, state),
   }
@@ -90,10 +130,9 @@ fn handler(key: Key, state: &mut State) {
     },
 
     Key::Right => match state.current_view().hovered {
-      Active::Playlists | Active::Library => match state.current_view().id {
-        ViewId::Home => state.set_current_view(None, Some(Active::Home)),
-        _ => {}
-      },
+      Active::Playlists | Active::Library if state.current_view().id == ViewId::Home => {
+        state.set_current_view(None, Some(Active::Home))
+      }
       _ => {}
     },
 
@@ -134,7 +173,5 @@ pub fn up_select_handler<I>(data: &[I], index: Option<usize>) -> usize {
 }
 
 fn handle_esc(state: &mut State) {
-  match state.current_view().active {
-    _ => state.set_current_view(Some(Active::None), None),
-  }
+  state.set_current_view(Some(Active::None), None)
 }
@@ -22,12 +22,11 @@ pub fn handler(key: Key, state: &mut State) {
     Key::Enter => {
       if let (Some(playlists), Some(playlist_index)) =
         (&state.playlists, &state.selected_playlist_index)
+        && let Some(playlist) = playlists.items.get(*playlist_index)
       {
-        if let Some(playlist) = playlists.items.get(*playlist_index) {
-          let id = playlist.id.to_owned();
-          // TODO: add playlist offset to state
-          state.dispatch(Event::PlaylistTracks(id, 0));
-        }
+        let id = playlist.id.to_owned();
+        // TODO: add playlist offset to state
+        state.dispatch(Event::PlaylistTracks(id, 0));
       }
     }
 
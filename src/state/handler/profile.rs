@@ -0,0 +1,34 @@
+use crate::{
+  io::{Event, key::Key},
+  state::{State, handler},
+};
+
+/// Handler for the profile switcher, which takes over every key.
+pub fn handler(key: Key, state: &mut State) {
+  let Some(switcher) = &mut state.profile_switcher else {
+    return;
+  };
+
+  match key {
+    Key::Down => {
+      switcher.selected = handler::down_select_handler(&switcher.profiles, Some(switcher.selected))
+    }
+    Key::Up => {
+      switcher.selected = handler::up_select_handler(&switcher.profiles, Some(switcher.selected))
+    }
+
+    Key::Enter => {
+      let profile = switcher.profiles.get(switcher.selected).cloned();
+      state.profile_switcher = None;
+
+      if let Some(profile) = profile
+        && profile != state.profile
+      {
+        state.dispatch(Event::SwitchProfile(profile));
+      }
+    }
+
+    Key::Esc => state.profile_switcher = None,
+    _ => {}
+  }
+}
@@ -9,59 +9,338 @@
 
 pub(crate) mod handler;
 
+#[cfg(feature = "album-art")]
+use crate::ui::cover::Covers;
 use crate::{
+  auth::{profile::Profile, session::Session},
   config::Config,
-  io::Event,
+  io::{Event, IoError, key::Key},
+  lyrics::{self, Lyrics},
   state::handler::{Active, DEFAULT_VIEW, View, ViewId},
 };
-use rspotify::model::{
-  CurrentPlaybackContext, Page, PlayableItem, PlaylistItem, SimplifiedPlaylist,
+use rspotify::{
+  model::{
+    CurrentPlaybackContext, Page, PlayableItem, PlaylistItem, RepeatState, SimplifiedPlaylist,
+  },
+  prelude::Id,
 };
-use std::{sync::mpsc::Sender, time::Instant};
+use std::{collections::HashMap, sync::mpsc::Sender, time::Instant};
+use tokio::sync::{broadcast, mpsc::UnboundedSender};
 
 /// All state that the application holds
 /// in order to render the UI.
 #[allow(unused)]
 pub(crate) struct State {
   pub config: Config,
+  pub profile: Profile,
 
   sender: Option<Sender<Event>>,
 
   pub playlists: Option<Page<SimplifiedPlaylist>>,
+  /// Whether the playlists were restored from the [cache](crate::io::cache) and not fetched yet.
+  playlists_are_cached: bool,
+  last_playlists_poll: Instant,
   pub selected_playlist_index: Option<usize>,
   pub playlist_tracks: Option<Page<PlaylistItem>>,
 
   pub current_playback_context: Option<CurrentPlaybackContext>,
+  /// When the playback was fetched, to tell its progress since.
+  playback_at: Instant,
   last_playback_pool: Instant,
   is_fetching_playback: bool,
 
   navigation: Vec<View>,
 
+  /// Lyrics of the playing track, loaded by the [lyrics](crate::lyrics) task.
+  pub lyrics: Option<Lyrics>,
+  pub show_lyrics: bool,
+
   seek_ms: Option<u128>,
+
+  pub status: Option<Status>,
+  /// Whether Spotify can't be reached, so the cached library is shown and changes are queued.
+  pub is_offline: bool,
+  pub auth_prompt: Option<AuthPrompt>,
+  pub profile_switcher: Option<ProfileSwitcher>,
+  /// Details of the signed in account, shown in a popup while set.
+  pub session: Option<Session>,
+
+  /// Keys bound to the commands of the [scripts](crate::scripts).
+  pub bindings: HashMap<Key, String>,
+  /// Sends the commands to run to the [scripts](crate::scripts).
+  commands: Option<UnboundedSender<String>>,
+
+  /// The album art drawn in the UI.
+  #[cfg(feature = "album-art")]
+  pub covers: Covers,
+
+  /// Notifies the subscribers, like the [IPC](crate::ipc) or the [hooks](crate::hooks), of changes.
+  changes: broadcast::Sender<Change>,
+}
+
+/// A change of the state pushed to its [subscribers](State::subscribe).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Change {
+  Playback,
+  /// The playing item jumped to another position, seeked here or elsewhere.
+  Seeked,
+  Profile,
+  /// The user's playlists were fetched again and differ.
+  Playlists,
+}
+
+/// Milliseconds the polled progress may be off from the expected one without
+/// being taken for a seek, with the latency of the requests.
+const SEEK_TOLERANCE_MS: u32 = 2000;
+
+/// What the [subscribers](State::subscribe) are told about when it changes.
+#[derive(PartialEq)]
+struct PlaybackSummary<'a> {
+  /// URI of the item, or the name of a local file, which has no id.
+  item: Option<String>,
+  is_playing: bool,
+  device_id: Option<&'a str>,
+  shuffle_state: bool,
+  repeat_state: RepeatState,
+  volume_percent: Option<u32>,
+}
+
+impl<'a> PlaybackSummary<'a> {
+  fn of(context: Option<&'a CurrentPlaybackContext>) -> Option<Self> {
+    let context = context?;
+    let item = match &context.item {
+      Some(PlayableItem::Track(track)) => Some(lyrics::uri(track)),
+      Some(PlayableItem::Episode(episode)) => Some(episode.id.uri()),
+      _ => None,
+    };
+
+    Some(Self {
+      item,
+      is_playing: context.is_playing,
+      device_id: context.device.id.as_deref(),
+      shuffle_state: context.shuffle_state,
+      repeat_state: context.repeat_state,
+      volume_percent: context.device.volume_percent,
+    })
+  }
+}
+
+/// Prompt shown when the session can't be refreshed and the user
+/// has to authorize orpheus again.
+#[derive(Debug)]
+pub(crate) struct AuthPrompt {
+  /// Spotify's authorization URL, opened in the browser.
+  pub url: String,
+  /// The redirect URL typed (or pasted) by the user.
+  pub input: String,
+}
+
+impl AuthPrompt {
+  pub fn new(url: String) -> Self {
+    Self {
+      url,
+      input: String::new(),
+    }
+  }
 }
 
+/// Popup listing the profiles to switch to.
+#[derive(Debug)]
+pub(crate) struct ProfileSwitcher {
+  pub profiles: Vec<Profile>,
+  pub selected: usize,
+}
+
+/// A message shown in the status line until it's dismissed.
+#[derive(Debug, Clone)]
+pub(crate) enum Status {
+  Error(IoError),
+  Info(String),
+}
+
+#[allow(unused)]
 impl State {
-  pub fn new(config: Config, sender: Sender<Event>) -> Self {
+  pub fn new(config: Config, profile: Profile, sender: Sender<Event>) -> Self {
     Self {
+      #[cfg(feature = "album-art")]
+      covers: Covers::new(config.album_art, profile.cache_dir().ok()),
       config,
+      profile,
       sender: Some(sender),
       playlists: None,
+      playlists_are_cached: false,
+      last_playlists_poll: Instant::now(),
       last_playback_pool: Instant::now(),
       current_playback_context: None,
+      playback_at: Instant::now(),
       seek_ms: None,
       is_fetching_playback: false,
       navigation: vec![DEFAULT_VIEW],
+      lyrics: None,
+      show_lyrics: false,
       selected_playlist_index: Some(0),
       playlist_tracks: None,
+      status: None,
+      is_offline: false,
+      auth_prompt: None,
+      profile_switcher: None,
+      session: None,
+      bindings: HashMap::new(),
+      commands: None,
+      changes: broadcast::channel(16).0,
     }
   }
 
   pub fn dispatch(&mut self, event: Event) {
-    if let Some(sender) = &self.sender {
-      if let Err(err) = sender.send(event) {
-        panic!("{err}")
-      }
+    if let Some(sender) = &self.sender
+      && let Err(err) = sender.send(event)
+    {
+      panic!("{err}")
+    }
+  }
+
+  /// Binds the keys of the loaded [scripts](crate::scripts), sending their commands to `commands`.
+  pub fn set_scripts(&mut self, bindings: HashMap<Key, String>, commands: UnboundedSender<String>) {
+    self.bindings = bindings;
+    self.commands = Some(commands);
+  }
+
+  /// Runs the script command `name`.
+  pub fn run_command(&mut self, name: &str) {
+    match &self.commands {
+      Some(commands) if commands.send(name.to_string()).is_ok() => {}
+      _ => self.set_info(format!("No script defines the '{name}' command")),
+    }
+  }
+
+  pub fn subscribe(&self) -> broadcast::Receiver<Change> {
+    self.changes.subscribe()
+  }
+
+  fn changed(&self, change: Change) {
+    // there's no receiver when nothing subscribed, that's fine
+    let _ = self.changes.send(change);
+  }
+
+  /// Stores the playback fetched by the [io](crate::io::Io).
+  pub fn set_playback(&mut self, context: Option<CurrentPlaybackContext>) {
+    // the progress moves on with every poll, which isn't worth announcing
+    let is_changed = PlaybackSummary::of(self.current_playback_context.as_ref())
+      != PlaybackSummary::of(context.as_ref());
+    let progress = context
+      .as_ref()
+      .and_then(|context| context.progress)
+      .map(|progress| progress.num_milliseconds().max(0) as u32);
+    let is_seeked = !is_changed
+      && self
+        .progress_ms()
+        .zip(progress)
+        .is_some_and(|(expected, progress)| expected.abs_diff(progress) > SEEK_TOLERANCE_MS);
+
+    self.current_playback_context = context;
+    self.playback_at = Instant::now();
+    if is_changed {
+      self.changed(Change::Playback);
+    } else if is_seeked {
+      self.changed(Change::Seeked);
+    }
+  }
+
+  /// Milliseconds into the playing item, counting the time since the playback was fetched.
+  pub fn progress_ms(&self) -> Option<u32> {
+    let context = self.current_playback_context.as_ref()?;
+    let progress = context.progress?.num_milliseconds().max(0) as u32;
+
+    match context.is_playing {
+      true => Some(progress + self.playback_at.elapsed().as_millis() as u32),
+      false => Some(progress),
+    }
+  }
+
+  pub fn toggle_lyrics(&mut self) {
+    self.show_lyrics = !self.show_lyrics;
+  }
+
+  /// Stores the playlists fetched by the [io](crate::io::Io).
+  pub fn set_playlists(&mut self, playlists: Page<SimplifiedPlaylist>) {
+    // the cached playlists weren't announced, so the first fetched always are
+    if self.playlists_are_cached || self.playlists.as_ref() != Some(&playlists) {
+      self.playlists = Some(playlists);
+      self.playlists_are_cached = false;
+      self.changed(Change::Playlists);
+    }
+  }
+
+  /// Whether the playlists are still those of the [cache](crate::io::cache).
+  pub fn playlists_are_cached(&self) -> bool {
+    self.playlists_are_cached
+  }
+
+  /// Shows the playlists of the [cache](crate::io::cache) until they're fetched.
+  pub fn restore_playlists(&mut self, playlists: Page<SimplifiedPlaylist>) {
+    if self.playlists.is_none() {
+      self.playlists = Some(playlists);
+      self.playlists_are_cached = true;
+    }
+  }
+
+  /// Lets the playback be [polled](Self::update_tick) again, whether fetching it worked or not.
+  pub fn finish_fetching_playback(&mut self) {
+    self.is_fetching_playback = false;
+  }
+
+  pub fn set_error(&mut self, err: IoError) {
+    self.status = Some(Status::Error(err));
+  }
+
+  pub fn set_info(&mut self, message: impl Into<String>) {
+    self.status = Some(Status::Info(message.into()));
+  }
+
+  pub fn dismiss_status(&mut self) {
+    self.status = None;
+  }
+
+  /// Opens the [switcher](self::ProfileSwitcher) on the current profile.
+  pub fn open_profile_switcher(&mut self) {
+    let profiles = match Profile::list() {
+      Ok(profiles) => profiles,
+      Err(err) => return self.set_info(err.to_string()),
+    };
+    let selected = profiles
+      .iter()
+      .position(|profile| profile == &self.profile)
+      .unwrap_or(0);
+
+    self.profile_switcher = Some(ProfileSwitcher { profiles, selected });
+  }
+
+  pub fn switch_profile(&mut self, profile: Profile, config: Config) {
+    self.profile = profile;
+    self.config = config;
+    #[cfg(feature = "album-art")]
+    {
+      self.covers = Covers::new(self.config.album_art, self.profile.cache_dir().ok());
     }
+    self.clear_account_data();
+    self.changed(Change::Profile);
+  }
+
+  /// Drops everything fetched with the previous account.
+  pub fn clear_account_data(&mut self) {
+    self.session = None;
+    self.playlists = None;
+    self.playlists_are_cached = false;
+    self.selected_playlist_index = Some(0);
+    self.playlist_tracks = None;
+    self.current_playback_context = None;
+    self.lyrics = None;
+    self.navigation = vec![DEFAULT_VIEW];
+  }
+
+  /// Whether keys are being typed into an input, so they're not shortcuts.
+  pub fn is_typing(&self) -> bool {
+    self.auth_prompt.is_some()
   }
 
   #[inline(always)]
@@ -107,7 +386,18 @@ impl State {
   }
 
   pub fn update_tick(&mut self) {
-    todo!()
+    self.poll_playback();
+    self.poll_playlists();
+  }
+
+  /// Fetches the playlists again once in a while, to catch changes made elsewhere.
+  fn poll_playlists(&mut self) {
+    const POLL_INTERVAL: u64 = 60;
+
+    if self.playlists.is_some() && self.last_playlists_poll.elapsed().as_secs() >= POLL_INTERVAL {
+      self.last_playlists_poll = Instant::now();
+      self.dispatch(Event::UserPlaylists);
+    }
   }
 
   fn poll_playback(&mut self) {
@@ -115,8 +405,11 @@ impl State {
 
     let elapsed = self.last_playback_pool.elapsed().as_millis();
     if !self.is_fetching_playback && elapsed >= POOL_INTERVAL {
+      self.last_playback_pool = Instant::now();
+      self.is_fetching_playback = true;
+
       match self.seek_ms {
-        Some(seek) => todo!(),
+        Some(_seek) => todo!(),
         _ => self.dispatch(Event::GetCurrentPlayback),
       }
     }
@@ -143,3 +436,64 @@ impl State {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::io::backend::fake;
+  use rspotify::model::FullTrack;
+  use tokio::sync::broadcast::error::TryRecvError;
+
+  fn state() -> State {
+    let (sender, _events) = std::sync::mpsc::channel();
+    State::new(Config::default(), Profile::rooted("unused"), sender)
+  }
+
+  fn local(name: &str) -> FullTrack {
+    let mut track = fake::track("local", name);
+    track.id = None;
+    track.is_local = true;
+    track
+  }
+
+  #[test]
+  fn only_changes_of_the_summary_and_seeks_are_announced() {
+    let mut state = state();
+    let mut changes = state.subscribe();
+    let track = fake::track("first", "First");
+
+    state.set_playback(Some(fake::playback(track.clone(), true)));
+    assert_eq!(changes.try_recv(), Ok(Change::Playback));
+
+    // a later poll, only the progress moved
+    let mut playback = fake::playback(track.clone(), true);
+    playback.progress = Some(chrono::Duration::seconds(61));
+    state.set_playback(Some(playback));
+    assert_eq!(changes.try_recv(), Err(TryRecvError::Empty));
+    assert_eq!(state.progress_ms().map(|ms| ms / 1000), Some(61));
+
+    // further than it could have played to since
+    let mut playback = fake::playback(track.clone(), true);
+    playback.progress = Some(chrono::Duration::seconds(120));
+    state.set_playback(Some(playback));
+    assert_eq!(changes.try_recv(), Ok(Change::Seeked));
+
+    state.set_playback(Some(fake::playback(track, false)));
+    assert_eq!(changes.try_recv(), Ok(Change::Playback));
+    state.set_playback(None);
+    assert_eq!(changes.try_recv(), Ok(Change::Playback));
+  }
+
+  #[test]
+  fn local_tracks_are_told_apart_by_name() {
+    let mut state = state();
+    let mut changes = state.subscribe();
+
+    state.set_playback(Some(fake::playback(local("One"), true)));
+    assert_eq!(changes.try_recv(), Ok(Change::Playback));
+    state.set_playback(Some(fake::playback(local("One"), true)));
+    assert_eq!(changes.try_recv(), Err(TryRecvError::Empty));
+    state.set_playback(Some(fake::playback(local("Two"), true)));
+    assert_eq!(changes.try_recv(), Ok(Change::Playback));
+  }
+}
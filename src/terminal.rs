@@ -21,7 +21,10 @@ use ratatui::{
   },
   prelude::CrosstermBackend,
 };
-use std::{io::stdout, sync::Arc};
+use std::{
+  io::{Stdout, stdout},
+  sync::Arc,
+};
 use thiserror::Error;
 use tokio::sync::Mutex;
 
@@ -33,38 +36,60 @@ pub enum TerminalError {
   Recv(#[from] std::sync::mpsc::RecvError),
 }
 
-pub(crate) async fn start(config: &Config, state: &Arc<Mutex<State>>) -> Result<(), TerminalError> {
-  let mut out = stdout();
-  execute!(out, EnterAlternateScreen, EnableMouseCapture)?;
-  enable_raw_mode()?;
+/// The terminal in the alternate screen, with the handler of its key events.
+pub(crate) struct Tui {
+  pub terminal: Terminal<CrosstermBackend<Stdout>>,
+  pub events: EventHandler,
+}
+
+impl Tui {
+  pub fn enter(config: &Config) -> Result<Self, TerminalError> {
+    let mut out = stdout();
+    execute!(out, EnterAlternateScreen, EnableMouseCapture)?;
+    enable_raw_mode()?;
 
-  let mut backend = CrosstermBackend::new(out);
-  backend.execute(SetTitle("orpheus"))?;
+    let mut backend = CrosstermBackend::new(out);
+    backend.execute(SetTitle("orpheus"))?;
 
-  let mut terminal = Terminal::new(backend)?;
-  terminal.hide_cursor()?;
+    let mut terminal = Terminal::new(backend)?;
+    terminal.hide_cursor()?;
 
-  let event_handler = EventHandler::new(config.tick_rate.as_millis() as _);
-  println!("{duration}", duration = config.tick_rate.as_millis() as u64);
+    let events = EventHandler::new(config.tick_rate.as_millis() as _);
+
+    Ok(Self { terminal, events })
+  }
 
+  pub fn leave(mut self) -> Result<(), TerminalError> {
+    disable_raw_mode()?;
+    execute!(
+      self.terminal.backend_mut(),
+      LeaveAlternateScreen,
+      DisableMouseCapture
+    )?;
+    self.terminal.show_cursor()?;
+    Ok(())
+  }
+}
+
+pub(crate) async fn start(tui: &mut Tui, state: &Arc<Mutex<State>>) -> Result<(), TerminalError> {
   let mut is_first_render = true;
 
   loop {
     let mut state = state.lock().await;
 
-    terminal.draw(|mut f| draw(&mut f, &state))?;
+    tui.terminal.draw(|f| draw(f, &state))?;
+    #[cfg(feature = "album-art")]
+    state.covers.flush(&mut tui.terminal)?;
 
-    match event_handler.next()? {
-      // TODO: only quit if not in the search input
+    match tui.events.next()? {
       key::Event::Input(key) => {
-        if key == Key::Char('q') {
+        if key == Key::Char('q') && !state.is_typing() {
           break;
         };
 
         handler::handle(key, &mut state);
       }
-
-      _ => {}
+      key::Event::Tick => state.update_tick(),
     }
 
     if is_first_render {
@@ -74,8 +99,5 @@ pub(crate) async fn start(config: &Config, state: &Arc<Mutex<State>>) -> Result<
     }
   }
 
-  disable_raw_mode()?;
-  let mut out = stdout();
-  execute!(out, LeaveAlternateScreen, DisableMouseCapture)?;
   Ok(())
 }
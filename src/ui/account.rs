@@ -0,0 +1,51 @@
+use crate::{
+  state::State,
+  ui::{auth::centered, pad, style::Palette},
+};
+use ratatui::{
+  Frame,
+  style::Style,
+  text::{Line, Span, Text},
+  widgets::{Block, Clear, Padding, Paragraph, Wrap},
+};
+
+pub fn draw_account(frame: &mut Frame, state: &State, palette: &Palette) {
+  let Some(session) = &state.session else {
+    return;
+  };
+
+  let area = centered(frame.area(), 60, 14);
+
+  let block = Block::bordered()
+    .title(pad("Account", 1))
+    .border_style(Style::default().fg(palette.accent))
+    .style(Style::default().bg(palette.background).fg(palette.text))
+    .padding(Padding::horizontal(1));
+
+  let muted = Style::default().fg(palette.muted);
+  let field = |name: &'static str, value: String| {
+    Line::from(vec![
+      Span::styled(format!("{name:<10}"), muted),
+      Span::raw(value),
+    ])
+  };
+
+  let text = Text::from(vec![
+    field("Profile", state.profile.name().to_string()),
+    field(
+      "Account",
+      format!("{} ({})", session.account(), session.user_id),
+    ),
+    field("Client ID", session.client_id.clone()),
+    field("Expires", session.expiry()),
+    field("Scopes", session.scopes.join(", ")),
+    Line::default(),
+    Line::from(Span::styled("(l to log out, esc to close)", muted)),
+  ]);
+
+  frame.render_widget(Clear, area);
+  frame.render_widget(
+    Paragraph::new(text).block(block).wrap(Wrap { trim: false }),
+    area,
+  );
+}
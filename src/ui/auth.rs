@@ -0,0 +1,70 @@
+use crate::{
+  auth::AuthMode,
+  state::State,
+  ui::{pad, style::Palette},
+};
+use ratatui::{
+  Frame,
+  layout::{Constraint, Flex, Layout, Rect},
+  style::Style,
+  text::{Line, Span, Text},
+  widgets::{Block, Clear, Padding, Paragraph, Wrap},
+};
+
+pub fn draw_auth_prompt(frame: &mut Frame, state: &State, palette: &Palette) {
+  let Some(prompt) = &state.auth_prompt else {
+    return;
+  };
+
+  let area = centered(frame.area(), 70, 14);
+
+  let block = Block::bordered()
+    .title(pad("Sign in again", 1))
+    .border_style(Style::default().fg(palette.accent))
+    .style(Style::default().bg(palette.background).fg(palette.text))
+    .padding(Padding::horizontal(1));
+
+  let (visit, paste) = match state.config.auth.mode {
+    AuthMode::Browser => (
+      "Authorize orpheus in the browser window that was opened, or visit:",
+      "Orpheus will pick it up, otherwise paste the URL you were redirected to:",
+    ),
+    AuthMode::Headless => (
+      "Open this URL in a browser on any device and authorize orpheus:",
+      "Then paste the URL you were redirected to, or just its code:",
+    ),
+  };
+
+  let muted = Style::default().fg(palette.muted);
+  let text = Text::from(vec![
+    Line::from("Orpheus needs to be authorized with your Spotify account."),
+    Line::from(visit),
+    Line::from(Span::styled(prompt.url.as_str(), muted)),
+    Line::default(),
+    Line::from(paste),
+    Line::from(vec![
+      Span::styled("> ", Style::default().fg(palette.accent)),
+      Span::raw(prompt.input.as_str()),
+    ]),
+    Line::default(),
+    Line::from(Span::styled("(esc to cancel)", muted)),
+  ]);
+
+  frame.render_widget(Clear, area);
+  frame.render_widget(
+    Paragraph::new(text).block(block).wrap(Wrap { trim: false }),
+    area,
+  );
+}
+
+/// A `width`% wide and `height` lines tall area in the center of `area`.
+pub fn centered(area: Rect, width: u16, height: u16) -> Rect {
+  let [area] = Layout::horizontal([Constraint::Percentage(width)])
+    .flex(Flex::Center)
+    .areas(area);
+  let [area] = Layout::vertical([Constraint::Length(height)])
+    .flex(Flex::Center)
+    .areas(area);
+
+  area
+}
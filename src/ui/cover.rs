@@ -0,0 +1,622 @@
+//! Album art, drawn with the graphics protocol of the terminal.
+//!
+//! Covers are downloaded in the [cache](crate::auth::profile::Profile::cache_dir),
+//! decoded and scaled to the area they're drawn in in the background, so drawing
+//! never waits for them: their area stays empty until they're ready. Each size
+//! of a cover is then kept for the next frames, and those that failed to load
+//! are tried again after a while.
+//!
+//! The vibrant colour of each cover is picked while decoding it, for the
+//! [dynamic accent](crate::config::Config::dynamic_accent).
+//!
+//! `Halfblocks` covers are drawn in the cells like any text. The others are
+//! escape sequences the cells of the cover are skipped for, written after the
+//! frame by [flush](Covers::flush) when they aren't on the screen already.
+
+use crate::{images, ui::style::ImageProtocol};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use image::{DynamicImage, ImageFormat, RgbImage, imageops::FilterType};
+use ratatui::{
+  Frame, Terminal,
+  buffer::{Buffer, CellDiffOption},
+  crossterm::{cursor::MoveTo, queue, terminal::window_size},
+  layout::{Rect, Size},
+  prelude::CrosstermBackend,
+  style::Color as Colour,
+};
+use std::{
+  collections::{BTreeSet, HashMap},
+  fmt::Write as _,
+  io::{Cursor, Stdout, Write},
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+/// Covers kept decoded, the least recently drawn being dropped past it.
+const MAX_IMAGES: usize = 32;
+
+/// Size in pixels of a cell, when the terminal doesn't tell.
+const CELL_SIZE: (u32, u32) = (10, 20);
+
+/// Size of the base64 chunks of the kitty protocol.
+const KITTY_CHUNK: usize = 4096;
+
+/// Time before loading a cover that failed again.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Hue ranges the colours of a cover are grouped in to find its vibrant one.
+const HUE_BINS: usize = 36;
+
+enum Entry {
+  Loading,
+  /// Failed at the instant, loaded again past [RETRY_DELAY].
+  Failed(Instant),
+  Ready {
+    image: Arc<DynamicImage>,
+    vibrant: Option<Colour>,
+  },
+}
+
+/// A cover scaled to an area.
+enum Rendered {
+  /// The top and bottom colour of each cell, row by row.
+  Cells(Vec<(Colour, Colour)>),
+  Sequence(String),
+}
+
+#[derive(Default)]
+struct Cache {
+  /// Covers by URL, with the frame they were last drawn in.
+  images: HashMap<String, (Entry, u64)>,
+  /// Covers by URL and size of their area, `None` while they're being scaled.
+  rendered: HashMap<(String, u16, u16), Option<Arc<Rendered>>>,
+  frame: u64,
+}
+
+/// A cover drawn with an escape sequence.
+struct Placement {
+  area: Rect,
+  url: String,
+  sequence: Arc<Rendered>,
+}
+
+impl PartialEq for Placement {
+  fn eq(&self, other: &Self) -> bool {
+    self.area == other.area && self.url == other.url
+  }
+}
+
+#[derive(Default)]
+struct Placements {
+  /// Placed in the frame being drawn.
+  next: Vec<Placement>,
+  /// On the screen, as of the terminal `size`.
+  shown: Vec<Placement>,
+  size: Option<Size>,
+}
+
+/// The covers drawn in the UI, part of the [state](crate::state::State).
+pub(crate) struct Covers {
+  protocol: ImageProtocol,
+  /// Where the covers are downloaded.
+  dir: Option<PathBuf>,
+  cache: Arc<Mutex<Cache>>,
+  placements: Mutex<Placements>,
+}
+
+impl Covers {
+  pub fn new(protocol: ImageProtocol, cache_dir: Option<PathBuf>) -> Self {
+    let protocol = match protocol {
+      ImageProtocol::Auto => detect(),
+      protocol => protocol,
+    };
+
+    Self {
+      protocol,
+      dir: cache_dir.map(|dir| dir.join("covers")),
+      cache: Arc::default(),
+      placements: Mutex::default(),
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.protocol != ImageProtocol::None
+  }
+
+  /// The vibrant colour of the cover at `url`, loading it if it's not yet.
+  pub fn vibrant(&self, url: &str) -> Option<Colour> {
+    let mut cache = self.cache.lock().unwrap();
+    match self.entry(&mut cache, url)? {
+      Entry::Ready { vibrant, .. } => *vibrant,
+      _ => None,
+    }
+  }
+
+  /// The cover at `url`, loading it if it's not yet.
+  fn entry<'a>(&self, cache: &'a mut Cache, url: &str) -> Option<&'a Entry> {
+    let frame = cache.frame;
+
+    let is_loaded = match cache.images.get(url) {
+      Some((Entry::Failed(at), _)) => at.elapsed() < RETRY_DELAY,
+      Some(_) => true,
+      None => false,
+    };
+    if !is_loaded {
+      cache
+        .images
+        .insert(url.to_string(), (Entry::Loading, frame));
+      evict(cache);
+      self.load(url);
+      return None;
+    }
+
+    let (entry, drawn) = cache.images.get_mut(url)?;
+    *drawn = frame;
+    Some(entry)
+  }
+
+  /// The cover at `url` scaled to `area`, loading and scaling it if it's not yet.
+  fn render(&self, url: &str, area: Rect) -> Option<Arc<Rendered>> {
+    let mut cache = self.cache.lock().unwrap();
+    let image = match self.entry(&mut cache, url)? {
+      Entry::Ready { image, .. } => image.clone(),
+      _ => return None,
+    };
+
+    let key = (url.to_string(), area.width, area.height);
+    if let Some(rendered) = cache.rendered.get(&key) {
+      return rendered.clone();
+    }
+    cache.rendered.insert(key.clone(), None);
+
+    // scaling and encoding take longer than a frame
+    let cache = self.cache.clone();
+    let protocol = self.protocol;
+    tokio::task::spawn_blocking(move || {
+      let rendered = Arc::new(render(protocol, &image, area));
+      // unless the cover was dropped meanwhile
+      if let Some(current) = cache.lock().unwrap().rendered.get_mut(&key) {
+        *current = Some(rendered);
+      }
+    });
+    None
+  }
+
+  /// Downloads and decodes the cover at `url` in the background.
+  fn load(&self, url: &str) {
+    let cache = self.cache.clone();
+    let dir = self.dir.clone();
+    let url = url.to_string();
+
+    tokio::spawn(async move {
+      let path = match &dir {
+        Some(dir) => images::download(dir, &url).await,
+        None => None,
+      };
+      let image = match path {
+        Some(path) => tokio::task::spawn_blocking(move || {
+          let image = decode(&path)?;
+          let vibrant = vibrant(&image);
+          Some((image, vibrant))
+        })
+        .await
+        .ok()
+        .flatten(),
+        None => None,
+      };
+
+      let entry = match image {
+        Some((image, vibrant)) => Entry::Ready {
+          image: Arc::new(image),
+          vibrant,
+        },
+        None => Entry::Failed(Instant::now()),
+      };
+      if let Some((current, _)) = cache.lock().unwrap().images.get_mut(&url) {
+        *current = entry;
+      }
+    });
+  }
+
+  /// Drops the covers hidden by what was drawn after them, like a popup,
+  /// so that they're written again once it's gone.
+  pub fn settle(&self, buffer: &Buffer) {
+    self.cache.lock().unwrap().frame += 1;
+
+    let mut placements = self.placements.lock().unwrap();
+    placements.next.retain(|placement| {
+      let is_skipped = |cell| buffer[cell].diff_option == CellDiffOption::Skip;
+      placement.area.positions().all(is_skipped)
+    });
+  }
+
+  /// Writes the covers placed in the last frame that aren't on the screen yet.
+  pub fn flush(&self, terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> std::io::Result<()> {
+    let size = terminal.size()?;
+    let mut placements = self.placements.lock().unwrap();
+    let next = std::mem::take(&mut placements.next);
+
+    // resizing clears the screen
+    let is_cleared = placements.size != Some(size);
+    if !is_cleared && next == placements.shown {
+      return Ok(());
+    }
+
+    let writer = terminal.backend_mut();
+    let is_kitty = self.protocol == ImageProtocol::Kitty;
+    if is_kitty {
+      // kitty images are above the text, they don't go away with it
+      write!(writer, "\x1b_Ga=d,d=A,q=2\x1b\\")?;
+    }
+
+    for placement in &next {
+      if (is_kitty || is_cleared || !placements.shown.contains(placement))
+        && let Rendered::Sequence(sequence) = &*placement.sequence
+      {
+        queue!(writer, MoveTo(placement.area.x, placement.area.y))?;
+        writer.write_all(sequence.as_bytes())?;
+      }
+    }
+    writer.flush()?;
+
+    placements.shown = next;
+    placements.size = Some(size);
+    Ok(())
+  }
+}
+
+/// Draws the cover at `url` in `area`, or leaves it empty until it's loaded.
+pub fn draw(frame: &mut Frame, covers: &Covers, url: &str, area: Rect) {
+  if !covers.is_enabled() || area.is_empty() {
+    return;
+  }
+  let Some(rendered) = covers.render(url, area) else {
+    return;
+  };
+
+  let buffer = frame.buffer_mut();
+  match &*rendered {
+    Rendered::Cells(colours) => {
+      for (cell, (top, bottom)) in area.positions().zip(colours) {
+        buffer[cell].set_symbol("▀").set_fg(*top).set_bg(*bottom);
+      }
+    }
+    Rendered::Sequence(_) => {
+      for cell in area.positions() {
+        buffer[cell].set_diff_option(CellDiffOption::Skip);
+      }
+      covers.placements.lock().unwrap().next.push(Placement {
+        area,
+        url: url.to_string(),
+        sequence: rendered,
+      });
+    }
+  }
+}
+
+/// The protocol of the terminal, guessed from its environment.
+fn detect() -> ImageProtocol {
+  let var = |name: &str| std::env::var(name).unwrap_or_default();
+  let (term, program) = (var("TERM"), var("TERM_PROGRAM"));
+
+  // multiplexers don't pass the graphics through
+  if std::env::var_os("TMUX").is_some() || term.starts_with("screen") {
+    return ImageProtocol::Halfblocks;
+  }
+
+  if std::env::var_os("KITTY_WINDOW_ID").is_some() || term.contains("kitty") || program == "ghostty"
+  {
+    ImageProtocol::Kitty
+  } else if program == "iTerm.app" || program == "WezTerm" {
+    ImageProtocol::Iterm2
+  } else if term.contains("foot") || term.contains("mlterm") || term.contains("contour") {
+    ImageProtocol::Sixel
+  } else {
+    ImageProtocol::Halfblocks
+  }
+}
+
+fn decode(path: &Path) -> Option<DynamicImage> {
+  match image::open(path) {
+    Ok(image) => Some(DynamicImage::ImageRgb8(image.to_rgb8())),
+    Err(err) => {
+      tracing::warn!("failed to decode the cover {}: {err}", path.display());
+      None
+    }
+  }
+}
+
+/// The most vivid colour of `image`: the average of the hue range with the
+/// most saturated pixels, leaving out the greys, the darkest and the brightest.
+fn vibrant(image: &DynamicImage) -> Option<Colour> {
+  let thumbnail = image.thumbnail(64, 64).to_rgb8();
+
+  // weight and sums of the channels of each hue range
+  let mut bins = [(0.0, [0.0; 3]); HUE_BINS];
+  for pixel in thumbnail.pixels() {
+    let [r, g, b] = pixel.0.map(|channel| channel as f32 / 255.0);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    if !(0.25..=0.95).contains(&max) {
+      continue;
+    }
+    let saturation = (max - min) / max;
+    if saturation < 0.3 {
+      continue;
+    }
+
+    let hue = if max == r {
+      (g - b) / (max - min)
+    } else if max == g {
+      2.0 + (b - r) / (max - min)
+    } else {
+      4.0 + (r - g) / (max - min)
+    }
+    .rem_euclid(6.0);
+    let bin = ((hue / 6.0 * HUE_BINS as f32) as usize).min(HUE_BINS - 1);
+
+    let weight = saturation * max;
+    let (total, sums) = &mut bins[bin];
+    *total += weight;
+    for (sum, channel) in sums.iter_mut().zip([r, g, b]) {
+      *sum += channel * weight;
+    }
+  }
+
+  let (total, sums) = bins
+    .into_iter()
+    .max_by(|(a, _), (b, _)| a.total_cmp(b))
+    .filter(|(total, _)| *total > 0.0)?;
+  let [r, g, b] = sums.map(|sum| (sum / total * 255.0).round() as u8);
+  Some(Colour::Rgb(r, g, b))
+}
+
+/// Drops the least recently drawn cover past [MAX_IMAGES].
+fn evict(cache: &mut Cache) {
+  if cache.images.len() <= MAX_IMAGES {
+    return;
+  }
+
+  let oldest = cache
+    .images
+    .iter()
+    .filter(|(_, (entry, _))| !matches!(entry, Entry::Loading))
+    .min_by_key(|(_, (_, drawn))| *drawn)
+    .map(|(url, _)| url.clone());
+
+  if let Some(url) = oldest {
+    cache.images.remove(&url);
+    cache
+      .rendered
+      .retain(|(rendered, _, _), _| *rendered != url);
+  }
+}
+
+fn render(protocol: ImageProtocol, image: &DynamicImage, area: Rect) -> Rendered {
+  let (columns, rows) = (area.width as u32, area.height as u32);
+
+  if protocol == ImageProtocol::Halfblocks {
+    let image = image
+      .resize_to_fill(columns, rows * 2, FilterType::Triangle)
+      .to_rgb8();
+    let colour = |x, y| {
+      let [r, g, b] = image.get_pixel(x, y).0;
+      Colour::Rgb(r, g, b)
+    };
+
+    let cells = (0..rows)
+      .flat_map(|row| (0..columns).map(move |column| (column, row)))
+      .map(|(x, y)| (colour(x, y * 2), colour(x, y * 2 + 1)))
+      .collect();
+    return Rendered::Cells(cells);
+  }
+
+  let (width, height) = cell_size();
+  let (width, mut height) = (columns * width, rows * height);
+  if protocol == ImageProtocol::Sixel {
+    // a partial band of sixels would spill on the next row
+    height = (height - height % 6).max(6);
+  }
+  let image = image.resize_to_fill(width, height, FilterType::Triangle);
+
+  let sequence = match protocol {
+    ImageProtocol::Kitty => kitty(&image.to_rgb8(), area),
+    ImageProtocol::Iterm2 => iterm2(&image, area),
+    _ => sixel(&image.to_rgb8()),
+  };
+  Rendered::Sequence(sequence)
+}
+
+fn cell_size() -> (u32, u32) {
+  match window_size() {
+    Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => (
+      (size.width / size.columns) as u32,
+      (size.height / size.rows) as u32,
+    ),
+    _ => CELL_SIZE,
+  }
+}
+
+/// <https://sw.kovidgoyal.net/kitty/graphics-protocol/>
+fn kitty(image: &RgbImage, area: Rect) -> String {
+  let data = STANDARD.encode(image.as_raw());
+  let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+
+  let mut sequence = String::with_capacity(data.len() + chunks.len() * 16);
+  for (i, chunk) in chunks.iter().enumerate() {
+    let more = u8::from(i + 1 < chunks.len());
+    match i {
+      // shown right away scaled to the area, without moving the cursor nor replying
+      0 => write!(
+        sequence,
+        "\x1b_Ga=T,f=24,s={},v={},c={},r={},C=1,q=2,m={more};",
+        image.width(),
+        image.height(),
+        area.width,
+        area.height
+      ),
+      _ => write!(sequence, "\x1b_Gm={more};"),
+    }
+    .ok();
+    // base64 is ASCII
+    sequence.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+    sequence.push_str("\x1b\\");
+  }
+  sequence
+}
+
+/// <https://iterm2.com/documentation-images.html>
+fn iterm2(image: &DynamicImage, area: Rect) -> String {
+  let mut png = vec![];
+  if let Err(err) = image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png) {
+    tracing::warn!("failed to encode a cover: {err}");
+  }
+
+  format!(
+    "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=0:{}\x07",
+    png.len(),
+    area.width,
+    area.height,
+    STANDARD.encode(&png)
+  )
+}
+
+/// <https://vt100.net/docs/vt3xx-gp/chapter14.html>, in the 216 colours of a 6×6×6 cube.
+fn sixel(image: &RgbImage) -> String {
+  let (width, height) = image.dimensions();
+  let level = |channel: u8| (channel as u32 * 5 + 127) / 255;
+  let colours: Vec<u32> = image
+    .pixels()
+    .map(|pixel| level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2]))
+    .collect();
+
+  let mut sequence = format!("\x1bPq\"1;1;{width};{height}");
+  for colour in colours.iter().collect::<BTreeSet<_>>() {
+    // in percents
+    let (r, g, b) = (colour / 36 * 20, colour / 6 % 6 * 20, colour % 6 * 20);
+    write!(sequence, "#{colour};2;{r};{g};{b}").ok();
+  }
+
+  for top in (0..height).step_by(6) {
+    let rows = top..(top + 6).min(height);
+    let band: BTreeSet<u32> = rows
+      .clone()
+      .flat_map(|y| (0..width).map(move |x| (x, y)))
+      .map(|(x, y)| colours[(y * width + x) as usize])
+      .collect();
+
+    for colour in band {
+      write!(sequence, "#{colour}").ok();
+
+      let mut run = ('?', 0);
+      for x in 0..width {
+        let bits = rows
+          .clone()
+          .filter(|y| colours[(y * width + x) as usize] == colour)
+          .fold(0, |bits, y| bits | 1 << (y - top));
+        let sixel = (63 + bits) as u8 as char;
+
+        if run.1 > 0 && run.0 != sixel {
+          push_run(&mut sequence, run);
+          run.1 = 0;
+        }
+        run = (sixel, run.1 + 1);
+      }
+      push_run(&mut sequence, run);
+
+      // back to the start of the band for the next colour
+      sequence.push('$');
+    }
+    sequence.push('-');
+  }
+
+  sequence.push_str("\x1b\\");
+  sequence
+}
+
+fn push_run(sequence: &mut String, (sixel, count): (char, u32)) {
+  match count {
+    0..=3 => sequence.extend(std::iter::repeat_n(sixel, count as usize)),
+    _ => {
+      write!(sequence, "!{count}{sixel}").ok();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use image::Rgb;
+
+  fn covers_with(url: &str, entry: Entry) -> Covers {
+    let covers = Covers::new(ImageProtocol::Halfblocks, None);
+    let mut cache = covers.cache.lock().unwrap();
+    cache.images.insert(url.to_string(), (entry, 0));
+    drop(cache);
+    covers
+  }
+
+  #[tokio::test]
+  async fn covers_are_scaled_in_the_background() {
+    let image = RgbImage::from_pixel(8, 8, Rgb([200, 40, 40]));
+    let entry = Entry::Ready {
+      image: Arc::new(DynamicImage::ImageRgb8(image)),
+      vibrant: None,
+    };
+    let covers = covers_with("cover", entry);
+    let area = Rect::new(0, 0, 4, 2);
+
+    assert!(covers.render("cover", area).is_none());
+    let rendered = loop {
+      match covers.render("cover", area) {
+        Some(rendered) => break rendered,
+        None => tokio::time::sleep(Duration::from_millis(10)).await,
+      }
+    };
+
+    let Rendered::Cells(cells) = &*rendered else {
+      panic!("halfblocks are drawn in the cells");
+    };
+    let red = Colour::Rgb(200, 40, 40);
+    assert_eq!(cells, &vec![(red, red); 8]);
+  }
+
+  #[tokio::test]
+  async fn failed_covers_are_loaded_again_after_a_while() {
+    let failed_at = Instant::now() - RETRY_DELAY / 2;
+    let covers = covers_with("cover", Entry::Failed(failed_at));
+    assert!(covers.vibrant("cover").is_none());
+    let is_loading = |covers: &Covers| {
+      let cache = covers.cache.lock().unwrap();
+      matches!(cache.images["cover"].0, Entry::Loading)
+    };
+    assert!(!is_loading(&covers));
+
+    let covers = covers_with("cover", Entry::Failed(failed_at - RETRY_DELAY));
+    assert!(covers.vibrant("cover").is_none());
+    assert!(is_loading(&covers));
+  }
+
+  #[test]
+  fn vibrant_picks_the_most_saturated_hue() {
+    // mostly a dull grey, with a band of blue and a narrower one of red
+    let image = RgbImage::from_fn(64, 64, |_, y| match y {
+      0..16 => Rgb([30, 60, 220]),
+      16..24 => Rgb([200, 40, 40]),
+      _ => Rgb([128, 128, 128]),
+    });
+
+    let vibrant = vibrant(&DynamicImage::ImageRgb8(image));
+    assert_eq!(vibrant, Some(Colour::Rgb(30, 60, 220)));
+  }
+
+  #[test]
+  fn greyscale_covers_have_no_vibrant_colour() {
+    let image = RgbImage::from_fn(64, 64, |x, _| {
+      let level = (x * 4) as u8;
+      Rgb([level, level, level])
+    });
+
+    assert_eq!(vibrant(&DynamicImage::ImageRgb8(image)), None);
+  }
+}
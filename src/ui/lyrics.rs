@@ -0,0 +1,81 @@
+use crate::{
+  lyrics,
+  state::State,
+  ui::{pad, style::Palette},
+};
+use ratatui::{
+  Frame,
+  layout::{Alignment, Rect},
+  style::{Modifier, Style},
+  text::{Line, Text},
+  widgets::{Block, Padding, Paragraph},
+};
+use rspotify::model::PlayableItem;
+
+pub fn draw_lyrics(frame: &mut Frame, state: &State, palette: &Palette, area: Rect) {
+  if area.is_empty() {
+    return;
+  }
+
+  let block = Block::bordered()
+    .title(pad("Lyrics", 1))
+    .border_style(Style::default().fg(palette.muted))
+    .padding(Padding::horizontal(1));
+  let inner = block.inner(area);
+  frame.render_widget(block, area);
+
+  let (playing, duration_ms) = match state
+    .current_playback_context
+    .as_ref()
+    .and_then(|context| context.item.as_ref())
+  {
+    Some(PlayableItem::Track(track)) => (
+      Some(lyrics::uri(track)),
+      track.duration.num_milliseconds().max(0) as u32,
+    ),
+    _ => (None, 0),
+  };
+
+  // until the lyrics of a new track are loaded, those of the previous are still there
+  let lyrics = state
+    .lyrics
+    .as_ref()
+    .filter(|lyrics| Some(&lyrics.uri) == playing.as_ref());
+  let Some(lyrics) = lyrics else {
+    let message = match playing {
+      Some(_) => "No lyrics for this track",
+      None => "Nothing playing",
+    };
+    let paragraph = Paragraph::new(Line::styled(message, Style::default().fg(palette.muted)))
+      .alignment(Alignment::Center);
+    frame.render_widget(paragraph, inner);
+    return;
+  };
+
+  let progress_ms = state.progress_ms().unwrap_or(0);
+  let current = lyrics.current(progress_ms);
+
+  let lines: Vec<Line> = lyrics
+    .lines
+    .iter()
+    .enumerate()
+    .map(|(i, line)| {
+      let style = match current {
+        Some(current) if i == current => Style::default()
+          .fg(palette.accent)
+          .add_modifier(Modifier::BOLD),
+        Some(current) if i < current => Style::default().fg(palette.muted),
+        _ if lyrics.is_synced => Style::default().fg(palette.subtext),
+        _ => Style::default().fg(palette.text),
+      };
+      Line::styled(line.text.as_str(), style)
+    })
+    .collect();
+
+  let scroll = lyrics.scroll(progress_ms, duration_ms, inner.height as usize);
+
+  let paragraph = Paragraph::new(Text::from(lines))
+    .alignment(Alignment::Center)
+    .scroll((scroll as u16, 0));
+  frame.render_widget(paragraph, inner);
+}
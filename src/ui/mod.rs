@@ -2,23 +2,39 @@
 
 #![allow(unused_variables)]
 
+mod account;
+mod auth;
+#[cfg(feature = "album-art")]
+pub(crate) mod cover;
+mod lyrics;
+pub(crate) mod onboarding;
 mod playlist;
+mod profile;
 pub(crate) mod style;
 
 use crate::{
-  state::{State, handler::Active},
+  images,
+  state::{State, Status, handler::Active},
   ui::{
+    account::draw_account,
+    auth::draw_auth_prompt,
+    lyrics::draw_lyrics,
     playlist::draw_playlist_sidebar,
+    profile::draw_profile_switcher,
     style::{Icon, IconKind, Palette},
   },
 };
 use ratatui::{
   Frame,
   layout::{Alignment, Constraint, Layout, Rect},
-  style::Style,
+  style::{Modifier, Style},
   text::{Line, Span, Text},
   widgets::{Block, Padding, Paragraph},
 };
+use rspotify::{
+  model::{PlayableItem, RepeatState},
+  prelude::Id,
+};
 
 pub struct Highlight {
   is_active: bool,
@@ -28,14 +44,14 @@ pub struct Highlight {
 #[inline(always)]
 fn pad(content: &str, size: usize) -> String {
   let mut out = String::with_capacity(content.len() + size * 2);
-  out.extend(std::iter::repeat(' ').take(size));
+  out.extend(std::iter::repeat_n(' ', size));
   out.push_str(content);
-  out.extend(std::iter::repeat(' ').take(size));
+  out.extend(std::iter::repeat_n(' ', size));
   out
 }
 
 pub(crate) fn draw(frame: &mut Frame, state: &State) {
-  let palette = Palette::from(&state.config.theme);
+  let palette = palette(state);
 
   frame.render_widget(ratatui::widgets::Clear, frame.area());
   frame.render_widget(
@@ -43,17 +59,18 @@ pub(crate) fn draw(frame: &mut Frame, state: &State) {
     frame.area(),
   );
 
-  let [header, middle, bottom] = Layout::vertical([
+  let [header, middle, bottom, status] = Layout::vertical([
     Constraint::Length(3),
     Constraint::Min(0),
     Constraint::Length(5),
+    Constraint::Length(state.status.is_some().into()),
   ])
   .areas(frame.area());
 
-  let [sidebar, main, player] = Layout::horizontal([
+  let [sidebar, main, lyrics] = Layout::horizontal([
     Constraint::Length(20),
     Constraint::Min(0),
-    Constraint::Length(0),
+    Constraint::Length(if state.show_lyrics { 40 } else { 0 }),
   ])
   .areas(middle);
 
@@ -63,17 +80,163 @@ pub(crate) fn draw(frame: &mut Frame, state: &State) {
   let (active, hovered) = state.currently_active();
   let highlight = Highlight::new(active == Active::Home, hovered == Active::Home);
 
-  frame.render_widget(
-    Block::bordered()
-      .border_style(highlight.get(&palette))
-      .title(pad("Main", 1)),
-    main,
-  );
+  let block = Block::bordered()
+    .border_style(highlight.get(&palette))
+    .title(pad("Main", 1));
+  match active {
+    Active::Playing => draw_album_header(frame, state, &palette, block.inner(main)),
+    _ => draw_playlist_header(frame, state, &palette, block.inner(main)),
+  }
+  frame.render_widget(block, main);
 
   draw_library(frame, state, &palette, library);
   draw_playing(frame, state, &palette, bottom);
+  draw_lyrics(frame, state, &palette, lyrics);
   draw_search(frame, state, &palette, header);
   draw_playlist_sidebar(frame, state, &palette, playlist);
+  draw_status(frame, state, &palette, status);
+  draw_account(frame, state, &palette);
+  draw_profile_switcher(frame, state, &palette);
+  draw_auth_prompt(frame, state, &palette);
+
+  #[cfg(feature = "album-art")]
+  state.covers.settle(frame.buffer_mut());
+}
+
+/// Draws the cover at `url` in a square on the left of `area`, when built with
+/// the `album-art` feature, returning the rest of the area.
+fn draw_cover(frame: &mut Frame, state: &State, url: Option<String>, area: Rect) -> Rect {
+  #[cfg(feature = "album-art")]
+  if let Some(url) = url
+    && state.covers.is_enabled()
+  {
+    // cells are about twice as high as wide
+    let [cover, _, rest] = Layout::horizontal([
+      Constraint::Length(area.height * 2),
+      Constraint::Length(1),
+      Constraint::Min(0),
+    ])
+    .areas(area);
+
+    cover::draw(frame, &state.covers, &url, cover);
+    return rest;
+  }
+
+  area
+}
+
+/// The palette of the theme, tinted to the album art playing if enabled.
+fn palette(state: &State) -> Palette {
+  let palette = Palette::from(&state.config.theme);
+
+  #[cfg(feature = "album-art")]
+  if state.config.dynamic_accent
+    && let Some(vibrant) = playing_cover(state).and_then(|url| state.covers.vibrant(&url))
+  {
+    return palette.with_accent(vibrant);
+  }
+
+  palette
+}
+
+/// The icon of `kind` in the glyph set of the config.
+fn icon(state: &State, kind: IconKind, is_active: bool) -> Icon {
+  let mut icon = Icon::new(kind, state.config.icons);
+  icon.set_active(is_active);
+  icon
+}
+
+/// URL of the cover of the playing item.
+fn playing_cover(state: &State) -> Option<String> {
+  let context = state.current_playback_context.as_ref()?;
+  match context.item.as_ref()? {
+    PlayableItem::Track(track) => images::cover(&track.album.images),
+    PlayableItem::Episode(episode) => images::cover(&episode.images),
+    _ => None,
+  }
+}
+
+/// The header of the selected playlist, with its cover.
+fn draw_playlist_header(frame: &mut Frame, state: &State, palette: &Palette, area: Rect) {
+  let playlist = state
+    .playlists
+    .as_ref()
+    .zip(state.selected_playlist_index)
+    .and_then(|(playlists, index)| playlists.items.get(index));
+  let Some(playlist) = playlist else {
+    return;
+  };
+
+  let [header, _] = Layout::vertical([Constraint::Length(6), Constraint::Min(0)]).areas(area);
+  let text = draw_cover(frame, state, images::cover(&playlist.images), header);
+
+  let owner = playlist
+    .owner
+    .display_name
+    .as_deref()
+    .unwrap_or(playlist.owner.id.id());
+  let lines = vec![
+    Line::from(Span::styled(
+      playlist.name.as_str(),
+      Style::default()
+        .fg(palette.text)
+        .add_modifier(Modifier::BOLD),
+    )),
+    Line::from(Span::styled(
+      format!(
+        "{} Playlist · {owner} · {} tracks",
+        icon(state, IconKind::Playlist, false),
+        playlist.tracks.total
+      ),
+      Style::default().fg(palette.subtext),
+    )),
+  ];
+
+  frame.render_widget(Paragraph::new(lines), text);
+}
+
+/// The header of the album playing, with its cover, while the playing panel is active.
+fn draw_album_header(frame: &mut Frame, state: &State, palette: &Palette, area: Rect) {
+  let album = match state
+    .current_playback_context
+    .as_ref()
+    .and_then(|context| context.item.as_ref())
+  {
+    Some(PlayableItem::Track(track)) => &track.album,
+    _ => return,
+  };
+
+  let [header, _] = Layout::vertical([Constraint::Length(6), Constraint::Min(0)]).areas(area);
+  let text = draw_cover(frame, state, images::cover(&album.images), header);
+
+  let artists: Vec<&str> = album.artists.iter().map(|a| a.name.as_str()).collect();
+  let mut details = vec![
+    format!("{} Album", icon(state, IconKind::Album, false)),
+    format!(
+      "{} {}",
+      icon(state, IconKind::Artist, false),
+      artists.join(", ")
+    ),
+  ];
+  // release dates start with the year
+  if let Some(year) = album.release_date.as_deref().and_then(|date| date.get(..4)) {
+    details.push(year.to_string());
+  }
+
+  let lines = vec![
+    Line::from(Span::styled(
+      album.name.as_str(),
+      Style::default()
+        .fg(palette.text)
+        .add_modifier(Modifier::BOLD),
+    )),
+    Line::from(Span::styled(
+      details.join(" · "),
+      Style::default().fg(palette.subtext),
+    )),
+  ];
+
+  frame.render_widget(Paragraph::new(lines), text);
 }
 
 fn draw_library(frame: &mut Frame, state: &State, palette: &Palette, area: Rect) {
@@ -82,20 +245,26 @@ fn draw_library(frame: &mut Frame, state: &State, palette: &Palette, area: Rect)
 
   let block = Block::bordered()
     .title(pad("Pages", 1))
-    .style(highlight.get(&palette))
+    .style(highlight.get(palette))
     .padding(Padding::left(1));
 
   let mut lines = vec![];
 
   // TODO: state and view for each line
   lines.push(Line::from(Span::styled(
-    format!("{} Home", Icon::new(IconKind::Home)),
-    highlight.get(&palette),
+    format!(
+      "{} Home",
+      icon(state, IconKind::Home, active == Active::Home)
+    ),
+    highlight.get(palette),
   )));
 
   lines.push(Line::from(vec![Span::styled(
-    format!("{} Library", Icon::new(IconKind::Library)),
-    highlight.get(&palette),
+    format!(
+      "{} Library",
+      icon(state, IconKind::Library, active == Active::Library)
+    ),
+    highlight.get(palette),
   )]));
 
   let paragraph = Paragraph::new(Text::from(lines))
@@ -110,17 +279,102 @@ fn draw_playing(frame: &mut Frame, state: &State, palette: &Palette, area: Rect)
   let highlight = Highlight::new(active == Active::Playing, hovered == Active::Playing);
 
   let block = Block::bordered()
-    .style(highlight.get(&palette))
+    .style(highlight.get(palette))
     .title(pad("Playing", 1));
-
+  let inner = block.inner(area);
   frame.render_widget(block, area);
+
+  let Some((context, item)) = state
+    .current_playback_context
+    .as_ref()
+    .and_then(|context| Some((context, context.item.as_ref()?)))
+  else {
+    return;
+  };
+
+  let (title, subtitle) = match item {
+    PlayableItem::Track(track) => {
+      let artists: Vec<&str> = track.artists.iter().map(|a| a.name.as_str()).collect();
+      (
+        track.name.as_str(),
+        format!("{} · {}", artists.join(", "), track.album.name),
+      )
+    }
+    PlayableItem::Episode(episode) => (
+      episode.name.as_str(),
+      format!(
+        "{} {}",
+        icon(state, IconKind::Podcast, false),
+        episode.show.name
+      ),
+    ),
+    _ => return,
+  };
+
+  let status = match context.is_playing {
+    true => icon(state, IconKind::Play, true),
+    false => icon(state, IconKind::Pause, false),
+  };
+  // the modes are lit up while on
+  let mode = |kind, is_on: bool| {
+    let colour = if is_on { palette.accent } else { palette.muted };
+    Span::styled(
+      icon(state, kind, is_on).to_string(),
+      Style::default().fg(colour),
+    )
+  };
+
+  let text = draw_cover(frame, state, playing_cover(state), inner);
+  let lines = vec![
+    Line::from(Span::styled(
+      format!("{status} {title}"),
+      Style::default()
+        .fg(palette.text)
+        .add_modifier(Modifier::BOLD),
+    )),
+    Line::from(Span::styled(subtitle, Style::default().fg(palette.subtext))),
+    Line::from(vec![
+      mode(IconKind::Shuffle, context.shuffle_state),
+      Span::raw(" "),
+      mode(IconKind::Repeat, context.repeat_state != RepeatState::Off),
+      Span::styled(
+        format!(
+          "  {} {}",
+          icon(state, IconKind::Device, false),
+          context.device.name
+        ),
+        Style::default().fg(palette.subtext),
+      ),
+    ]),
+  ];
+
+  frame.render_widget(Paragraph::new(lines), text);
 }
 
 fn draw_search(frame: &mut Frame, state: &State, palette: &Palette, area: Rect) {
-  let input = Block::bordered()
+  let mut input = Block::bordered()
     .title(pad("What do you wanna listen?", 2))
     .title_alignment(Alignment::Center);
+  if state.is_offline {
+    let offline = Line::styled(pad("offline", 1), Style::default().fg(palette.error));
+    input = input.title(offline.right_aligned());
+  }
   let input = Paragraph::new("").block(input);
 
   frame.render_widget(input, area);
 }
+
+fn draw_status(frame: &mut Frame, state: &State, palette: &Palette, area: Rect) {
+  let (message, colour) = match &state.status {
+    Some(Status::Error(err)) => (err.to_string(), palette.error),
+    Some(Status::Info(message)) => (message.clone(), palette.subtext),
+    None => return,
+  };
+
+  let line = Line::from(vec![
+    Span::styled(pad(&message, 1), Style::default().fg(colour)),
+    Span::styled("(esc to dismiss)", Style::default().fg(palette.muted)),
+  ]);
+
+  frame.render_widget(Paragraph::new(line), area);
+}
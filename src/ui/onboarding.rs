@@ -0,0 +1,217 @@
+use crate::{
+  auth::{self, AuthMode, credentials::DEFAULT_REDIRECT_URI},
+  onboarding::{Input, Step, Wizard},
+  ui::{auth::centered, pad, style::Palette},
+};
+use ratatui::{
+  Frame,
+  layout::{Constraint, Flex, Layout, Rect},
+  style::{Modifier, Style},
+  text::{Line, Span, Text},
+  widgets::{Block, Padding, Paragraph, Wrap},
+};
+
+pub fn draw(frame: &mut Frame, wizard: &Wizard, palette: &Palette) {
+  frame.render_widget(
+    Block::default().style(Style::default().bg(palette.background)),
+    frame.area(),
+  );
+
+  let (step, steps) = wizard.progress();
+
+  let block = Block::bordered()
+    .title(pad("Welcome to orpheus", 1))
+    .title_bottom(pad(&format!("step {step} of {steps}"), 1))
+    .border_style(Style::default().fg(palette.accent))
+    .style(Style::default().bg(palette.background).fg(palette.text))
+    .padding(Padding::uniform(1));
+
+  let qr = qr_code(wizard);
+  let muted = Style::default().fg(palette.muted);
+  let mut lines = match wizard.step {
+    Step::Welcome => welcome(wizard),
+    Step::ClientId => vec![
+      Line::from("Paste the Client ID shown in the settings of your Spotify app."),
+      Line::default(),
+      input(&wizard.client_id, palette),
+      Line::from(Span::styled(
+        format!("{}/32 characters", wizard.client_id.value.trim().len()),
+        muted,
+      )),
+    ],
+    Step::RedirectUri => vec![
+      Line::from("The Redirect URI you set in your Spotify app."),
+      Line::from(Span::styled(
+        format!("Keep {DEFAULT_REDIRECT_URI} unless you changed it."),
+        muted,
+      )),
+      Line::default(),
+      input(&wizard.redirect_uri, palette),
+    ],
+    Step::Passphrase => vec![
+      Line::from("Choose a passphrase to encrypt your credentials and token."),
+      Line::from(Span::styled(
+        "It's asked for every time orpheus starts.",
+        muted,
+      )),
+      Line::default(),
+      input(&wizard.passphrase, palette),
+    ],
+    Step::ConfirmPassphrase => vec![
+      Line::from("Type the passphrase again to confirm it."),
+      Line::default(),
+      input(&wizard.confirmation, palette),
+    ],
+    Step::Authorize => authorize(wizard, qr.as_deref(), palette),
+  };
+
+  if let Some(error) = &wizard.error {
+    lines.push(Line::default());
+    lines.push(Line::from(Span::styled(
+      error.as_str(),
+      Style::default().fg(palette.error),
+    )));
+  }
+
+  lines.push(Line::default());
+  lines.push(Line::from(Span::styled(hints(wizard.step), muted)));
+
+  // the QR code can't be scanned once it's clipped or wrapped
+  let area = match &qr {
+    Some(code) => fit(frame.area(), code, &lines),
+    None => centered(frame.area(), 80, 24),
+  };
+
+  frame.render_widget(
+    Paragraph::new(Text::from(lines))
+      .block(block)
+      .wrap(Wrap { trim: false }),
+    area,
+  );
+}
+
+fn welcome(wizard: &Wizard) -> Vec<Line<'static>> {
+  let mut lines = vec![];
+  if wizard.profile.name() != auth::profile::DEFAULT_PROFILE {
+    lines.push(Line::from(format!(
+      "Setting up the profile '{}'.",
+      wizard.profile.name()
+    )));
+    lines.push(Line::default());
+  }
+
+  lines.extend(
+    [
+      "To use orpheus, you need to create a Spotify Developer App:",
+      "",
+      "  1. Go to https://developer.spotify.com/dashboard and log in",
+      "  2. Click 'Create App' and fill in any name and description",
+      &format!("  3. Set the Redirect URI to {DEFAULT_REDIRECT_URI}"),
+      "  4. Check 'Web API' under the APIs you're planning to use",
+      "  5. Accept the terms and click 'Save'",
+      "",
+      "Then open the app's 'Settings' to find its Client ID.",
+    ]
+    .map(|line| Line::from(line.to_string())),
+  );
+
+  lines
+}
+
+/// The QR code of the authorize URL, when it's shown.
+fn qr_code(wizard: &Wizard) -> Option<String> {
+  let is_shown = wizard.step == Step::Authorize
+    && wizard.qr
+    && wizard.mode == AuthMode::Headless
+    && !wizard.is_authorizing;
+
+  is_shown
+    .then(|| auth::qr_code(wizard.authorize_url.as_ref()?))
+    .flatten()
+}
+
+/// An area in the center of `area`, large enough for the `lines` to fit
+/// around the `qr` code without wrapping it.
+fn fit(area: Rect, qr: &str, lines: &[Line]) -> Rect {
+  // the borders and the padding
+  const SPACING: u16 = 4;
+
+  let qr_width = qr.lines().map(|line| line.chars().count()).max();
+  let width = (qr_width.unwrap_or_default() as u16 + SPACING)
+    .max(area.width * 8 / 10)
+    .min(area.width);
+
+  let inner_width = width.saturating_sub(SPACING).max(1);
+  let height = lines
+    .iter()
+    .map(|line| (line.width() as u16).div_ceil(inner_width).max(1))
+    .sum::<u16>()
+    + SPACING;
+
+  let [area] = Layout::horizontal([Constraint::Length(width)])
+    .flex(Flex::Center)
+    .areas(area);
+  let [area] = Layout::vertical([Constraint::Length(height.max(24))])
+    .flex(Flex::Center)
+    .areas(area);
+
+  area
+}
+
+fn authorize(wizard: &Wizard, qr: Option<&str>, palette: &Palette) -> Vec<Line<'static>> {
+  let muted = Style::default().fg(palette.muted);
+
+  if wizard.is_authorizing {
+    return vec![Line::from("Authorizing with Spotify...")];
+  }
+
+  let Some(url) = &wizard.authorize_url else {
+    return vec![];
+  };
+
+  let (visit, paste) = match wizard.mode {
+    AuthMode::Browser => (
+      "Authorize orpheus in the browser window that was opened, or visit:",
+      "Orpheus will pick it up, otherwise paste the URL you were redirected to:",
+    ),
+    AuthMode::Headless => (
+      "Open this URL in a browser on any device and authorize orpheus:",
+      "Then paste the URL you were redirected to, or just its code:",
+    ),
+  };
+
+  let mut lines = vec![
+    Line::from(visit),
+    Line::from(Span::styled(url.clone(), muted)),
+  ];
+
+  if let Some(code) = qr {
+    lines.extend(code.lines().map(|line| Line::from(line.to_string())));
+  }
+
+  lines.extend([
+    Line::default(),
+    Line::from(paste),
+    input(&wizard.code, palette),
+  ]);
+
+  lines
+}
+
+fn input(input: &Input, palette: &Palette) -> Line<'static> {
+  Line::from(vec![
+    Span::styled("> ", Style::default().fg(palette.accent)),
+    Span::raw(input.display()),
+    Span::styled("_", Style::default().add_modifier(Modifier::SLOW_BLINK)),
+  ])
+}
+
+fn hints(step: Step) -> &'static str {
+  match step {
+    Step::Welcome => "(enter to continue, esc to quit)",
+    Step::Passphrase | Step::ConfirmPassphrase => {
+      "(enter to continue, tab to show or hide, esc to go back)"
+    }
+    _ => "(enter to continue, esc to go back)",
+  }
+}
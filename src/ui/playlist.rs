@@ -1,7 +1,7 @@
 use crate::{
   state::{State, handler::Active},
-  ui::style::Palette,
-  ui::{Highlight, pad},
+  ui::style::{IconKind, Palette},
+  ui::{Highlight, icon, pad},
 };
 use ratatui::{
   Frame,
@@ -18,7 +18,13 @@ pub fn draw_playlist_sidebar(frame: &mut Frame, state: &State, palette: &Palette
     Some(playlist) => playlist
       .items
       .iter()
-      .map(|item| demoji(&item.name))
+      .map(|item| {
+        format!(
+          "{} {}",
+          icon(state, IconKind::Playlist, false),
+          demoji(&item.name)
+        )
+      })
       .collect(),
     None => vec![],
   };
@@ -56,13 +62,13 @@ fn draw_selectable<I: AsRef<str>>(
     .collect::<Vec<_>>();
 
   let block = Block::bordered()
-    .border_style(highlight.get(&palette))
-    .title(Span::styled(pad(title, 1), highlight.get(&palette)))
+    .border_style(highlight.get(palette))
+    .title(Span::styled(pad(title, 1), highlight.get(palette)))
     .style(Style::default().fg(palette.muted));
 
   let list = List::new(items)
     .block(block)
-    .highlight_style(highlight.get(&palette).add_modifier(Modifier::BOLD));
+    .highlight_style(highlight.get(palette).add_modifier(Modifier::BOLD));
 
   frame.render_stateful_widget(list, area, &mut state);
 }
@@ -0,0 +1,52 @@
+use crate::{
+  state::State,
+  ui::{auth::centered, pad, style::Palette},
+};
+use ratatui::{
+  Frame,
+  style::Style,
+  text::{Line, Span, Text},
+  widgets::{Block, Clear, Padding, Paragraph},
+};
+
+pub fn draw_profile_switcher(frame: &mut Frame, state: &State, palette: &Palette) {
+  let Some(switcher) = &state.profile_switcher else {
+    return;
+  };
+
+  // one line per profile, plus the hint and the borders
+  let height = switcher.profiles.len() as u16 + 4;
+  let area = centered(frame.area(), 40, height);
+
+  let block = Block::bordered()
+    .title(pad("Profiles", 1))
+    .border_style(Style::default().fg(palette.accent))
+    .style(Style::default().bg(palette.background).fg(palette.text))
+    .padding(Padding::horizontal(1));
+
+  let mut lines = switcher
+    .profiles
+    .iter()
+    .enumerate()
+    .map(|(index, profile)| {
+      let marker = match profile == &state.profile {
+        true => "● ",
+        false => "  ",
+      };
+      let style = match index == switcher.selected {
+        true => Style::default().fg(palette.accent),
+        false => Style::default().fg(palette.subtext),
+      };
+      Line::from(Span::styled(format!("{marker}{}", profile.name()), style))
+    })
+    .collect::<Vec<_>>();
+
+  lines.push(Line::default());
+  lines.push(Line::from(Span::styled(
+    "(enter to switch, esc to cancel)",
+    Style::default().fg(palette.muted),
+  )));
+
+  frame.render_widget(Clear, area);
+  frame.render_widget(Paragraph::new(Text::from(lines)).block(block), area);
+}
@@ -1,8 +1,10 @@
 use std::fmt::Display;
 
 use ratatui::style::Color as Colour;
+use serde::Deserialize;
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
 /// Colour theme used to derive the UI schema.
 pub enum Theme {
   /// From: <https://catppuccin.com/palette/> mauve variant
@@ -18,12 +20,14 @@ pub struct Palette {
   pub subtext: Colour,
   pub muted: Colour,
   pub background: Colour,
+  pub error: Colour,
 }
 
 #[derive(Debug, Clone, Copy)]
 /// This represents a icon and its active state for styling.
 pub struct Icon {
   kind: IconKind,
+  mode: IconMode,
   is_active: bool,
 }
 
@@ -32,38 +36,136 @@ pub struct Icon {
 pub enum IconKind {
   Library,
   Home,
+  Play,
+  Pause,
+  Shuffle,
+  Repeat,
+  Device,
+  Album,
+  Artist,
+  Podcast,
+  Playlist,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Which glyph set icons are rendered with.
+///
+/// Nerd Font glyphs live in the private-use area, so terminals without a
+/// patched font show them as tofu. `Unicode` sticks to standard symbols and
+/// `Ascii` works everywhere.
+pub enum IconMode {
+  #[default]
+  Nerd,
+  Unicode,
+  Ascii,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// How the album art is drawn in the terminal.
+///
+/// `Auto` picks the graphics protocol of the terminal from its environment,
+/// falling back to `Halfblocks`, coloured `▀` that any truecolor terminal shows.
+pub enum ImageProtocol {
+  #[default]
+  Auto,
+  Kitty,
+  Sixel,
+  Iterm2,
+  Halfblocks,
+  None,
 }
 
-#[allow(unused)]
 impl Icon {
-  pub fn new(kind: IconKind) -> Self {
+  pub fn new(kind: IconKind, mode: IconMode) -> Self {
     Self {
       is_active: false,
       kind,
+      mode,
     }
   }
 
-  pub fn is_active(&self) -> bool {
-    self.is_active
-  }
-
   pub fn set_active(&mut self, active: bool) {
     self.is_active = active
   }
 }
 
-impl Display for Icon {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Icon {
+  fn nerd(&self) -> &'static str {
+    match (self.kind, self.is_active) {
+      (IconKind::Library, false) => "\u{f00c3}",
+      (IconKind::Library, true) => "\u{f02e}",
+
+      (IconKind::Home, false) => "\u{f46d}",
+      (IconKind::Home, true) => "\u{f4e2}",
+
+      (IconKind::Play, _) => "\u{f040a}",
+      (IconKind::Pause, _) => "\u{f03e4}",
+
+      (IconKind::Shuffle, false) => "\u{f049e}",
+      (IconKind::Shuffle, true) => "\u{f049d}",
+
+      (IconKind::Repeat, false) => "\u{f0457}",
+      (IconKind::Repeat, true) => "\u{f0456}",
+
+      (IconKind::Device, _) => "\u{f04c3}",
+      (IconKind::Album, _) => "\u{f0025}",
+      (IconKind::Artist, _) => "\u{f0803}",
+      (IconKind::Podcast, _) => "\u{f0994}",
+      (IconKind::Playlist, _) => "\u{f0cb8}",
+    }
+  }
+
+  fn unicode(&self) -> &'static str {
     match (self.kind, self.is_active) {
-      (IconKind::Library, false) => f.write_str("\u{f00c3}"),
-      (IconKind::Library, true) => f.write_str("\u{f02e}"),
+      (IconKind::Library, _) => "≡",
+      (IconKind::Home, _) => "⌂",
+
+      (IconKind::Play, _) => "▶",
+      (IconKind::Pause, _) => "‖",
 
-      (IconKind::Home, false) => f.write_str("\u{f46d}"),
-      (IconKind::Home, true) => f.write_str("\u{f4e2}"),
+      (IconKind::Shuffle, _) => "⤮",
+      (IconKind::Repeat, _) => "↻",
+
+      (IconKind::Device, _) => "◈",
+      (IconKind::Album, _) => "◉",
+      (IconKind::Artist, _) => "☺",
+      (IconKind::Podcast, _) => "◎",
+      (IconKind::Playlist, _) => "♫",
+    }
+  }
+
+  fn ascii(&self) -> &'static str {
+    match (self.kind, self.is_active) {
+      (IconKind::Library, _) => "#",
+      (IconKind::Home, _) => "~",
+
+      (IconKind::Play, _) => ">",
+      (IconKind::Pause, _) => "=",
+
+      (IconKind::Shuffle, _) => "x",
+      (IconKind::Repeat, _) => "o",
+
+      (IconKind::Device, _) => "d",
+      (IconKind::Album, _) => "@",
+      (IconKind::Artist, _) => "&",
+      (IconKind::Podcast, _) => "%",
+      (IconKind::Playlist, _) => "*",
     }
   }
 }
 
+impl Display for Icon {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(match self.mode {
+      IconMode::Nerd => self.nerd(),
+      IconMode::Unicode => self.unicode(),
+      IconMode::Ascii => self.ascii(),
+    })
+  }
+}
+
 impl From<&Theme> for Palette {
   fn from(theme: &Theme) -> Self {
     match theme {
@@ -73,13 +175,143 @@ impl From<&Theme> for Palette {
         subtext: Colour::Rgb(166, 173, 200),
         muted: Colour::Rgb(88, 91, 112),
         background: Colour::Rgb(17, 17, 27),
+        error: Colour::Rgb(243, 139, 168),
       },
     }
   }
 }
 
+#[cfg(feature = "album-art")]
+/// Lowest [contrast ratio](https://www.w3.org/TR/WCAG21/#contrast-minimum) of
+/// an accent with the background, that of normal text.
+const MIN_CONTRAST: f32 = 4.5;
+
+#[cfg(feature = "album-art")]
+impl Palette {
+  /// Uses `accent` instead of the accent of the theme, if it contrasts enough with the background.
+  pub fn with_accent(mut self, accent: Colour) -> Self {
+    if let (Some(accent_luminance), Some(background)) =
+      (luminance(accent), luminance(self.background))
+    {
+      let (lighter, darker) = match accent_luminance > background {
+        true => (accent_luminance, background),
+        false => (background, accent_luminance),
+      };
+      if (lighter + 0.05) / (darker + 0.05) >= MIN_CONTRAST {
+        self.accent = accent;
+      }
+    }
+    self
+  }
+}
+
+#[cfg(feature = "album-art")]
+/// The relative luminance of an RGB colour.
+fn luminance(colour: Colour) -> Option<f32> {
+  let Colour::Rgb(r, g, b) = colour else {
+    return None;
+  };
+
+  let linear = |channel: u8| {
+    let channel = channel as f32 / 255.0;
+    match channel <= 0.04045 {
+      true => channel / 12.92,
+      false => ((channel + 0.055) / 1.055).powf(2.4),
+    }
+  };
+  Some(0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b))
+}
+
 impl Default for Palette {
   fn default() -> Self {
     Palette::from(&Theme::Catppuccin)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const KINDS: [IconKind; 11] = [
+    IconKind::Library,
+    IconKind::Home,
+    IconKind::Play,
+    IconKind::Pause,
+    IconKind::Shuffle,
+    IconKind::Repeat,
+    IconKind::Device,
+    IconKind::Album,
+    IconKind::Artist,
+    IconKind::Podcast,
+    IconKind::Playlist,
+  ];
+
+  fn glyph(kind: IconKind, mode: IconMode, is_active: bool) -> String {
+    let mut icon = Icon::new(kind, mode);
+    icon.set_active(is_active);
+    icon.to_string()
+  }
+
+  #[test]
+  fn icons_are_drawn_with_the_glyphs_of_their_mode() {
+    assert_eq!(glyph(IconKind::Home, IconMode::Nerd, false), "\u{f46d}");
+    assert_eq!(glyph(IconKind::Home, IconMode::Nerd, true), "\u{f4e2}");
+    assert_eq!(glyph(IconKind::Home, IconMode::Unicode, false), "⌂");
+    assert_eq!(glyph(IconKind::Home, IconMode::Ascii, false), "~");
+  }
+
+  #[test]
+  fn fallback_glyphs_avoid_the_private_use_area() {
+    let private_use = |glyph: &str| glyph.chars().any(|c| c >= '\u{e000}');
+
+    for kind in KINDS {
+      for is_active in [false, true] {
+        assert!(private_use(&glyph(kind, IconMode::Nerd, is_active)));
+        assert!(!private_use(&glyph(kind, IconMode::Unicode, is_active)));
+
+        let ascii = glyph(kind, IconMode::Ascii, is_active);
+        assert!(ascii.len() == 1 && ascii.is_ascii(), "{kind:?} is {ascii}");
+      }
+    }
+  }
+
+  #[cfg(feature = "album-art")]
+  fn on(background: Colour) -> Palette {
+    Palette {
+      background,
+      ..Palette::default()
+    }
+  }
+
+  #[test]
+  #[cfg(feature = "album-art")]
+  fn luminance_spans_black_to_white() {
+    assert_eq!(luminance(Colour::Rgb(0, 0, 0)), Some(0.0));
+    assert!((luminance(Colour::Rgb(255, 255, 255)).unwrap() - 1.0).abs() < 1e-6);
+    assert_eq!(luminance(Colour::Red), None);
+  }
+
+  #[test]
+  #[cfg(feature = "album-art")]
+  fn contrasting_accents_replace_the_theme_one() {
+    let white = Colour::Rgb(255, 255, 255);
+    assert_eq!(on(Colour::Rgb(0, 0, 0)).with_accent(white).accent, white);
+  }
+
+  #[test]
+  #[cfg(feature = "album-art")]
+  fn accents_lost_in_the_background_keep_the_theme_one() {
+    let theme = Palette::default().accent;
+    let accent = on(Colour::Rgb(10, 10, 20)).with_accent(Colour::Rgb(40, 20, 40));
+    assert_eq!(accent.accent, theme);
+  }
+
+  #[test]
+  #[cfg(feature = "album-art")]
+  fn backgrounds_of_the_terminal_keep_the_theme_accent() {
+    let theme = Palette::default().accent;
+    let white = Colour::Rgb(255, 255, 255);
+    assert_eq!(on(Colour::Reset).with_accent(white).accent, theme);
+    assert_eq!(on(Colour::Black).with_accent(white).accent, theme);
+  }
+}